@@ -0,0 +1,257 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::dto::DBConfig;
+use crate::modules::conninfo::utils::ConnParams;
+use crate::modules::heartbeat::DEFAULT_HEARTBEAT_STATEMENT;
+use crate::modules::logging::LogFormat;
+use crate::modules::sink::format::{CloudEventsMode, EventFormat};
+use crate::modules::slot::monitor::{parse_bytes, parse_duration, DropPolicy, Thresholds};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "cyphercdc", version, about = "Change data capture for PostgreSQL over logical replication")]
+pub struct Cli {
+    /// Pipelines file (TOML), streams every pipeline defined in it
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    /// Serve /metrics, /healthz, /readyz and /status on this address, e.g. 0.0.0.0:9187
+    #[arg(long, global = true, alias = "metrics-address")]
+    pub http_address: Option<String>,
+
+    /// Log level or filter directives, RUST_LOG takes precedence (error, warn, info, debug, trace)
+    #[arg(long, global = true, default_value = "info")]
+    pub log_level: String,
+
+    /// Log format on stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Log every protocol message sent and received (authentication, XLogData, pgoutput, keepalives)
+    #[arg(long, global = true)]
+    pub protocol_trace: bool,
+
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+// each flag overrides the connection string and the DB_*/PG* environment variables
+#[derive(Args)]
+pub struct ConnectionArgs {
+    /// libpq connection string or postgres:// URI (DATABASE_URL)
+    #[arg(long, global = true)]
+    pub dsn: Option<String>,
+    /// Database host (DB_HOST, PGHOST)
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+    /// Database port (DB_PORT, PGPORT)
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+    /// Database name (DB_NAME, PGDATABASE)
+    #[arg(long, global = true)]
+    pub dbname: Option<String>,
+    /// Database user (DB_USER, PGUSER)
+    #[arg(long, global = true)]
+    pub user: Option<String>,
+    /// Database password (DB_PASSWORD, PGPASSWORD, ~/.pgpass)
+    #[arg(long, global = true)]
+    pub password: Option<String>,
+    /// Replication slot name (DB_SLOT)
+    #[arg(long, global = true)]
+    pub slot: Option<String>,
+    /// Publication name(s), comma separated (DB_PUBLICATION)
+    #[arg(long, global = true)]
+    pub publication: Option<String>,
+}
+
+impl ConnectionArgs {
+    pub fn dsn(&self) -> Option<String> {
+        self.dsn.clone().or_else(|| std::env::var("DATABASE_URL").ok())
+    }
+
+    pub fn params(&self) -> ConnParams {
+        let mut params = ConnParams::new();
+        let flags = [("host", self.host.clone()), ("port", self.port.map(|p| p.to_string())), ("dbname", self.dbname.clone()),
+            ("user", self.user.clone()), ("password", self.password.clone())];
+        for (key, value) in flags {
+            if let Some(value) = value {
+                params.insert(key.to_owned(), value);
+            }
+        }
+
+        params
+    }
+
+    pub fn resolve(&self) -> Result<DBConfig, String> {
+        let mut config = DBConfig::resolve(self.dsn().as_deref(), &self.params())?;
+        if let Some(slot) = &self.slot { config.replication_slot = slot.clone(); }
+        if let Some(publication) = &self.publication { config.publication_name = publication.clone(); }
+
+        Ok(config)
+    }
+}
+
+// stdout format of `snapshot` and of `stream` without a pipelines file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Jsonl,
+    Debezium,
+    #[value(name = "cloudevents")]
+    CloudEvents,
+}
+
+impl OutputFormat {
+    // format of the JSON lines written instead of text
+    pub fn event_format(self) -> Option<EventFormat> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Jsonl => Some(EventFormat::Json),
+            OutputFormat::Debezium => Some(EventFormat::Debezium { schemas: false }),
+            OutputFormat::CloudEvents => Some(EventFormat::CloudEvents { mode: CloudEventsMode::Structured }),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Stream changes from the replication slot
+    Stream {
+        /// File the flushed LSN is persisted to, streaming resumes from it after a restart
+        #[arg(long)]
+        offset_file: Option<PathBuf>,
+        /// Run the heartbeat statement this often (e.g. 30s) so the slot advances while published tables are idle
+        #[arg(long, value_parser = parse_duration)]
+        heartbeat_interval: Option<Duration>,
+        /// Statement run by the heartbeat on a normal connection
+        #[arg(long, default_value = DEFAULT_HEARTBEAT_STATEMENT)]
+        heartbeat_statement: String,
+        /// How changes are printed on stdout, the other formats write one JSON object per event
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Create the publication and the replication slot if they do not exist
+    Setup {
+        /// Tables to publish (schema.table), all tables when omitted
+        #[arg(long, value_delimiter = ',')]
+        tables: Vec<String>,
+        /// Create the slot with failover enabled so it is synchronized to standbys (PostgreSQL 17+)
+        #[arg(long)]
+        failover: bool,
+    },
+    /// Print the current content of every published table
+    Snapshot {
+        /// How rows are printed, the other formats write them as read events
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Write the Protobuf schemas of the published tables: a .proto per table and descriptors.pb, a FileDescriptorSet
+    Proto {
+        /// Directory the files are written to, the .proto files in subdirectories following their package
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+        /// Pipeline name the packages start with, the slot name like `stream` without a pipelines file uses
+        #[arg(long)]
+        name: Option<String>,
+        /// The field_numbers file of the pipeline's kafka sink, without it fields are numbered in column order
+        #[arg(long)]
+        field_numbers: Option<PathBuf>,
+    },
+    /// Manage replication slots
+    Slot {
+        #[command(subcommand)]
+        command: SlotCommand,
+    },
+    /// Manage publications
+    Publication {
+        #[command(subcommand)]
+        command: PublicationCommand,
+    },
+    /// Show the state of the replication slot
+    Status,
+    /// Watch the health of the replication slot(s) and warn before WAL retention becomes a problem
+    Monitor(MonitorArgs),
+    /// Verify that the database is ready for logical replication, with remediation SQL for problems
+    Check,
+}
+
+// slots of every pipeline with --config, the --slot/DB_SLOT one otherwise
+#[derive(Args)]
+pub struct MonitorArgs {
+    /// Seconds between polls
+    #[arg(long, default_value_t = 30)]
+    pub interval: u64,
+    /// Poll once, print the status and exit
+    #[arg(long)]
+    pub once: bool,
+    /// Warn when the slot retains more WAL than this (e.g. 10GB)
+    #[arg(long, value_parser = parse_bytes)]
+    pub max_retained: Option<i64>,
+    /// Warn when confirmed_flush_lsn is further behind than this (e.g. 1GB)
+    #[arg(long, value_parser = parse_bytes)]
+    pub max_lag: Option<i64>,
+    /// Warn when the consumer's flush lag is longer than this (e.g. 5m)
+    #[arg(long, value_parser = parse_duration)]
+    pub max_lag_time: Option<Duration>,
+    /// Warn when the slot has been inactive for longer than this (e.g. 1h)
+    #[arg(long, value_parser = parse_duration)]
+    pub max_inactive: Option<Duration>,
+    /// Drop a slot that has been inactive for this long (e.g. 7d), disabled when omitted
+    #[arg(long, value_parser = parse_duration)]
+    pub drop_inactive_after: Option<Duration>,
+    /// Only drop an abandoned slot that retains at least this much WAL
+    #[arg(long, value_parser = parse_bytes, default_value = "0")]
+    pub drop_min_retained: i64,
+}
+
+impl MonitorArgs {
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            retained_bytes: self.max_retained,
+            lag_bytes: self.max_lag,
+            lag_time: self.max_lag_time,
+            inactive: self.max_inactive,
+        }
+    }
+
+    pub fn drop_policy(&self) -> Option<DropPolicy> {
+        self.drop_inactive_after.map(|inactive_for| DropPolicy { inactive_for, min_retained_bytes: self.drop_min_retained })
+    }
+}
+
+#[derive(Subcommand)]
+pub enum SlotCommand {
+    /// List all replication slots
+    List,
+    /// Create the logical replication slot
+    Create {
+        /// Enable failover so the slot is synchronized to standbys (PostgreSQL 17+)
+        #[arg(long)]
+        failover: bool,
+    },
+    /// Drop the replication slot
+    Drop,
+}
+
+#[derive(Subcommand)]
+pub enum PublicationCommand {
+    /// List publications and their tables
+    List,
+    /// Create the publication
+    Create {
+        /// Tables to publish (schema.table), all tables when omitted
+        #[arg(long, value_delimiter = ',')]
+        tables: Vec<String>,
+    },
+    /// Drop the publication
+    Drop,
+    /// Show how the publications declared in the pipelines file differ from the database
+    Sync {
+        /// Apply the changes instead of only showing them
+        #[arg(long)]
+        apply: bool,
+    },
+}
\ No newline at end of file
@@ -1,7 +1,15 @@
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use crate::dto::DBConfig;
 
+static RESOLVED_CONFIG: OnceCell<DBConfig> = OnceCell::new();
+
+// falls back to the environment when no config was set with init_config
 pub static CONFIG: Lazy<DBConfig> = Lazy::new(|| {
     dotenv::dotenv().ok();
-    DBConfig::from_env()
+    RESOLVED_CONFIG.get().cloned().unwrap_or_else(DBConfig::from_env)
 });
+
+// must be called before CONFIG is first used, e.g. with command line overrides applied
+pub fn init_config(config: DBConfig) -> Result<(), String> {
+    RESOLVED_CONFIG.set(config).map_err(|_| String::from("configuration already initialised"))
+}
\ No newline at end of file
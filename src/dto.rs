@@ -1,24 +1,31 @@
 use dotenv::dotenv;
-use std::env;
+use crate::modules::conninfo::target::TargetSessionAttrs;
+use crate::modules::conninfo::utils::{resolve_config, ConnParams};
+use crate::modules::secret::SecretString;
 #[derive(Debug, Clone)]
 pub struct DBConfig {
+    // comma separated when the cluster is reached through several hosts
     pub db_host: String,
     pub db_port: String,
     pub db_name: String,
     pub db_user: String,
-    pub db_password: String
+    pub db_password: SecretString,
+    pub target_session_attrs: TargetSessionAttrs,
+    // try the hosts in random order instead of the listed one
+    pub load_balance_hosts: bool,
+    pub replication_slot: String,
+    pub publication_name: String
 }
 
 impl DBConfig {
     pub(crate) fn from_env() -> DBConfig {
+        DBConfig::resolve(None, &ConnParams::new()).unwrap_or_else(|e| panic!("Invalid connection settings: {}", e))
+    }
+
+    // conninfo is a libpq keyword/value string or URI, overrides win over everything else
+    pub(crate) fn resolve(conninfo: Option<&str>, overrides: &ConnParams) -> Result<DBConfig, String> {
         dotenv().ok(); // load .env into process env
 
-        DBConfig {
-            db_host: env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string()),
-            db_port: env::var("DB_PORT").unwrap_or_else(|_| "5432".to_string()),
-            db_name: env::var("DB_NAME").unwrap_or_else(|_| "mydb".to_string()),
-            db_user: env::var("DB_USER").unwrap_or_else(|_| "myuser".to_string()),
-            db_password: env::var("DB_PASSWORD").unwrap_or_else(|_| "mypassword".to_string()),
-        }
+        resolve_config(conninfo, overrides)
     }
-}
+}
\ No newline at end of file
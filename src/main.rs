@@ -2,68 +2,346 @@ extern crate core;
 
 mod modules;
 mod config;
+mod cli;
 pub mod dto;
 
-use crate::config::CONFIG;
-use crate::modules::replication::utils as replication_utils;
-use crate::modules::sasl::authentication_error::AuthenticationError;
-use modules::sasl::utils;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use crate::cli::{Cli, Command, ConnectionArgs, MonitorArgs, OutputFormat, PublicationCommand, SlotCommand};
+use crate::config::{init_config, CONFIG};
+use crate::modules::check::utils::{check_environment, CheckStatus};
+use crate::modules::db::{connect_db, system_identifier};
+use crate::modules::heartbeat::{HeartbeatConfig, DEFAULT_HEARTBEAT_STATEMENT};
+use crate::modules::logging::init_logging;
+use crate::modules::metrics::server::serve_http;
+use crate::modules::publication::spec::diff_publication;
+use crate::modules::publication::utils::{apply_publication_changes, create_publication, drop_publication, list_publications, publication_exists, read_publication};
+use crate::modules::pipeline::config_file::{load_pipelines, load_publications, SinkConfig};
+use crate::modules::pipeline::utils::{run_pipelines, Pipeline};
+use crate::modules::sink::format::EventEncoder;
+use crate::modules::sink::json_lines::JsonLinesConfig;
+use crate::modules::sink::protobuf::descriptor::{write_descriptors, DescriptorCache};
+use crate::modules::sasl::utils::sasl_authentication;
+use crate::modules::shutdown::{install_signal_handlers, ShutdownSignal};
+use crate::dto::DBConfig;
+use crate::modules::slot::monitor::SlotMonitor;
+use crate::modules::slot::utils::{create_slot, drop_slot, find_slot, list_slots};
+use crate::modules::snapshot::utils::{publication_tables, snapshot_publication, table_columns};
+use crate::modules::tcp::utils::close_tcp_connection;
+use clap::Parser;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use std::process::ExitCode;
+use tracing::{error, info, warn};
 
-fn main() {
+// replication stopped on a fatal error
+const EXIT_FATAL_ERROR: u8 = 1;
+// shutdown was requested but the final acknowledgement could not be sent
+const EXIT_UNCLEAN_SHUTDOWN: u8 = 2;
+// the pipelines file could not be loaded or stream flags conflict with it
+const EXIT_INVALID_CONFIG: u8 = 3;
+
+fn main() -> ExitCode {
     dotenv::dotenv().ok();
-    // sasl authentication
-    match start_sasl_authentication(&*CONFIG.db_host, CONFIG.db_port.parse().unwrap(), &*CONFIG.db_user
-    ) {
-        Ok(mut tcpstream) => {
-            start_replication(&mut tcpstream)
+    let cli = Cli::parse();
+    if let Err(e) = init_logging(&cli.log_level, cli.log_format, cli.protocol_trace) {
+        eprintln!("{}", e);
+        return ExitCode::from(EXIT_FATAL_ERROR);
+    }
+    if let Err(e) = cli.connection.resolve().and_then(init_config) {
+        eprintln!("{}", e);
+        return ExitCode::from(EXIT_FATAL_ERROR);
+    }
+    if let Some(address) = &cli.http_address
+        && let Err(e) = serve_http(address) {
+        error!("Could not serve HTTP on {}: {}", address, e);
+        return ExitCode::from(EXIT_FATAL_ERROR);
+    }
+
+    let result = match cli.command {
+        Command::Stream { offset_file, heartbeat_interval, heartbeat_statement, output } => {
+            if cli.config.is_some() {
+                // the pipelines file sets these per pipeline
+                let ignored = [("--offset-file", offset_file.is_some()), ("--heartbeat-interval", heartbeat_interval.is_some()),
+                    ("--heartbeat-statement", heartbeat_statement != DEFAULT_HEARTBEAT_STATEMENT), ("--output", output != OutputFormat::Text)];
+                let flags: Vec<&str> = ignored.iter().filter(|(_, set)| *set).map(|(flag, _)| *flag).collect();
+                if !flags.is_empty() {
+                    eprintln!("{} cannot be used with --config, set them on the pipelines in the file instead", flags.join(", "));
+                    return ExitCode::from(EXIT_INVALID_CONFIG);
+                }
+            }
+            let heartbeat = heartbeat_interval.map(|interval| HeartbeatConfig { interval, statement: heartbeat_statement });
+            return stream(cli.config.as_deref(), &cli.connection, offset_file, heartbeat, output);
+        },
+        Command::Setup { tables, failover } => setup(&tables, failover),
+        Command::Snapshot { output } => snapshot(output),
+        Command::Proto { out_dir, name, field_numbers } => proto(&out_dir, name.as_deref(), field_numbers),
+        Command::Slot { command } => slot(command),
+        Command::Publication { command } => publication(command, cli.config.as_deref()),
+        Command::Status => status(),
+        Command::Monitor(args) => monitor(&args, cli.config.as_deref(), &cli.connection),
+        Command::Check => check(),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_FATAL_ERROR)
         },
-        Err(error) => eprintln!("{}", error),
     }
-    // replication
 }
 
-fn start_sasl_authentication(host: &str, port: u16, user: &str) -> Result<TcpStream, AuthenticationError> {
-    utils::sasl_authentication(host, port, user)
+fn stream(config_path: Option<&str>, connection: &ConnectionArgs, offset_file: Option<PathBuf>,
+          heartbeat: Option<HeartbeatConfig>, output: OutputFormat) -> ExitCode {
+    let pipelines = match config_path {
+        Some(path) => match load_pipelines(path, connection.dsn().as_deref(), &connection.params()) {
+            Ok(pipelines) => pipelines,
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                return ExitCode::from(EXIT_INVALID_CONFIG);
+            },
+        },
+        None => {
+            let mut pipeline = Pipeline::from_config(&CONFIG);
+            pipeline.offset_file = offset_file;
+            pipeline.heartbeat = heartbeat;
+            if let Some(format) = output.event_format() {
+                pipeline.sinks = vec![SinkConfig::JsonLines(JsonLinesConfig { format, ..JsonLinesConfig::default() })];
+            }
+            vec![pipeline]
+        },
+    };
+
+    let shutdown = ShutdownSignal::new();
+    if let Err(e) = install_signal_handlers(&shutdown) {
+        error!("Could not install signal handlers: {}", e);
+        return ExitCode::from(EXIT_FATAL_ERROR);
+    }
+
+    // sasl authentication and replication per pipeline, reconnecting on retryable errors
+    let failures = run_pipelines(pipelines, &shutdown);
+    for (pipeline, error) in &failures {
+        error!(pipeline = %pipeline, %error, "Pipeline failed");
+    }
+    if failures.is_empty() {
+        ExitCode::SUCCESS
+    } else if shutdown.is_triggered() {
+        ExitCode::from(EXIT_UNCLEAN_SHUTDOWN)
+    } else {
+        ExitCode::from(EXIT_FATAL_ERROR)
+    }
 }
 
-fn start_replication(tcp_stream: &mut TcpStream) {
-    replication_utils::replication(tcp_stream);
+fn setup(tables: &[String], failover: bool) -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    if publication_exists(&mut client, &CONFIG.publication_name)? {
+        println!("Publication {} already exists", CONFIG.publication_name);
+    } else {
+        create_publication(&mut client, &CONFIG.publication_name, tables)?;
+        println!("Created publication {}", CONFIG.publication_name);
+    }
+    match find_slot(&mut client, &CONFIG.replication_slot)? {
+        Some(_) => println!("Replication slot {} already exists", CONFIG.replication_slot),
+        None => {
+            let lsn = create_slot(&mut client, &CONFIG.replication_slot, failover)?;
+            println!("Created replication slot {} at {}", CONFIG.replication_slot, lsn);
+        },
+    }
+
+    Ok(())
 }
 
-fn start_replication_step(host: String, port: i32) -> () {
-    let server_address = format!("{}:{}", host, port);
-    let mut stream = TcpStream::connect(server_address).expect("Failed to connect to server");
+fn snapshot(output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    // named like the events of `stream` without a pipelines file
+    let mut encoder = output.event_format()
+        .map(|format| EventEncoder::new(format, &CONFIG.replication_slot, &CONFIG.db_name));
+    if let Some(encoder) = &mut encoder {
+        encoder.set_system_id(&system_identifier(&mut client)?);
+    }
+    let rows = snapshot_publication(&mut client, &CONFIG.publication_name, encoder.as_ref())?;
+    info!(rows, "Snapshot complete");
+
+    Ok(())
+}
 
-    let message = "START_REPLICATION SLOT scopes_slot LOGICAL 0/0 (proto_version '1', publication_names 'scopes_pub')";
-    stream.write_all(message.as_bytes()).expect("Failed to write to server");
+fn proto(out_dir: &Path, name: Option<&str>, field_numbers: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    let name = name.unwrap_or(&CONFIG.replication_slot);
+    // the pipeline owns the file, columns it has not seen yet get the numbers it would give them
+    let mut cache = match field_numbers {
+        Some(path) => DescriptorCache::read(path)?,
+        None => DescriptorCache::default(),
+    };
+    let mut descriptors = Vec::new();
+    for table in publication_tables(&mut client, &CONFIG.publication_name)? {
+        let (schema, table_name) = table.split_once('.').unwrap_or(("public", &table));
+        descriptors.push((*cache.descriptor(name, schema, table_name, &table_columns(&mut client, &table)?)?).clone());
+    }
+    if descriptors.is_empty() {
+        return Err(format!("Publication {} has no tables", CONFIG.publication_name).into());
+    }
+    for path in write_descriptors(out_dir, &descriptors)? {
+        println!("Wrote {}", path.display());
+    }
 
-    let mut buffer = vec![0; 1024]; // Create a buffer to store received data
-    let mut bytes_read = stream.read(&mut buffer).expect("Failed to read from stream");
+    Ok(())
+}
 
-    while bytes_read > 0 {
-        let received_data = String::from_utf8_lossy(&buffer[..bytes_read]);
-        println!("Received: {}", received_data);
-        bytes_read = stream.read(&mut buffer).expect("Failed to read from stream");
+fn slot(command: SlotCommand) -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    match command {
+        SlotCommand::List => {
+            for slot in list_slots(&mut client)? {
+                println!("{}\t{}\t{}\tactive={}\tconfirmed_flush={}\tfailover={}\tsynced={}", slot.slot_name, slot.slot_type,
+                         slot.plugin.unwrap_or_default(), slot.active, slot.confirmed_flush_lsn.unwrap_or_default(),
+                         slot.failover.unwrap_or_default(), slot.synced.unwrap_or_default());
+            }
+        },
+        SlotCommand::Create { failover } => {
+            let lsn = create_slot(&mut client, &CONFIG.replication_slot, failover)?;
+            println!("Created replication slot {} at {}", CONFIG.replication_slot, lsn);
+        },
+        SlotCommand::Drop => {
+            drop_slot(&mut client, &CONFIG.replication_slot)?;
+            println!("Dropped replication slot {}", CONFIG.replication_slot);
+        },
     }
+
+    Ok(())
 }
 
-async fn process_replication(client: tokio_postgres::Client) -> () {
-    println!("Processing replication....");
-
-    let query = "START_REPLICATION SLOT scopes_slot LOGICAL 0/0 (proto_version '1', publication_names 'scopes_pub')";
-    match client.simple_query(query).await {
-        Ok(messages) => {
-            for message in messages {
-                match message {
-                    tokio_postgres::SimpleQueryMessage::Row(row) => {
-                        println!("Replication message: {:?}", row);
-                    }
-                    _ => {}
+fn publication(command: PublicationCommand, config_path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    match command {
+        PublicationCommand::List => {
+            for publication in list_publications(&mut client)? {
+                let tables = if publication.all_tables { String::from("ALL TABLES") } else { publication.tables.join(",") };
+                println!("{}\t{}", publication.name, tables);
+            }
+        },
+        PublicationCommand::Create { tables } => {
+            create_publication(&mut client, &CONFIG.publication_name, &tables)?;
+            println!("Created publication {}", CONFIG.publication_name);
+        },
+        PublicationCommand::Drop => {
+            drop_publication(&mut client, &CONFIG.publication_name)?;
+            println!("Dropped publication {}", CONFIG.publication_name);
+        },
+        PublicationCommand::Sync { apply } => {
+            let path = config_path.ok_or("publication sync needs --config with [[publication]] definitions")?;
+            let specs = load_publications(path).map_err(|errors| {
+                errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+            })?;
+            let mut pending = false;
+            for spec in &specs {
+                let current = read_publication(&mut client, &spec.name)?;
+                let changes = diff_publication(current.as_ref(), spec);
+                if changes.is_empty() {
+                    println!("Publication {} is up to date", spec.name);
+                    continue;
+                }
+                pending = true;
+                for change in &changes {
+                    println!("{}\n    {};", change, change.sql());
+                }
+                if apply {
+                    apply_publication_changes(&mut client, &changes)?;
+                    println!("Applied {} changes to publication {}", changes.len(), spec.name);
+                }
+            }
+            if pending && !apply {
+                println!("Run with --apply to make these changes");
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn status() -> Result<(), Box<dyn Error>> {
+    let mut client = connect_db()?;
+    match find_slot(&mut client, &CONFIG.replication_slot)? {
+        Some(slot) => {
+            println!("slot:                {}", slot.slot_name);
+            println!("plugin:              {}", slot.plugin.unwrap_or_default());
+            println!("database:            {}", slot.database.unwrap_or_default());
+            println!("active:              {}", slot.active);
+            println!("active pid:          {}", slot.active_pid.map(|p| p.to_string()).unwrap_or_default());
+            println!("restart lsn:         {}", slot.restart_lsn.unwrap_or_default());
+            println!("confirmed flush lsn: {}", slot.confirmed_flush_lsn.unwrap_or_default());
+            println!("retained wal bytes:  {}", slot.retained_bytes.map(|b| b.to_string()).unwrap_or_default());
+            println!("failover:            {}", slot.failover.map(|f| f.to_string()).unwrap_or_default());
+            println!("synced:              {}", slot.synced.map(|s| s.to_string()).unwrap_or_default());
+            Ok(())
+        },
+        None => Err(format!("Replication slot {} does not exist", CONFIG.replication_slot).into()),
+    }
+}
+
+fn monitor(args: &MonitorArgs, config_path: Option<&str>, connection: &ConnectionArgs) -> Result<(), Box<dyn Error>> {
+    // one monitor per source database, watching the slots of its pipelines
+    let mut sources: Vec<(DBConfig, Vec<String>)> = Vec::new();
+    match config_path {
+        Some(path) => {
+            let pipelines = load_pipelines(path, connection.dsn().as_deref(), &connection.params())
+                .map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))?;
+            for pipeline in pipelines {
+                let slot = pipeline.source.replication_slot.clone();
+                match sources.iter_mut().find(|(source, _)| source_key(source) == source_key(&pipeline.source)) {
+                    Some((_, slots)) => slots.push(slot),
+                    None => sources.push((pipeline.source, vec![slot])),
                 }
             }
+        },
+        None => sources.push((CONFIG.clone(), vec![CONFIG.replication_slot.clone()])),
+    }
+
+    let shutdown = ShutdownSignal::new();
+    if !args.once {
+        install_signal_handlers(&shutdown)?;
+    }
+    let handles: Vec<_> = sources.into_iter().map(|(source, slots)| {
+        let mut monitor = SlotMonitor::new(source, slots)
+            .with_interval(Duration::from_secs(args.interval.max(1)))
+            .with_thresholds(args.thresholds())
+            .with_shutdown(shutdown.clone())
+            .on_event(|event| if event.is_warning() { warn!("{}", event) } else { info!("{}", event) });
+        if let Some(policy) = args.drop_policy() {
+            monitor = monitor.with_drop_policy(policy);
         }
-        Err(e) => eprintln!("Failed to start replication: {}", e),
+        let once = args.once;
+        thread::spawn(move || if once { monitor.poll_once().map_err(|e| e.to_string()) } else { monitor.run(); Ok(()) })
+    }).collect();
+
+    for handle in handles {
+        handle.join().map_err(|_| "monitor thread panicked")??;
+    }
+
+    Ok(())
+}
+
+// same server, database and user
+fn source_key(config: &DBConfig) -> (String, String, String, String) {
+    (config.db_host.clone(), config.db_port.clone(), config.db_name.clone(), config.db_user.clone())
+}
+
+// pre-flight report for the configured database, fails when any check failed
+fn check() -> Result<(), Box<dyn Error>> {
+    let mut report = check_environment()?;
+    match sasl_authentication(&CONFIG) {
+        Ok(stream) => {
+            close_tcp_connection(&stream)?;
+            report.push("replication connection", CheckStatus::Pass, format!("authenticated as {}", CONFIG.db_user), None);
+        },
+        Err(e) => report.push("replication connection", CheckStatus::Fail, e.to_string(), None),
+    }
+    println!("{}", report);
+
+    match report.status() {
+        CheckStatus::Fail => Err("pre-flight check failed".into()),
+        _ => Ok(()),
     }
 }
@@ -0,0 +1 @@
+pub mod utils;
\ No newline at end of file
@@ -0,0 +1,251 @@
+use crate::config::CONFIG;
+use crate::dto::DBConfig;
+use crate::modules::db::{connect_db, quote_identifier, server_version_num};
+use crate::modules::slot::utils::find_slot;
+use postgres::Client;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    // SQL that fixes a warning or failure
+    pub remediation: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub results: Vec<CheckResult>,
+}
+
+impl CheckReport {
+    pub fn status(&self) -> CheckStatus {
+        self.results.iter().map(|r| r.status).max().unwrap_or(CheckStatus::Pass)
+    }
+
+    pub fn push(&mut self, name: &str, status: CheckStatus, detail: String, remediation: Option<String>) {
+        self.results.push(CheckResult { name: name.to_owned(), status, detail, remediation });
+    }
+
+    fn pass(&mut self, name: &str, detail: String) {
+        self.push(name, CheckStatus::Pass, detail, None);
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "[{}] {}: {}", result.status, result.name, result.detail)?;
+            if let Some(remediation) = &result.remediation {
+                for line in remediation.lines() {
+                    writeln!(f, "       {}", line)?;
+                }
+            }
+        }
+        let count = |status| self.results.iter().filter(|r| r.status == status).count();
+        write!(f, "{} passed, {} warnings, {} failed", count(CheckStatus::Pass), count(CheckStatus::Warn), count(CheckStatus::Fail))
+    }
+}
+
+// checks the database CONFIG points to
+pub fn check_environment() -> Result<CheckReport, Box<dyn Error>> {
+    let mut client = connect_db()?;
+
+    check_database(&mut client, &CONFIG)
+}
+
+// Verifies that the server, the role, the publications and the published tables are ready for logical replication
+pub fn check_database(client: &mut Client, config: &DBConfig) -> Result<CheckReport, Box<dyn Error>> {
+    let mut report = CheckReport::default();
+    check_wal_level(client, &mut report)?;
+    check_wal_senders(client, &mut report)?;
+    check_replication_slots(client, config, &mut report)?;
+    check_slot_wal_keep_size(client, &mut report)?;
+    check_role(client, config, &mut report)?;
+    let publications: Vec<&str> = config.publication_name.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+    check_publications(client, &publications, &mut report)?;
+    check_replica_identity(client, &publications, &mut report)?;
+
+    Ok(report)
+}
+
+fn setting(client: &mut Client, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let row = client.query_one("SELECT current_setting($1, true)", &[&name])?;
+
+    Ok(row.get(0))
+}
+
+fn int_setting(client: &mut Client, name: &str) -> Result<i64, Box<dyn Error>> {
+    let value = setting(client, name)?.ok_or_else(|| format!("setting {} is not available", name))?;
+
+    Ok(value.parse()?)
+}
+
+fn check_wal_level(client: &mut Client, report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    let wal_level = setting(client, "wal_level")?.unwrap_or_default();
+    if wal_level == "logical" {
+        report.pass("wal_level", String::from("logical"));
+    } else {
+        report.push("wal_level", CheckStatus::Fail, format!("is '{}', logical decoding needs 'logical'", wal_level),
+                    Some(String::from("ALTER SYSTEM SET wal_level = logical; -- then restart the server")));
+    }
+
+    Ok(())
+}
+
+fn check_wal_senders(client: &mut Client, report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    let max = int_setting(client, "max_wal_senders")?;
+    let used: i64 = client.query_one("SELECT count(*) FROM pg_stat_replication", &[])?.get(0);
+    let remediation = Some(format!("ALTER SYSTEM SET max_wal_senders = {}; -- then restart the server", (used + 5).max(10)));
+    if max == 0 {
+        report.push("max_wal_senders", CheckStatus::Fail, String::from("is 0, no replication connection is possible"), remediation);
+    } else if used >= max {
+        report.push("max_wal_senders", CheckStatus::Fail, format!("all {} WAL senders are in use", max), remediation);
+    } else if used + 1 >= max {
+        report.push("max_wal_senders", CheckStatus::Warn, format!("{} of {} WAL senders in use, no room for a reconnect", used, max), remediation);
+    } else {
+        report.pass("max_wal_senders", format!("{} of {} in use", used, max));
+    }
+
+    Ok(())
+}
+
+fn check_replication_slots(client: &mut Client, config: &DBConfig, report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    let max = int_setting(client, "max_replication_slots")?;
+    let used: i64 = client.query_one("SELECT count(*) FROM pg_replication_slots", &[])?.get(0);
+    let slot = find_slot(client, &config.replication_slot)?;
+    let remediation = Some(format!("ALTER SYSTEM SET max_replication_slots = {}; -- then restart the server", (used + 5).max(10)));
+    if max == 0 {
+        report.push("max_replication_slots", CheckStatus::Fail, String::from("is 0, no replication slot can be created"), remediation);
+    } else if slot.is_none() && used >= max {
+        report.push("max_replication_slots", CheckStatus::Fail,
+                    format!("all {} slots are in use, slot {} cannot be created", max, config.replication_slot), remediation);
+    } else {
+        report.pass("max_replication_slots", format!("{} of {} in use", used, max));
+    }
+
+    match slot {
+        Some(slot) if slot.plugin.as_deref() != Some("pgoutput") => {
+            report.push("replication slot", CheckStatus::Fail,
+                        format!("{} uses plugin '{}', pgoutput is required", slot.slot_name, slot.plugin.unwrap_or_default()),
+                        Some(format!("SELECT pg_drop_replication_slot('{0}');\nSELECT pg_create_logical_replication_slot('{0}', 'pgoutput');",
+                                     slot.slot_name.replace('\'', "''"))));
+        },
+        Some(slot) => report.pass("replication slot", format!("{} exists", slot.slot_name)),
+        None => report.push("replication slot", CheckStatus::Warn, format!("{} does not exist yet", config.replication_slot),
+                            Some(format!("SELECT pg_create_logical_replication_slot('{}', 'pgoutput');",
+                                         config.replication_slot.replace('\'', "''")))),
+    }
+
+    Ok(())
+}
+
+// without a limit an abandoned slot retains WAL until the disk is full
+fn check_slot_wal_keep_size(client: &mut Client, report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    if server_version_num(client)? < 130000 {
+        report.push("max_slot_wal_keep_size", CheckStatus::Warn,
+                    String::from("not available before PostgreSQL 13, slots can retain WAL without limit"), None);
+        return Ok(());
+    }
+    let value = setting(client, "max_slot_wal_keep_size")?.unwrap_or_default();
+    if value == "-1" {
+        report.push("max_slot_wal_keep_size", CheckStatus::Warn,
+                    String::from("is unlimited, a stalled slot can fill the WAL disk"),
+                    Some(String::from("ALTER SYSTEM SET max_slot_wal_keep_size = '10GB'; SELECT pg_reload_conf();")));
+    } else {
+        report.pass("max_slot_wal_keep_size", format!("{}, the slot is invalidated when it falls further behind", value));
+    }
+
+    Ok(())
+}
+
+fn check_role(client: &mut Client, config: &DBConfig, report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    let row = client.query_one("SELECT rolreplication, rolsuper FROM pg_roles WHERE rolname = current_user", &[])?;
+    let (replication, superuser): (bool, bool) = (row.get(0), row.get(1));
+    if replication || superuser {
+        report.pass("replication role", format!("{} has {}", config.db_user, if superuser { "SUPERUSER" } else { "REPLICATION" }));
+    } else {
+        report.push("replication role", CheckStatus::Fail, format!("{} has no REPLICATION attribute", config.db_user),
+                    Some(format!("ALTER ROLE {} WITH REPLICATION;", quote_identifier(&config.db_user))));
+    }
+
+    Ok(())
+}
+
+fn check_publications(client: &mut Client, publications: &[&str], report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    if publications.is_empty() {
+        report.push("publication", CheckStatus::Fail, String::from("no publication configured"), None);
+    }
+    for publication in publications {
+        let row = client.query_opt("SELECT puballtables, (SELECT count(*) FROM pg_publication_tables t WHERE t.pubname = p.pubname) \
+            FROM pg_publication p WHERE pubname = $1", &[publication])?;
+        match row {
+            Some(row) => {
+                let (all_tables, tables): (bool, i64) = (row.get(0), row.get(1));
+                if tables == 0 {
+                    report.push("publication", CheckStatus::Warn, format!("{} publishes no tables", publication),
+                                Some(format!("ALTER PUBLICATION {} ADD TABLE <schema>.<table>;", quote_identifier(publication))));
+                } else {
+                    report.pass("publication", format!("{} publishes {} tables{}", publication, tables,
+                                                      if all_tables { " (ALL TABLES)" } else { "" }));
+                }
+            },
+            None => report.push("publication", CheckStatus::Fail, format!("{} does not exist", publication),
+                                Some(format!("CREATE PUBLICATION {} FOR ALL TABLES;", quote_identifier(publication)))),
+        }
+    }
+
+    Ok(())
+}
+
+// UPDATE and DELETE on a published table without a usable replica identity fail on the source,
+// and the old row values are needed to identify changed rows downstream
+fn check_replica_identity(client: &mut Client, publications: &[&str], report: &mut CheckReport) -> Result<(), Box<dyn Error>> {
+    let publications: Vec<String> = publications.iter().map(|p| p.to_string()).collect();
+    let rows = client.query("SELECT DISTINCT n.nspname::text, c.relname::text, c.relreplident::text, \
+            exists(SELECT 1 FROM pg_index i WHERE i.indrelid = c.oid AND i.indisprimary) \
+        FROM pg_publication_tables t \
+        JOIN pg_namespace n ON n.nspname = t.schemaname \
+        JOIN pg_class c ON c.relnamespace = n.oid AND c.relname = t.tablename \
+        WHERE t.pubname = ANY($1) ORDER BY 1, 2", &[&publications])?;
+
+    let mut checked = 0;
+    for row in &rows {
+        let (schema, table, identity, has_primary_key): (String, String, String, bool) = (row.get(0), row.get(1), row.get(2), row.get(3));
+        let name = format!("{}.{}", quote_identifier(&schema), quote_identifier(&table));
+        let fix = Some(format!("ALTER TABLE {} ADD PRIMARY KEY (...); -- or: ALTER TABLE {} REPLICA IDENTITY FULL;", name, name));
+        match identity.as_str() {
+            "d" if !has_primary_key => report.push("replica identity", CheckStatus::Fail,
+                                                   format!("{}.{} has no primary key and REPLICA IDENTITY DEFAULT", schema, table), fix),
+            "n" => report.push("replica identity", CheckStatus::Fail,
+                               format!("{}.{} has REPLICA IDENTITY NOTHING", schema, table), fix),
+            "f" => report.push("replica identity", CheckStatus::Warn,
+                               format!("{}.{} has REPLICA IDENTITY FULL, every change logs the whole old row", schema, table), None),
+            _ => checked += 1,
+        }
+    }
+    if checked > 0 {
+        report.pass("replica identity", format!("{} published tables have a usable key", checked));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,4 @@
+pub mod pgpass;
+pub mod service_file;
+pub mod target;
+pub mod utils;
\ No newline at end of file
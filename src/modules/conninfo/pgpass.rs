@@ -0,0 +1,124 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// ~/.pgpass unless PGPASSFILE or the passfile parameter points elsewhere
+pub fn default_passfile() -> Option<PathBuf> {
+    env::var("HOME").ok().map(|home| PathBuf::from(home).join(".pgpass"))
+}
+
+// First matching "hostname:port:database:username:password" line, '*' matches anything
+pub fn lookup_password(passfile: &Path, host: &str, port: &str, database: &str, user: &str) -> Option<String> {
+    if !has_safe_permissions(passfile) {
+        warn!("Password file {} has group or world access, permissions should be u=rw (0600) or less", passfile.display());
+        return None;
+    }
+    let content = fs::read_to_string(passfile).ok()?;
+    for line in content.lines() {
+        if line.trim_start().starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_fields(line);
+        if fields.len() != 5 {
+            continue;
+        }
+        let matches = |pattern: &str, value: &str| pattern == "*" || pattern == value;
+        if matches(&fields[0], host) && matches(&fields[1], port) && matches(&fields[2], database) && matches(&fields[3], user) {
+            return Some(fields[4].clone());
+        }
+    }
+
+    None
+}
+
+// ':' and '\' are escaped with a backslash
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => if let Some(escaped) = chars.next() { fields.last_mut().unwrap().push(escaped) },
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    fields
+}
+
+#[cfg(unix)]
+fn has_safe_permissions(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.permissions().mode() & 0o077 == 0,
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn has_safe_permissions(_path: &Path) -> bool {
+    true
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a password file readable by its owner only, removed when dropped
+    struct Passfile(PathBuf);
+
+    impl Passfile {
+        fn new(name: &str, content: &str) -> Passfile {
+            let path = env::temp_dir().join(format!("cyphercdc-pgpass-{}-{}", std::process::id(), name));
+            fs::write(&path, content).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+            }
+            Passfile(path)
+        }
+    }
+
+    impl Drop for Passfile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn returns_the_first_matching_line() {
+        let passfile = Passfile::new("lookup", "# comment\n\
+            \n\
+            db:5432:app:cdc:first\n\
+            db:5432:app:cdc:second\n\
+            *:*:*:admin:wildcard\n\
+            db:*:other:cdc:any-port\n\
+            db\\:1:5432:app:cdc:escaped\\:colon\\\\slash\n\
+            short:line\n\
+            db:5432:app:colon:has:colons\n");
+        let lookup = |host, port, database, user| lookup_password(&passfile.0, host, port, database, user);
+        assert_eq!(lookup("db", "5432", "app", "cdc").as_deref(), Some("first"));
+        assert_eq!(lookup("elsewhere", "6432", "any", "admin").as_deref(), Some("wildcard"));
+        assert_eq!(lookup("db", "6432", "other", "cdc").as_deref(), Some("any-port"));
+        assert_eq!(lookup("db:1", "5432", "app", "cdc").as_deref(), Some("escaped:colon\\slash"));
+        // the password is everything after the fourth separator
+        assert_eq!(lookup("db", "5432", "app", "colon").as_deref(), Some("has:colons"));
+        assert_eq!(lookup("db", "5433", "app", "cdc"), None);
+        assert_eq!(lookup("short", "line", "", ""), None);
+    }
+
+    #[test]
+    fn ignores_missing_and_unprotected_files() {
+        assert_eq!(lookup_password(Path::new("/nonexistent/.pgpass"), "db", "5432", "app", "cdc"), None);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let passfile = Passfile::new("open", "*:*:*:*:secret\n");
+            assert_eq!(lookup_password(&passfile.0, "db", "5432", "app", "cdc").as_deref(), Some("secret"));
+            fs::set_permissions(&passfile.0, fs::Permissions::from_mode(0o644)).unwrap();
+            assert_eq!(lookup_password(&passfile.0, "db", "5432", "app", "cdc"), None);
+        }
+    }
+}
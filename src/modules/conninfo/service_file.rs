@@ -0,0 +1,54 @@
+use crate::modules::conninfo::utils::ConnParams;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Looks the service up in PGSERVICEFILE (or ~/.pg_service.conf), then in PGSYSCONFDIR/pg_service.conf
+pub fn lookup_service(service: &str) -> Result<ConnParams, String> {
+    let mut candidates = Vec::new();
+    match env::var("PGSERVICEFILE") {
+        Ok(path) => candidates.push(PathBuf::from(path)),
+        Err(_) => if let Ok(home) = env::var("HOME") {
+            candidates.push(PathBuf::from(home).join(".pg_service.conf"));
+        },
+    }
+    if let Ok(sysconfdir) = env::var("PGSYSCONFDIR") {
+        candidates.push(PathBuf::from(sysconfdir).join("pg_service.conf"));
+    }
+
+    for path in candidates {
+        if let Ok(content) = fs::read_to_string(&path)
+            && let Some(params) = parse_service(&content, service, &path)? {
+            return Ok(params);
+        }
+    }
+
+    Err(format!("definition of service \"{}\" not found", service))
+}
+
+// INI style file: [service] sections of keyword=value lines
+fn parse_service(content: &str, service: &str, path: &Path) -> Result<Option<ConnParams>, String> {
+    let mut params: Option<ConnParams> = None;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if params.is_some() {
+                break;
+            }
+            if section == service {
+                params = Some(ConnParams::new());
+            }
+            continue;
+        }
+        if let Some(params) = params.as_mut() {
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("syntax error in service file {}, line {}", path.display(), number + 1))?;
+            params.insert(key.trim().to_owned(), value.trim().to_owned());
+        }
+    }
+
+    Ok(params)
+}
\ No newline at end of file
@@ -0,0 +1,100 @@
+use crate::dto::DBConfig;
+use rand::seq::SliceRandom;
+use std::fmt;
+
+// libpq target_session_attrs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TargetSessionAttrs {
+    #[default]
+    Any,
+    ReadWrite,
+    ReadOnly,
+    Primary,
+    Standby,
+    PreferStandby,
+}
+
+impl TargetSessionAttrs {
+    pub fn parse(value: &str) -> Result<TargetSessionAttrs, String> {
+        match value {
+            "any" => Ok(TargetSessionAttrs::Any),
+            "read-write" => Ok(TargetSessionAttrs::ReadWrite),
+            "read-only" => Ok(TargetSessionAttrs::ReadOnly),
+            "primary" => Ok(TargetSessionAttrs::Primary),
+            "standby" => Ok(TargetSessionAttrs::Standby),
+            "prefer-standby" => Ok(TargetSessionAttrs::PreferStandby),
+            other => Err(format!("invalid target_session_attrs \"{}\"", other)),
+        }
+    }
+
+    // prefer-standby looks for a standby first and settles for any server on a second pass
+    pub fn passes(self) -> Vec<TargetSessionAttrs> {
+        match self {
+            TargetSessionAttrs::PreferStandby => vec![TargetSessionAttrs::Standby, TargetSessionAttrs::Any],
+            other => vec![other],
+        }
+    }
+
+    // whether a single pass accepts the server
+    pub fn accepts(self, state: &SessionState) -> bool {
+        match self {
+            TargetSessionAttrs::Any | TargetSessionAttrs::PreferStandby => true,
+            TargetSessionAttrs::ReadWrite => !state.read_only,
+            TargetSessionAttrs::ReadOnly => state.read_only,
+            TargetSessionAttrs::Primary => !state.in_recovery,
+            TargetSessionAttrs::Standby => state.in_recovery,
+        }
+    }
+}
+
+impl fmt::Display for TargetSessionAttrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetSessionAttrs::Any => write!(f, "any"),
+            TargetSessionAttrs::ReadWrite => write!(f, "read-write"),
+            TargetSessionAttrs::ReadOnly => write!(f, "read-only"),
+            TargetSessionAttrs::Primary => write!(f, "primary"),
+            TargetSessionAttrs::Standby => write!(f, "standby"),
+            TargetSessionAttrs::PreferStandby => write!(f, "prefer-standby"),
+        }
+    }
+}
+
+// what a server reported about itself after connecting
+#[derive(Debug, Clone, Copy)]
+pub struct SessionState {
+    pub in_recovery: bool,
+    pub read_only: bool,
+}
+
+// query that yields the session state as two "t"/"f" columns
+pub const SESSION_STATE_QUERY: &str = "SELECT pg_catalog.pg_is_in_recovery(), pg_catalog.current_setting('transaction_read_only') = 'on'";
+
+impl SessionState {
+    pub fn from_text(in_recovery: &str, read_only: &str) -> SessionState {
+        SessionState { in_recovery: in_recovery == "t", read_only: read_only == "t" }
+    }
+}
+
+// Host/port pairs to try, in order unless load_balance_hosts=random.
+// A single port applies to every host, otherwise the lists must have the same length
+pub fn candidate_hosts(config: &DBConfig) -> Result<Vec<(String, u16)>, String> {
+    let hosts: Vec<&str> = config.db_host.split(',').map(str::trim)
+        .map(|h| if h.is_empty() { "localhost" } else { h })
+        .collect();
+    let ports = config.db_port.split(',').map(str::trim)
+        .map(|p| if p.is_empty() { Ok(5432) } else { p.parse::<u16>().map_err(|_| format!("invalid port \"{}\"", p)) })
+        .collect::<Result<Vec<u16>, String>>()?;
+    if ports.len() != 1 && ports.len() != hosts.len() {
+        return Err(format!("could not match {} port numbers to {} hosts", ports.len(), hosts.len()));
+    }
+
+    let mut candidates: Vec<(String, u16)> = hosts.iter().enumerate()
+        .map(|(i, host)| (host.to_string(), if ports.len() == 1 { ports[0] } else { ports[i] }))
+        .collect();
+    if config.load_balance_hosts {
+        candidates.shuffle(&mut rand::rng());
+    }
+
+    Ok(candidates)
+}
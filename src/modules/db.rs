@@ -1,29 +1,74 @@
-use std::env;
 use postgres::{Client};
 use postgres::NoTls;
-use tokio_postgres::NoTls as TokioNoTls;
 use crate::config::CONFIG;
+use crate::dto::DBConfig;
+use crate::modules::conninfo::target::{candidate_hosts, SessionState, TargetSessionAttrs, SESSION_STATE_QUERY};
+use crate::modules::conninfo::utils::to_conninfo;
+use tracing::{debug, warn};
 
 pub fn connect_db () -> Result<Client, Box<dyn std::error::Error>> {
-    println!("Connecting to database...");
-    let conn_string = std::format!("host={} user={} password={} dbname={}", CONFIG.db_host,
-                                   CONFIG.db_user, CONFIG.db_password, CONFIG.db_name);
-    let conn = Client::connect(&conn_string, NoTls)?;
+    connect_to(&CONFIG)
+}
+
+// first host that accepts the connection and matches target_session_attrs
+pub fn connect_to(config: &DBConfig) -> Result<Client, Box<dyn std::error::Error>> {
+    debug!(hosts = %config.db_host, database = %config.db_name, "Connecting to database");
+    let candidates = candidate_hosts(config)?;
+    let mut last_error: Box<dyn std::error::Error> = "no host to connect to".into();
+    for attrs in config.target_session_attrs.passes() {
+        for (host, port) in &candidates {
+            let mut conn = match Client::connect(to_conninfo(config, host, *port).expose(), NoTls) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!(host = %host, port, error = %e, "Could not connect");
+                    last_error = e.into();
+                    continue;
+                },
+            };
+            if attrs == TargetSessionAttrs::Any {
+                return Ok(conn);
+            }
+            let row = match conn.query_one(SESSION_STATE_QUERY, &[]) {
+                Ok(row) => row,
+                Err(e) => {
+                    warn!(host = %host, port, error = %e, "Could not read the session state");
+                    last_error = e.into();
+                    continue;
+                },
+            };
+            let state = SessionState { in_recovery: row.get(0), read_only: row.get(1) };
+            if attrs.accepts(&state) {
+                return Ok(conn);
+            }
+            last_error = format!("no server matching target_session_attrs={}", config.target_session_attrs).into();
+        }
+    }
 
-    return Ok(conn);
+    Err(last_error)
 }
 
-pub async fn logical_replication_connection() -> Result<tokio_postgres::Client, Box<dyn std::error::Error>> {
-    println!("Consuming replication...");
-    let conn_str = format!("host={} user={} password={} dbname={} replication=database",
-    CONFIG.db_host, CONFIG.db_user, CONFIG.db_password, CONFIG.db_name);
-    let (client, connection) = tokio_postgres::connect(&*conn_str, TokioNoTls).await?;
+// e.g. 170002 for 17.2
+pub fn server_version_num(client: &mut Client) -> Result<i32, Box<dyn std::error::Error>> {
+    let row = client.query_one("SELECT current_setting('server_version_num')::int", &[])?;
 
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("connection error: {}", e);
-        }
-    });
+    Ok(row.get(0))
+}
+
+// the same as IDENTIFY_SYSTEM reports on a replication connection
+pub fn system_identifier(client: &mut Client) -> Result<String, Box<dyn std::error::Error>> {
+    let row = client.query_one("SELECT system_identifier::text FROM pg_control_system()", &[])?;
+
+    Ok(row.get(0))
+}
+
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
 
-    Ok(client)
+// "schema.table" or "table" (public schema) to a quoted, schema qualified name
+pub fn quote_table_name(table: &str) -> String {
+    match table.split_once('.') {
+        Some((schema, name)) => format!("{}.{}", quote_identifier(schema), quote_identifier(name)),
+        None => format!("{}.{}", quote_identifier("public"), quote_identifier(table)),
+    }
 }
\ No newline at end of file
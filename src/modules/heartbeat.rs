@@ -0,0 +1,66 @@
+use crate::dto::DBConfig;
+use crate::modules::db::connect_to;
+use crate::modules::shutdown::ShutdownSignal;
+use postgres::Client;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{info_span, warn};
+
+// writes WAL without touching a table, the slot advances once the keepalive after it is acknowledged
+pub const DEFAULT_HEARTBEAT_STATEMENT: &str = "SELECT pg_logical_emit_message(false, 'cyphercdc_heartbeat', now()::text)";
+
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub statement: String,
+}
+
+// Runs the heartbeat statement on a normal connection so a slot whose published tables are idle
+// still sees WAL move past it, otherwise confirmed_flush stays put and the server retains WAL
+pub struct Heartbeat {
+    name: String,
+    source: DBConfig,
+    config: HeartbeatConfig,
+    shutdown: ShutdownSignal,
+}
+
+impl Heartbeat {
+    pub fn new(name: &str, source: DBConfig, config: HeartbeatConfig) -> Heartbeat {
+        Heartbeat { name: name.to_owned(), source, config, shutdown: ShutdownSignal::new() }
+    }
+
+    pub fn with_shutdown(mut self, shutdown: ShutdownSignal) -> Heartbeat {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        thread::spawn(move || self.run())
+    }
+
+    // failures are reported and retried on the next beat, a heartbeat never stops replication
+    fn run(&self) {
+        let _span = info_span!("heartbeat", pipeline = %self.name, slot = %self.source.replication_slot).entered();
+        let mut client: Option<Client> = None;
+        while !self.shutdown.is_triggered() {
+            if let Err(e) = self.beat(&mut client) {
+                warn!(error = %e, "Heartbeat failed");
+                client = None;
+            }
+            let deadline = Instant::now() + self.config.interval;
+            while !self.shutdown.is_triggered() && Instant::now() < deadline {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(200)));
+            }
+        }
+    }
+
+    fn beat(&self, client: &mut Option<Client>) -> Result<(), Box<dyn std::error::Error>> {
+        if client.is_none() {
+            *client = Some(connect_to(&self.source)?);
+        }
+        client.as_mut().unwrap().batch_execute(&self.config.statement)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+// target of the events that dump protocol messages, off unless --protocol-trace is given
+pub const PROTOCOL_TARGET: &str = "protocol";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// Logs go to stderr, stdout stays reserved for command output and the stdout sink.
+// RUST_LOG takes precedence over the level, e.g. RUST_LOG=info,cyphercdc::modules::sasl=debug
+pub fn init_logging(level: &str, format: LogFormat, protocol_trace: bool) -> Result<(), String> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(level).map_err(|e| format!("invalid log level \"{}\": {}", level, e))?,
+    };
+    let directive = if protocol_trace { "protocol=trace" } else { "protocol=off" };
+    let filter = filter.add_directive(directive.parse().map_err(|e| format!("{}", e))?);
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+
+    result.map_err(|e| e.to_string())
+}
@@ -0,0 +1,219 @@
+use crate::modules::metrics::status::{PipelineState, STATUS};
+use crate::modules::replication::dto::Transaction;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::supervisor::ConnectionEvent;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub static METRICS: Lazy<Registry> = Lazy::new(Registry::default);
+
+// upper bounds in seconds, from a fast stdout write to a slow remote sink
+const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+// every metric the connector exports, in the order they are rendered
+const FAMILIES: [(&str, Kind, &str); 15] = [
+    ("cyphercdc_events_total", Kind::Counter, "Change events decoded, per table and operation"),
+    ("cyphercdc_received_bytes_total", Kind::Counter, "Bytes of replication messages received"),
+    ("cyphercdc_transactions_total", Kind::Counter, "Source transactions processed"),
+    ("cyphercdc_received_lsn", Kind::Gauge, "Last WAL position received"),
+    ("cyphercdc_flushed_lsn", Kind::Gauge, "WAL position up to which everything was delivered and may be acknowledged"),
+    ("cyphercdc_applied_lsn", Kind::Gauge, "End of the last transaction handed to the sinks"),
+    ("cyphercdc_lag_bytes", Kind::Gauge, "WAL between the server's last reported position and the flushed position"),
+    ("cyphercdc_lag_seconds", Kind::Gauge, "Server time of the last message minus the commit time of the last applied transaction"),
+    ("cyphercdc_reconnects_total", Kind::Counter, "Reconnects after a retryable error"),
+    ("cyphercdc_auth_failures_total", Kind::Counter, "Failed authentications of the replication connection"),
+    ("cyphercdc_sink_latency_seconds", Kind::Histogram, "Time a sink took to accept one transaction"),
+    ("cyphercdc_snapshot_tables", Kind::Gauge, "Tables in the running snapshot"),
+    ("cyphercdc_snapshot_tables_done", Kind::Gauge, "Tables of the running snapshot that were read completely"),
+    ("cyphercdc_snapshot_rows_total", Kind::Counter, "Rows read by snapshots, per table"),
+    ("cyphercdc_up", Kind::Gauge, "1 while the process is running"),
+];
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    Histogram { buckets: [u64; LATENCY_BUCKETS.len()], sum: f64, count: u64 },
+}
+
+// Counters, gauges and histograms by name and labels, rendered in the Prometheus text format
+#[derive(Default)]
+pub struct Registry {
+    series: Mutex<BTreeMap<&'static str, BTreeMap<Labels, Value>>>,
+}
+
+impl Registry {
+    pub fn inc_counter(&self, name: &'static str, labels: &[(&str, &str)], by: u64) {
+        let mut series = self.series.lock().unwrap();
+        let value = series.entry(name).or_default().entry(to_labels(labels)).or_insert(Value::Number(0.0));
+        if let Value::Number(n) = value {
+            *n += by as f64;
+        }
+    }
+
+    pub fn set_gauge(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut series = self.series.lock().unwrap();
+        series.entry(name).or_default().insert(to_labels(labels), Value::Number(value));
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], seconds: f64) {
+        let mut series = self.series.lock().unwrap();
+        let value = series.entry(name).or_default().entry(to_labels(labels))
+            .or_insert(Value::Histogram { buckets: [0; LATENCY_BUCKETS.len()], sum: 0.0, count: 0 });
+        if let Value::Histogram { buckets, sum, count } = value {
+            for (bucket, bound) in buckets.iter_mut().zip(LATENCY_BUCKETS) {
+                if seconds <= bound {
+                    *bucket += 1;
+                }
+            }
+            *sum += seconds;
+            *count += 1;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        for (name, kind, help) in FAMILIES {
+            let Some(family) = series.get(name) else { continue };
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, match kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+                Kind::Histogram => "histogram",
+            });
+            for (labels, value) in family {
+                match value {
+                    Value::Number(n) => {
+                        let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), n);
+                    },
+                    Value::Histogram { buckets, sum, count } => {
+                        for (bucket, bound) in buckets.iter().zip(LATENCY_BUCKETS) {
+                            let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some(&bound.to_string())), bucket);
+                        }
+                        let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(labels, Some("+Inf")), count);
+                        let _ = writeln!(out, "{}_sum{} {}", name, format_labels(labels, None), sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, format_labels(labels, None), count);
+                    },
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let escape = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    let mut pairs: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() { String::new() } else { format!("{{{}}}", pairs.join(",")) }
+}
+
+// Metrics and status of one pipeline, labelled with its name
+#[derive(Debug, Clone)]
+pub struct PipelineMetrics {
+    pipeline: String,
+}
+
+impl PipelineMetrics {
+    pub fn new(pipeline: &str) -> PipelineMetrics {
+        PipelineMetrics { pipeline: pipeline.to_owned() }
+    }
+
+    // counters start at zero so rates and alerts work before the first increment
+    pub fn register(&self, slot: &str) {
+        STATUS.update(&self.pipeline, |status| status.slot = Some(slot.to_owned()));
+        for name in ["cyphercdc_reconnects_total", "cyphercdc_auth_failures_total", "cyphercdc_transactions_total",
+            "cyphercdc_received_bytes_total"] {
+            METRICS.inc_counter(name, &[("pipeline", &self.pipeline)], 0);
+        }
+    }
+
+    pub fn received(&self, bytes: usize) {
+        METRICS.inc_counter("cyphercdc_received_bytes_total", &[("pipeline", &self.pipeline)], bytes as u64);
+    }
+
+    // counted per table and operation before filters, so the numbers match the source
+    pub fn transaction(&self, transaction: &Transaction) {
+        let mut counts: BTreeMap<(String, String), u64> = BTreeMap::new();
+        for event in &transaction.events {
+            *counts.entry((format!("{}.{}", event.schema, event.table), event.operation.to_string())).or_default() += 1;
+        }
+        STATUS.update(&self.pipeline, |status| for ((table, operation), count) in &counts {
+            *status.tables.entry(table.clone()).or_default().entry(operation.clone()).or_default() += count;
+        });
+        for ((table, operation), count) in counts {
+            METRICS.inc_counter("cyphercdc_events_total",
+                                &[("pipeline", &self.pipeline), ("table", &table), ("operation", &operation)], count);
+        }
+        METRICS.inc_counter("cyphercdc_transactions_total", &[("pipeline", &self.pipeline)], 1);
+    }
+
+    pub fn applied(&self, end_lsn: Lsn) {
+        METRICS.set_gauge("cyphercdc_applied_lsn", &[("pipeline", &self.pipeline)], end_lsn.0 as f64);
+        STATUS.update(&self.pipeline, |status| status.applied_lsn = end_lsn);
+    }
+
+    pub fn positions(&self, received: Lsn, flushed: Lsn, server_wal_end: Lsn, lag: Duration) {
+        let labels = [("pipeline", self.pipeline.as_str())];
+        METRICS.set_gauge("cyphercdc_received_lsn", &labels, received.0 as f64);
+        METRICS.set_gauge("cyphercdc_flushed_lsn", &labels, flushed.0 as f64);
+        METRICS.set_gauge("cyphercdc_lag_bytes", &labels, server_wal_end.0.saturating_sub(flushed.0) as f64);
+        METRICS.set_gauge("cyphercdc_lag_seconds", &labels, lag.as_secs_f64());
+        STATUS.update(&self.pipeline, |status| {
+            status.received_lsn = received;
+            status.flushed_lsn = flushed;
+            status.lag_bytes = server_wal_end.0.saturating_sub(flushed.0);
+            status.lag_seconds = lag.as_secs_f64();
+        });
+    }
+
+    pub fn connection_event(&self, event: &ConnectionEvent) {
+        STATUS.update(&self.pipeline, |status| match event {
+            ConnectionEvent::Connecting { .. } => status.state = PipelineState::Connecting,
+            ConnectionEvent::Authenticated { server } => {
+                status.state = PipelineState::Authenticated;
+                status.server = Some(server.clone());
+            },
+            ConnectionEvent::Streaming { .. } => status.state = PipelineState::Streaming,
+            ConnectionEvent::Disconnected { error, .. } => status.last_error = Some(error.clone()),
+            ConnectionEvent::Reconnecting { .. } => status.state = PipelineState::Reconnecting,
+            ConnectionEvent::Failed { error } => {
+                status.state = PipelineState::Failed;
+                status.last_error = Some(error.clone());
+            },
+            ConnectionEvent::Stopped { .. } => status.state = PipelineState::Stopped,
+            ConnectionEvent::TimelineSwitched { .. } => {},
+        });
+    }
+
+    pub fn reconnect(&self) {
+        METRICS.inc_counter("cyphercdc_reconnects_total", &[("pipeline", &self.pipeline)], 1);
+    }
+
+    pub fn auth_failure(&self) {
+        METRICS.inc_counter("cyphercdc_auth_failures_total", &[("pipeline", &self.pipeline)], 1);
+    }
+
+    pub fn sink_latency(&self, sink: &str, elapsed: Duration) {
+        METRICS.observe("cyphercdc_sink_latency_seconds", &[("pipeline", &self.pipeline), ("sink", sink)], elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::modules::metrics::registry::METRICS;
+use crate::modules::metrics::status::STATUS;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info};
+
+// Serves /metrics, /healthz, /readyz and /status on its own thread for the lifetime of the process
+pub fn serve_http(address: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!("Serving metrics and health checks on http://{}", listener.local_addr()?);
+    METRICS.set_gauge("cyphercdc_up", &[], 1.0);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // one request at a time is plenty, a stuck client only blocks until the timeout
+            if let Err(e) = handle(stream) {
+                debug!(error = %e, "HTTP request failed");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are not needed, but must be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header != "\r\n" && header != "\n" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default();
+    let failed = STATUS.failed();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", METRICS.render()),
+        // alive while this thread answers and no pipeline failed, the others keep the process running
+        ("GET", "/healthz") if failed.is_empty() => ("200 OK", "text/plain", String::from("ok\n")),
+        ("GET", "/healthz") => ("503 Service Unavailable", "text/plain", format!("failed: {}\n", failed.join(", "))),
+        ("GET", "/readyz") if STATUS.is_ready() => ("200 OK", "text/plain", String::from("ready\n")),
+        ("GET", "/readyz") => ("503 Service Unavailable", "text/plain", String::from("not ready\n")),
+        ("GET", "/status") => ("200 OK", "application/json", STATUS.to_json()),
+        ("GET", _) => ("404 Not Found", "text/plain", String::from("not found\n")),
+        _ => ("405 Method Not Allowed", "text/plain", String::from("method not allowed\n")),
+    };
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, content_type, body.len(), body)?;
+    stream.flush()
+}
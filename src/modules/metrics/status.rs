@@ -0,0 +1,93 @@
+use crate::modules::replication::lsn::Lsn;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+pub static STATUS: Lazy<StatusBoard> = Lazy::new(StatusBoard::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineState {
+    Starting,
+    Connecting,
+    Authenticated,
+    Streaming,
+    Reconnecting,
+    Snapshotting,
+    Stopped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineStatus {
+    pub state: PipelineState,
+    pub slot: Option<String>,
+    pub server: Option<String>,
+    pub received_lsn: Lsn,
+    pub flushed_lsn: Lsn,
+    pub applied_lsn: Lsn,
+    pub lag_bytes: u64,
+    pub lag_seconds: f64,
+    pub last_error: Option<String>,
+    // events per operation, or rows for a snapshot, by schema qualified table
+    pub tables: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Default for PipelineStatus {
+    fn default() -> Self {
+        PipelineStatus {
+            state: PipelineState::Starting,
+            slot: None,
+            server: None,
+            received_lsn: Lsn::default(),
+            flushed_lsn: Lsn::default(),
+            applied_lsn: Lsn::default(),
+            lag_bytes: 0,
+            lag_seconds: 0.0,
+            last_error: None,
+            tables: BTreeMap::new(),
+        }
+    }
+}
+
+// State of every pipeline and snapshot in the process, for /readyz and /status
+#[derive(Default)]
+pub struct StatusBoard {
+    pipelines: Mutex<BTreeMap<String, PipelineStatus>>,
+}
+
+impl StatusBoard {
+    pub fn update<F: FnOnce(&mut PipelineStatus)>(&self, name: &str, update: F) {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        update(pipelines.entry(name.to_owned()).or_default());
+    }
+
+    pub fn is_ready(&self) -> bool {
+        ready(&self.pipelines.lock().unwrap())
+    }
+
+    // names of the pipelines that gave up, the others keep running in the same process
+    pub fn failed(&self) -> Vec<String> {
+        self.pipelines.lock().unwrap().iter()
+            .filter(|(_, p)| p.state == PipelineState::Failed)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let pipelines = self.pipelines.lock().unwrap();
+        #[derive(Serialize)]
+        struct Report<'a> {
+            ready: bool,
+            pipelines: &'a BTreeMap<String, PipelineStatus>,
+        }
+        serde_json::to_string_pretty(&Report { ready: ready(&pipelines), pipelines: &pipelines }).unwrap_or_default()
+    }
+}
+
+// every pipeline is streaming or snapshotting, a process without any is not ready either
+fn ready(pipelines: &BTreeMap<String, PipelineStatus>) -> bool {
+    !pipelines.is_empty() && pipelines.values()
+        .all(|p| matches!(p.state, PipelineState::Streaming | PipelineState::Snapshotting))
+}
@@ -3,3 +3,15 @@ mod debug_utils;
 pub mod sasl;
 pub mod tcp;
 pub mod replication;
+pub mod shutdown;
+pub mod slot;
+pub mod publication;
+pub mod snapshot;
+pub mod pipeline;
+pub mod conninfo;
+pub mod check;
+pub mod heartbeat;
+pub mod metrics;
+pub mod logging;
+pub mod secret;
+pub mod sink;
\ No newline at end of file
@@ -0,0 +1,40 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+// configuration problem, with the position in the file when it is known
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: String,
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(path: &str, message: String) -> ConfigError {
+        ConfigError { path: path.to_owned(), position: None, message }
+    }
+
+    // span is a byte range into the file content
+    pub fn at(path: &str, content: &str, span: std::ops::Range<usize>, message: String) -> ConfigError {
+        ConfigError { path: path.to_owned(), position: Some(line_and_column(content, span.start)), message }
+    }
+}
+
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+
+    (line, column)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}", self.path, line, column, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
\ No newline at end of file
@@ -0,0 +1,779 @@
+use crate::dto::DBConfig;
+use crate::modules::conninfo::target::candidate_hosts;
+use crate::modules::conninfo::utils::{resolve_target_config, ConnParams};
+use crate::modules::heartbeat::{HeartbeatConfig, DEFAULT_HEARTBEAT_STATEMENT};
+use crate::modules::pipeline::config_error::ConfigError;
+use crate::modules::pipeline::filter::{TableFilter, TablePattern};
+use crate::modules::pipeline::transform::Transform;
+use crate::modules::pipeline::utils::Pipeline;
+use crate::modules::slot::monitor::{parse_bytes, parse_duration};
+use crate::modules::publication::spec::{PublicationSpec, PublicationTarget, TableSpec, PUBLISH_OPERATIONS};
+use crate::modules::secret::SecretString;
+use crate::modules::sink::avro::registry::COMPATIBILITY_LEVELS;
+use crate::modules::sink::avro::serializer::{SchemaRegistryConfig, SubjectStrategy};
+use crate::modules::sink::format::{CloudEventsMode, EventFormat};
+use crate::modules::sink::http_client::HttpClient;
+use crate::modules::sink::json_lines::{Compression, JsonLinesConfig, JsonLinesSink};
+use crate::modules::sink::kafka::kafka_sink::{KafkaConfig, KafkaSink, DEFAULT_BATCH_SIZE, DEFAULT_TOPIC};
+use crate::modules::sink::postgres::{OnConflict, PostgresConfig, PostgresSink, DEFAULT_OFFSETS_TABLE};
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::sqlite::{SqliteConfig, SqliteSink, DEFAULT_TABLE_NAME};
+use crate::modules::sink::stdout::StdoutSink;
+use crate::modules::sink::traits::Sink;
+use crate::modules::sink::webhook::{WebhookConfig, WebhookSink};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use toml::Spanned;
+
+// [[pipeline]] and [[publication]] tables of a pipelines file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineFile {
+    #[serde(rename = "pipeline", default)]
+    pipelines: Vec<Spanned<PipelineConfig>>,
+    #[serde(rename = "publication", default)]
+    publications: Vec<Spanned<PublicationConfig>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineConfig {
+    name: Spanned<String>,
+    #[serde(default)]
+    source: SourceConfig,
+    slot: Spanned<String>,
+    publications: Spanned<Vec<String>>,
+    #[serde(default)]
+    tables: TablesConfig,
+    #[serde(default)]
+    transforms: Vec<Spanned<TransformConfig>>,
+    #[serde(default)]
+    sinks: Vec<Spanned<SinkToml>>,
+    offset_file: Option<Spanned<EnvString>>,
+    heartbeat: Option<Spanned<HeartbeatToml>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeartbeatToml {
+    interval: String,
+    statement: Option<EnvString>,
+}
+
+// fields win over dsn, unset ones fall back to the command line, the service file and the environment
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SourceConfig {
+    dsn: Option<EnvString>,
+    host: Option<EnvString>,
+    port: Option<u16>,
+    dbname: Option<EnvString>,
+    user: Option<EnvString>,
+    password: Option<EnvString>,
+}
+
+// desired state of a publication, reconciled by `publication sync`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublicationConfig {
+    name: Spanned<String>,
+    #[serde(default)]
+    all_tables: bool,
+    #[serde(default)]
+    tables: Vec<Spanned<PublicationTableConfig>>,
+    #[serde(default)]
+    schemas: Vec<String>,
+    publish: Option<Spanned<Vec<String>>>,
+}
+
+// "schema.table" or { name, columns, filter }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PublicationTableConfig {
+    Name(String),
+    Detailed(PublicationTableDetails),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublicationTableDetails {
+    name: String,
+    columns: Option<Vec<String>>,
+    filter: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TablesConfig {
+    #[serde(default)]
+    include: Vec<Spanned<String>>,
+    #[serde(default)]
+    exclude: Vec<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TransformConfig {
+    DropColumns {
+        #[serde(default = "all_tables")]
+        tables: String,
+        columns: Vec<String>,
+    },
+    MaskColumns {
+        #[serde(default = "all_tables")]
+        tables: String,
+        columns: Vec<String>,
+        #[serde(default = "default_mask")]
+        mask: String,
+    },
+    RenameTable {
+        from: String,
+        to: String,
+    },
+}
+
+fn all_tables() -> String {
+    String::from("*.*")
+}
+
+fn default_mask() -> String {
+    String::from("****")
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SinkToml {
+    Stdout,
+    JsonLines {
+        path: Option<EnvString>,
+        rotate_size: Option<String>,
+        rotate_interval: Option<String>,
+        #[serde(default)]
+        compression: Compression,
+        #[serde(default)]
+        format: FormatToml,
+        #[serde(default)]
+        schemas: bool,
+        cloudevents_mode: Option<CloudEventsMode>,
+    },
+    Kafka {
+        brokers: Vec<EnvString>,
+        topic: Option<String>,
+        #[serde(default)]
+        routes: Vec<KafkaRouteToml>,
+        client_id: Option<String>,
+        acks: Option<String>,
+        #[serde(default = "default_true")]
+        idempotent: bool,
+        #[serde(default)]
+        compression: Compression,
+        batch_size: Option<String>,
+        timeout: Option<String>,
+        retries: Option<u32>,
+        #[serde(default)]
+        format: FormatToml,
+        #[serde(default)]
+        schemas: bool,
+        cloudevents_mode: Option<CloudEventsMode>,
+        schema_registry: Option<Box<SchemaRegistryToml>>,
+        tombstones: Option<bool>,
+        field_numbers: Option<EnvString>,
+    },
+    Webhook {
+        url: EnvString,
+        #[serde(default)]
+        format: FormatToml,
+        #[serde(default)]
+        schemas: bool,
+        cloudevents_mode: Option<CloudEventsMode>,
+        batch_size: Option<usize>,
+        linger: Option<String>,
+        #[serde(default)]
+        headers: BTreeMap<String, EnvString>,
+        hmac_secret: Option<EnvString>,
+        timeout: Option<String>,
+        retries: Option<u32>,
+        circuit_breaker: Option<CircuitBreakerToml>,
+    },
+    Postgres {
+        dsn: EnvString,
+        #[serde(default)]
+        on_conflict: OnConflict,
+        #[serde(default)]
+        create_tables: bool,
+        offsets_table: Option<String>,
+    },
+    Sqlite {
+        path: EnvString,
+        table_name: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CircuitBreakerToml {
+    failures: Option<u32>,
+    open_for: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaRegistryToml {
+    url: EnvString,
+    username: Option<EnvString>,
+    password: Option<EnvString>,
+    compatibility: Option<String>,
+    #[serde(default)]
+    subject_strategy: SubjectStrategy,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FormatToml {
+    #[default]
+    Json,
+    Debezium,
+    Cloudevents,
+    Avro,
+    Protobuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KafkaRouteToml {
+    tables: String,
+    topic: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone)]
+pub enum SinkConfig {
+    Stdout,
+    JsonLines(JsonLinesConfig),
+    Kafka(KafkaConfig),
+    Webhook(WebhookConfig),
+    Postgres(PostgresConfig),
+    Sqlite(SqliteConfig),
+}
+
+impl SinkConfig {
+    // database is the source database, named in the events of some formats
+    pub fn build(&self, pipeline: &str, database: &str) -> Result<Box<dyn Sink>, SinkError> {
+        match self {
+            SinkConfig::Stdout => Ok(Box::new(StdoutSink::new(pipeline))),
+            SinkConfig::JsonLines(config) => Ok(Box::new(JsonLinesSink::new(pipeline, database, config.clone())?)),
+            SinkConfig::Kafka(config) => Ok(Box::new(KafkaSink::new(pipeline, database, config.clone())?)),
+            SinkConfig::Webhook(config) => Ok(Box::new(WebhookSink::new(pipeline, database, config.clone())?)),
+            SinkConfig::Postgres(config) => Ok(Box::new(PostgresSink::new(pipeline, config.clone()))),
+            SinkConfig::Sqlite(config) => Ok(Box::new(SqliteSink::new(pipeline, config.clone()))),
+        }
+    }
+}
+
+// string with ${VAR} and ${VAR:-default} references resolved from the environment
+#[derive(Debug, Clone)]
+pub struct EnvString(pub String);
+
+impl<'de> Deserialize<'de> for EnvString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        interpolate_env(&raw).map(EnvString).map_err(serde::de::Error::custom)
+    }
+}
+
+// "$$" is a literal dollar sign
+pub fn interpolate_env(raw: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| format!("unterminated variable reference in '{}'", raw))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            match (env::var(name), default) {
+                // like the shell, ":-" also replaces a variable that is set but empty
+                (Ok(value), Some(default)) if value.is_empty() => result.push_str(default),
+                (Ok(value), _) => result.push_str(&value),
+                (Err(_), Some(default)) => result.push_str(default),
+                (Err(_), None) => return Err(format!("environment variable {} is not set", name)),
+            }
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+fn read_file(path: &str) -> Result<(String, PipelineFile), Vec<ConfigError>> {
+    let content = fs::read_to_string(path).map_err(|e| vec![ConfigError::new(path, e.to_string())])?;
+    let file: PipelineFile = toml::from_str(&content).map_err(|e| vec![match e.span() {
+        Some(span) => ConfigError::at(path, &content, span, e.message().to_owned()),
+        None => ConfigError::new(path, e.message().to_owned()),
+    }])?;
+
+    Ok((content, file))
+}
+
+pub fn load_publications(path: &str) -> Result<Vec<PublicationSpec>, Vec<ConfigError>> {
+    let (content, file) = read_file(path)?;
+    let mut errors = Vec::new();
+    if file.publications.is_empty() {
+        errors.push(ConfigError::new(path, String::from("no [[publication]] defined")));
+    }
+    let mut names = HashSet::new();
+    let mut publications = Vec::new();
+    for spanned in file.publications {
+        let span = spanned.span();
+        let config = spanned.into_inner();
+        let mut error = |span: std::ops::Range<usize>, message: String| errors.push(ConfigError::at(path, &content, span, message));
+
+        if config.name.get_ref().is_empty() {
+            error(config.name.span(), String::from("publication name must not be empty"));
+        } else if !names.insert(config.name.get_ref().clone()) {
+            error(config.name.span(), format!("duplicate publication name '{}'", config.name.get_ref()));
+        }
+        if config.all_tables && (!config.tables.is_empty() || !config.schemas.is_empty()) {
+            error(span.clone(), String::from("all_tables cannot be combined with tables or schemas"));
+        }
+        let publish = match &config.publish {
+            Some(publish) => {
+                if publish.get_ref().is_empty() {
+                    error(publish.span(), String::from("publish needs at least one operation"));
+                }
+                for operation in publish.get_ref() {
+                    if !PUBLISH_OPERATIONS.contains(&operation.as_str()) {
+                        error(publish.span(), format!("unknown publish operation '{}', expected one of {}",
+                                                      operation, PUBLISH_OPERATIONS.join(", ")));
+                    }
+                }
+                publish.get_ref().clone()
+            },
+            None => PUBLISH_OPERATIONS.iter().map(|o| o.to_string()).collect(),
+        };
+
+        let mut tables: Vec<TableSpec> = Vec::new();
+        for table in &config.tables {
+            let spec = match table.get_ref() {
+                PublicationTableConfig::Name(name) => TableSpec::new(name),
+                PublicationTableConfig::Detailed(details) => TableSpec {
+                    columns: details.columns.clone(),
+                    row_filter: details.filter.clone(),
+                    ..TableSpec::new(&details.name)
+                },
+            };
+            if spec.columns.as_ref().is_some_and(Vec::is_empty) {
+                error(table.span(), format!("column list of {} must not be empty", spec.name));
+            }
+            if tables.iter().any(|t| t.name == spec.name) {
+                error(table.span(), format!("table {} is listed twice", spec.name));
+            }
+            tables.push(spec);
+        }
+
+        publications.push(PublicationSpec {
+            name: config.name.into_inner(),
+            target: if config.all_tables {
+                PublicationTarget::AllTables
+            } else {
+                PublicationTarget::Objects { tables, schemas: config.schemas }
+            },
+            publish,
+        });
+    }
+
+    if errors.is_empty() { Ok(publications) } else { Err(errors) }
+}
+
+pub fn load_pipelines(path: &str, base_dsn: Option<&str>, base_params: &ConnParams) -> Result<Vec<Pipeline>, Vec<ConfigError>> {
+    let (content, file) = read_file(path)?;
+
+    let mut errors = Vec::new();
+    if file.pipelines.is_empty() {
+        errors.push(ConfigError::new(path, String::from("no [[pipeline]] defined")));
+    }
+    let mut names = HashSet::new();
+    let mut offset_files = HashSet::new();
+    let mut pipelines = Vec::new();
+    for spanned in file.pipelines {
+        let span = spanned.span();
+        let config = spanned.into_inner();
+        let mut error = |span: std::ops::Range<usize>, message: String| errors.push(ConfigError::at(path, &content, span, message));
+
+        if config.name.get_ref().is_empty() {
+            error(config.name.span(), String::from("pipeline name must not be empty"));
+        } else if !names.insert(config.name.get_ref().clone()) {
+            error(config.name.span(), format!("duplicate pipeline name '{}'", config.name.get_ref()));
+        }
+        let slot = config.slot.get_ref();
+        if slot.is_empty() || !slot.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            error(config.slot.span(), format!("invalid slot name '{}', use lower case letters, digits and underscores", slot));
+        }
+        if config.publications.get_ref().is_empty() {
+            error(config.publications.span(), String::from("at least one publication is required"));
+        }
+        if config.sinks.is_empty() {
+            error(span.clone(), format!("pipeline '{}' has no sinks", config.name.get_ref()));
+        }
+
+        if let Some(offset_file) = &config.offset_file
+            && !offset_files.insert(offset_file.get_ref().0.clone()) {
+            error(offset_file.span(), format!("offset file '{}' is used by another pipeline", offset_file.get_ref().0));
+        }
+
+        let heartbeat = config.heartbeat.as_ref().and_then(|heartbeat| match parse_duration(&heartbeat.get_ref().interval) {
+            Ok(interval) if !interval.is_zero() => Some(HeartbeatConfig {
+                interval,
+                statement: heartbeat.get_ref().statement.as_ref()
+                    .map(|s| s.0.clone()).unwrap_or_else(|| DEFAULT_HEARTBEAT_STATEMENT.to_owned()),
+            }),
+            Ok(_) => {
+                error(heartbeat.span(), String::from("heartbeat interval must be positive"));
+                None
+            },
+            Err(e) => {
+                error(heartbeat.span(), e);
+                None
+            },
+        });
+
+        let mut filter = TableFilter::default();
+        for (patterns, target) in [(&config.tables.include, &mut filter.include), (&config.tables.exclude, &mut filter.exclude)] {
+            for pattern in patterns {
+                match TablePattern::parse(pattern.get_ref()) {
+                    Ok(p) => target.push(p),
+                    Err(e) => error(pattern.span(), e),
+                }
+            }
+        }
+
+        let mut transforms = Vec::new();
+        for transform in &config.transforms {
+            match build_transform(transform.get_ref()) {
+                Ok(t) => transforms.push(t),
+                Err(e) => error(transform.span(), e),
+            }
+        }
+
+        let mut sinks = Vec::new();
+        for sink in &config.sinks {
+            match build_sink(sink.get_ref()) {
+                Ok(s) => sinks.push((s, sink.span())),
+                Err(e) => error(sink.span(), e),
+            }
+        }
+
+        let mut params = base_params.clone();
+        let fields = [("host", config.source.host.as_ref().map(|h| h.0.clone())), ("port", config.source.port.map(|p| p.to_string())),
+            ("dbname", config.source.dbname.as_ref().map(|d| d.0.clone())), ("user", config.source.user.as_ref().map(|u| u.0.clone())),
+            ("password", config.source.password.as_ref().map(|p| p.0.clone()))];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                params.insert(key.to_owned(), value);
+            }
+        }
+        let dsn = config.source.dsn.as_ref().map(|d| d.0.as_str()).or(base_dsn);
+        let mut source = match DBConfig::resolve(dsn, &params) {
+            Ok(source) => source,
+            Err(e) => {
+                error(span.clone(), format!("pipeline '{}': {}", config.name.get_ref(), e));
+                continue;
+            },
+        };
+        source.replication_slot = slot.clone();
+        source.publication_name = config.publications.get_ref().join(",");
+        for (sink, span) in &sinks {
+            if let SinkConfig::Postgres(postgres) = sink
+                && same_database(&source, &postgres.target) {
+                error(span.clone(), format!("the postgres sink's target is the source database {} of pipeline '{}'",
+                                            source.db_name, config.name.get_ref()));
+            }
+        }
+
+        pipelines.push(Pipeline {
+            name: config.name.into_inner(),
+            source,
+            filter,
+            transforms,
+            sinks: sinks.into_iter().map(|(sink, _)| sink).collect(),
+            offset_file: config.offset_file.map(|f| PathBuf::from(f.into_inner().0)),
+            heartbeat,
+        });
+    }
+
+    if errors.is_empty() { Ok(pipelines) } else { Err(errors) }
+}
+
+// applying the changes to the database they come from would replicate them again, endlessly
+fn same_database(source: &DBConfig, target: &DBConfig) -> bool {
+    let (Ok(sources), Ok(targets)) = (candidate_hosts(source), candidate_hosts(target)) else {
+        return false;
+    };
+    source.db_name == target.db_name && targets.iter().any(|host| sources.contains(host))
+}
+
+fn build_sink(config: &SinkToml) -> Result<SinkConfig, String> {
+    match config {
+        SinkToml::Stdout => Ok(SinkConfig::Stdout),
+        SinkToml::JsonLines { path, rotate_size, rotate_interval, compression, format, schemas, cloudevents_mode } => {
+            let format = build_format(*format, *schemas, *cloudevents_mode)?;
+            if format == (EventFormat::CloudEvents { mode: CloudEventsMode::Binary }) {
+                return Err(String::from("binary CloudEvents need headers, json_lines only writes structured ones"));
+            }
+            if format == EventFormat::Protobuf {
+                return Err(String::from("format = \"protobuf\" is binary, json_lines writes one JSON object per line"));
+            }
+            let rotate_size = rotate_size.as_deref().map(parse_bytes).transpose()?;
+            if rotate_size.is_some_and(|size| size <= 0) {
+                return Err(String::from("rotate_size must be positive"));
+            }
+            let rotate_interval = rotate_interval.as_deref().map(parse_duration).transpose()?;
+            if rotate_interval.is_some_and(|interval| interval.is_zero()) {
+                return Err(String::from("rotate_interval must be positive"));
+            }
+            if path.is_none() && (rotate_size.is_some() || rotate_interval.is_some() || *compression != Compression::None) {
+                return Err(String::from("rotation and compression need a path, stdout is never rotated"));
+            }
+            Ok(SinkConfig::JsonLines(JsonLinesConfig {
+                path: path.as_ref().map(|p| PathBuf::from(&p.0)),
+                rotate_size: rotate_size.map(|size| size as u64),
+                rotate_interval,
+                compression: *compression,
+                format,
+            }))
+        },
+        SinkToml::Kafka { brokers, topic, routes, client_id, acks, idempotent, compression, batch_size, timeout, retries,
+                          format, schemas, cloudevents_mode, schema_registry, tombstones, field_numbers } => {
+            let avro = match (format, schema_registry) {
+                (FormatToml::Avro, Some(registry)) => Some(build_schema_registry(registry)?),
+                (FormatToml::Avro, None) => return Err(String::from("format = \"avro\" needs a schema_registry")),
+                (_, Some(_)) => return Err(String::from("schema_registry is only used with format = \"avro\"")),
+                (_, None) => None,
+            };
+            let format = build_format(if avro.is_some() { FormatToml::Json } else { *format }, *schemas, *cloudevents_mode)?;
+            // consumers decode by field number, after a restart a column must get the number it had
+            match (format, field_numbers) {
+                (EventFormat::Protobuf, None) => {
+                    return Err(String::from("format = \"protobuf\" needs field_numbers, the file that keeps the field number of every column"));
+                },
+                (EventFormat::Protobuf, Some(_)) | (_, None) => {},
+                (_, Some(_)) => return Err(String::from("field_numbers is only used with format = \"protobuf\"")),
+            }
+            if brokers.is_empty() {
+                return Err(String::from("kafka needs at least one broker"));
+            }
+            // acks = 0 would leave nothing to wait for before the LSN is acknowledged
+            let acks = match acks.as_deref() {
+                None | Some("all") | Some("-1") => -1,
+                Some("1") => 1,
+                Some(other) => return Err(format!("invalid acks '{}', use \"all\" or \"1\"", other)),
+            };
+            if *idempotent && acks != -1 {
+                return Err(String::from("an idempotent producer needs acks = \"all\""));
+            }
+            let mut parsed_routes = Vec::new();
+            for route in routes {
+                parsed_routes.push((TablePattern::parse(&route.tables)?, route.topic.clone()));
+            }
+            let batch_size = parse_bytes(batch_size.as_deref().unwrap_or(DEFAULT_BATCH_SIZE))?;
+            if batch_size <= 0 {
+                return Err(String::from("batch_size must be positive"));
+            }
+            let timeout = parse_duration(timeout.as_deref().unwrap_or("30s"))?;
+            if timeout.is_zero() {
+                return Err(String::from("timeout must be positive"));
+            }
+            Ok(SinkConfig::Kafka(KafkaConfig {
+                brokers: brokers.iter().map(|b| b.0.clone()).collect(),
+                topic: topic.clone().unwrap_or_else(|| DEFAULT_TOPIC.to_owned()),
+                routes: parsed_routes,
+                client_id: client_id.clone().unwrap_or_else(|| String::from("cyphercdc")),
+                acks,
+                idempotent: *idempotent,
+                compression: *compression,
+                batch_size: batch_size as usize,
+                timeout,
+                retries: retries.unwrap_or(5),
+                format,
+                avro,
+                // Debezium emits tombstones by default (tombstones.on.delete)
+                tombstones: tombstones.unwrap_or(matches!(format, EventFormat::Debezium { .. })),
+                field_numbers: field_numbers.as_ref().map(|path| PathBuf::from(&path.0)),
+            }))
+        },
+        SinkToml::Webhook { url, format, schemas, cloudevents_mode, batch_size, linger, headers, hmac_secret, timeout, retries,
+                            circuit_breaker } => {
+            HttpClient::new(&url.0, Duration::ZERO)?;
+            let format = build_format(*format, *schemas, *cloudevents_mode)?;
+            if format == (EventFormat::CloudEvents { mode: CloudEventsMode::Binary }) {
+                return Err(String::from("binary CloudEvents need headers per event, webhook sends structured batches"));
+            }
+            if format == EventFormat::Protobuf {
+                return Err(String::from("format = \"protobuf\" is binary, webhook sends JSON arrays"));
+            }
+            let batch_size = batch_size.unwrap_or(100);
+            if batch_size == 0 {
+                return Err(String::from("batch_size must be positive"));
+            }
+            // the client writes Host, Content-Length and Content-Type itself
+            for (name, value) in headers {
+                if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)) {
+                    return Err(format!("invalid header name '{}'", name));
+                }
+                if ["host", "content-length", "content-type", "transfer-encoding"].contains(&name.to_ascii_lowercase().as_str()) {
+                    return Err(format!("header {} is set by the sink", name));
+                }
+                if value.0.contains(['\r', '\n']) {
+                    return Err(format!("header {} contains a line break", name));
+                }
+            }
+            let timeout = parse_duration(timeout.as_deref().unwrap_or("10s"))?;
+            if timeout.is_zero() {
+                return Err(String::from("timeout must be positive"));
+            }
+            let failure_threshold = circuit_breaker.as_ref().and_then(|c| c.failures).unwrap_or(5);
+            if failure_threshold == 0 {
+                return Err(String::from("circuit_breaker.failures must be positive"));
+            }
+            let open_duration = parse_duration(circuit_breaker.as_ref().and_then(|c| c.open_for.as_deref()).unwrap_or("30s"))?;
+            Ok(SinkConfig::Webhook(WebhookConfig {
+                url: url.0.clone(),
+                format,
+                batch_size,
+                linger: parse_duration(linger.as_deref().unwrap_or("100ms"))?,
+                headers: headers.iter().map(|(name, value)| (name.clone(), SecretString::new(value.0.clone()))).collect(),
+                hmac_secret: hmac_secret.as_ref().map(|secret| SecretString::new(secret.0.clone())),
+                timeout,
+                retries: retries.unwrap_or(5),
+                failure_threshold,
+                open_duration,
+            }))
+        },
+        SinkToml::Postgres { dsn, on_conflict, create_tables, offsets_table } => {
+            // the source's flags and environment variables are not meant for the target
+            let target = resolve_target_config(&dsn.0)?;
+            let offsets_table = offsets_table.clone().unwrap_or_else(|| DEFAULT_OFFSETS_TABLE.to_owned());
+            if offsets_table.split('.').any(str::is_empty) || offsets_table.split('.').count() > 2 {
+                return Err(format!("invalid offsets_table '{}', expected table or schema.table", offsets_table));
+            }
+            Ok(SinkConfig::Postgres(PostgresConfig { target, on_conflict: *on_conflict, create_tables: *create_tables, offsets_table }))
+        },
+        SinkToml::Sqlite { path, table_name } => {
+            let table_name = table_name.clone().unwrap_or_else(|| DEFAULT_TABLE_NAME.to_owned());
+            if !table_name.contains("{table}") {
+                return Err(format!("table_name '{}' must contain {{table}}, or every table would be mirrored into one", table_name));
+            }
+            Ok(SinkConfig::Sqlite(SqliteConfig { path: PathBuf::from(&path.0), table_name }))
+        },
+    }
+}
+
+fn build_format(format: FormatToml, schemas: bool, cloudevents_mode: Option<CloudEventsMode>) -> Result<EventFormat, String> {
+    if schemas && !matches!(format, FormatToml::Debezium) {
+        return Err(String::from("schemas need format = \"debezium\""));
+    }
+    if cloudevents_mode.is_some() && !matches!(format, FormatToml::Cloudevents) {
+        return Err(String::from("cloudevents_mode needs format = \"cloudevents\""));
+    }
+
+    Ok(match format {
+        FormatToml::Json => EventFormat::Json,
+        FormatToml::Debezium => EventFormat::Debezium { schemas },
+        FormatToml::Cloudevents => EventFormat::CloudEvents { mode: cloudevents_mode.unwrap_or_default() },
+        FormatToml::Protobuf => EventFormat::Protobuf,
+        FormatToml::Avro => return Err(String::from("format = \"avro\" is only supported by the kafka sink")),
+    })
+}
+
+fn build_schema_registry(config: &SchemaRegistryToml) -> Result<SchemaRegistryConfig, String> {
+    HttpClient::new(&config.url.0, Duration::ZERO)?;
+    let compatibility = config.compatibility.as_ref().map(|c| c.to_ascii_uppercase());
+    if let Some(compatibility) = &compatibility
+        && !COMPATIBILITY_LEVELS.contains(&compatibility.as_str()) {
+        return Err(format!("unknown compatibility '{}', expected one of {}", compatibility, COMPATIBILITY_LEVELS.join(", ")));
+    }
+    let credentials = match (&config.username, &config.password) {
+        (Some(username), Some(password)) => Some((username.0.clone(), SecretString::new(password.0.clone()))),
+        (None, None) => None,
+        _ => return Err(String::from("schema_registry needs both username and password")),
+    };
+
+    Ok(SchemaRegistryConfig { url: config.url.0.clone(), credentials, compatibility, subject_strategy: config.subject_strategy })
+}
+
+fn build_transform(config: &TransformConfig) -> Result<Transform, String> {
+    match config {
+        TransformConfig::DropColumns { tables, columns } => {
+            if columns.is_empty() {
+                return Err(String::from("drop_columns needs at least one column"));
+            }
+            Ok(Transform::DropColumns { tables: TablePattern::parse(tables)?, columns: columns.clone() })
+        },
+        TransformConfig::MaskColumns { tables, columns, mask } => {
+            if columns.is_empty() {
+                return Err(String::from("mask_columns needs at least one column"));
+            }
+            Ok(Transform::MaskColumns { tables: TablePattern::parse(tables)?, columns: columns.clone(), mask: mask.clone() })
+        },
+        TransformConfig::RenameTable { from, to } => {
+            let (schema, table) = to.split_once('.')
+                .ok_or_else(|| format!("invalid rename target '{}', expected schema.table", to))?;
+            Ok(Transform::RenameTable { from: TablePattern::parse(from)?, schema: schema.to_owned(), table: table.to_owned() })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_environment_variables() {
+        // no other test touches these variables
+        unsafe {
+            env::set_var("CYPHERCDC_TEST_HOST", "db.internal");
+            env::set_var("CYPHERCDC_TEST_EMPTY", "");
+            env::remove_var("CYPHERCDC_TEST_UNSET");
+        }
+        assert_eq!(interpolate_env("postgres://${CYPHERCDC_TEST_HOST}:5432").unwrap(), "postgres://db.internal:5432");
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_HOST}${CYPHERCDC_TEST_HOST}").unwrap(), "db.internaldb.internal");
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_UNSET:-localhost}").unwrap(), "localhost");
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_UNSET:-}").unwrap(), "");
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_HOST:-localhost}").unwrap(), "db.internal");
+        // set but empty takes the default too, like ${VAR:-default} in a shell
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_EMPTY:-x}").unwrap(), "x");
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_EMPTY}").unwrap(), "");
+        assert_eq!(interpolate_env("no references").unwrap(), "no references");
+    }
+
+    #[test]
+    fn keeps_literal_dollar_signs() {
+        assert_eq!(interpolate_env("pa$$word").unwrap(), "pa$word");
+        assert_eq!(interpolate_env("$$${CYPHERCDC_TEST_UNSET_TOO:-x}").unwrap(), "$x");
+        assert_eq!(interpolate_env("cost: 5$ or $HOME").unwrap(), "cost: 5$ or $HOME");
+        assert_eq!(interpolate_env("$").unwrap(), "$");
+    }
+
+    #[test]
+    fn rejects_unset_and_unterminated_references() {
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_NEVER_SET}"), Err(String::from("environment variable CYPHERCDC_TEST_NEVER_SET is not set")));
+        assert_eq!(interpolate_env("${CYPHERCDC_TEST_HOST"), Err(String::from("unterminated variable reference in '${CYPHERCDC_TEST_HOST'")));
+    }
+}
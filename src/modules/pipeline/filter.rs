@@ -0,0 +1,48 @@
+// "schema.table" pattern where '*' matches any run of characters, e.g. "public.*" or "*.audit_*"
+#[derive(Debug, Clone)]
+pub struct TablePattern {
+    schema: String,
+    table: String,
+}
+
+impl TablePattern {
+    pub fn parse(pattern: &str) -> Result<TablePattern, String> {
+        match pattern.split_once('.') {
+            Some((schema, table)) if !schema.is_empty() && !table.is_empty() && !table.contains('.') =>
+                Ok(TablePattern { schema: schema.to_owned(), table: table.to_owned() }),
+            _ => Err(format!("invalid table pattern '{}', expected schema.table", pattern)),
+        }
+    }
+
+    pub fn matches(&self, schema: &str, table: &str) -> bool {
+        wildcard_match(&self.schema, schema) && wildcard_match(&self.table, table)
+    }
+}
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == value,
+        Some((prefix, rest)) => {
+            if !value.starts_with(prefix) {
+                return false;
+            }
+            let value = &value[prefix.len()..];
+            (0..=value.len()).filter(|&i| value.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &value[i..]))
+        },
+    }
+}
+
+// an empty include list lets every table through
+#[derive(Debug, Clone, Default)]
+pub struct TableFilter {
+    pub include: Vec<TablePattern>,
+    pub exclude: Vec<TablePattern>,
+}
+
+impl TableFilter {
+    pub fn matches(&self, schema: &str, table: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(schema, table)))
+            && !self.exclude.iter().any(|p| p.matches(schema, table))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,5 @@
+pub mod config_error;
+pub mod config_file;
+pub mod filter;
+pub mod transform;
+pub mod utils;
\ No newline at end of file
@@ -0,0 +1,43 @@
+use crate::modules::pipeline::filter::TablePattern;
+use crate::modules::replication::dto::{ChangeEvent, ColumnValue};
+
+#[derive(Debug, Clone)]
+pub enum Transform {
+    DropColumns { tables: TablePattern, columns: Vec<String> },
+    MaskColumns { tables: TablePattern, columns: Vec<String>, mask: String },
+    RenameTable { from: TablePattern, schema: String, table: String },
+}
+
+impl Transform {
+    pub fn apply(&self, mut event: ChangeEvent) -> ChangeEvent {
+        match self {
+            Transform::DropColumns { tables, columns } if tables.matches(&event.schema, &event.table) => {
+                let keep: Vec<bool> = event.columns.iter().map(|c| !columns.contains(&c.name)).collect();
+                event.columns = retain_by(event.columns, &keep);
+                event.old = event.old.map(|values| retain_by(values, &keep));
+                event.new = event.new.map(|values| retain_by(values, &keep));
+            },
+            Transform::MaskColumns { tables, columns, mask } if tables.matches(&event.schema, &event.table) => {
+                let masked: Vec<bool> = event.columns.iter().map(|c| columns.contains(&c.name)).collect();
+                for values in [&mut event.old, &mut event.new].into_iter().flatten() {
+                    for (value, masked) in values.iter_mut().zip(&masked) {
+                        if *masked && !matches!(value, ColumnValue::Null | ColumnValue::UnchangedToast) {
+                            *value = ColumnValue::Text(mask.clone());
+                        }
+                    }
+                }
+            },
+            Transform::RenameTable { from, schema, table } if from.matches(&event.schema, &event.table) => {
+                event.schema = schema.clone();
+                event.table = table.clone();
+            },
+            _ => {},
+        }
+
+        event
+    }
+}
+
+fn retain_by<T>(values: Vec<T>, keep: &[bool]) -> Vec<T> {
+    values.into_iter().zip(keep).filter(|(_, keep)| **keep).map(|(value, _)| value).collect()
+}
\ No newline at end of file
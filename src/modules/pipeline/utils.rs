@@ -0,0 +1,176 @@
+use crate::dto::DBConfig;
+use crate::modules::heartbeat::{Heartbeat, HeartbeatConfig};
+use crate::modules::metrics::registry::PipelineMetrics;
+use crate::modules::pipeline::config_file::SinkConfig;
+use crate::modules::pipeline::filter::TableFilter;
+use crate::modules::pipeline::transform::Transform;
+use crate::modules::replication::dto::Transaction;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::offset_store::OffsetStore;
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::supervisor::{ConnectionEvent, Supervisor};
+use crate::modules::shutdown::ShutdownSignal;
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
+use tracing::{error, info, info_span, warn};
+
+pub struct Pipeline {
+    pub name: String,
+    // connection settings, including slot and publications
+    pub source: DBConfig,
+    pub filter: TableFilter,
+    pub transforms: Vec<Transform>,
+    pub sinks: Vec<SinkConfig>,
+    // where the flushed LSN is persisted, streaming resumes from it after a restart
+    pub offset_file: Option<PathBuf>,
+    pub heartbeat: Option<HeartbeatConfig>,
+}
+
+impl Pipeline {
+    // the single pipeline used when no pipelines file is given
+    pub fn from_config(config: &DBConfig) -> Pipeline {
+        Pipeline {
+            name: config.replication_slot.clone(),
+            source: config.clone(),
+            filter: TableFilter::default(),
+            transforms: Vec::new(),
+            sinks: vec![SinkConfig::Stdout],
+            offset_file: None,
+            heartbeat: None,
+        }
+    }
+
+    // the filter, the transforms and the configured sinks as one sink for the supervisor
+    pub fn sink(&self, metrics: PipelineMetrics) -> Result<PipelineSink, SinkError> {
+        let sinks = self.sinks.iter().map(|sink| sink.build(&self.name, &self.source.db_name)).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(PipelineSink {
+            name: self.name.clone(),
+            filter: self.filter.clone(),
+            transforms: self.transforms.clone(),
+            sinks,
+            metrics,
+        })
+    }
+}
+
+// Filters and transforms each transaction, then writes it to every sink of the pipeline
+pub struct PipelineSink {
+    name: String,
+    filter: TableFilter,
+    transforms: Vec<Transform>,
+    sinks: Vec<Box<dyn Sink>>,
+    metrics: PipelineMetrics,
+}
+
+#[async_trait]
+impl Sink for PipelineSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn identify(&mut self, system_id: &str) {
+        for sink in &mut self.sinks {
+            sink.identify(system_id);
+        }
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        let mut transaction = batch.clone();
+        let events = std::mem::take(&mut transaction.events);
+        transaction.events = events.into_iter()
+            .filter(|event| self.filter.matches(&event.schema, &event.table))
+            .map(|event| self.transforms.iter().fold(event, |event, transform| transform.apply(event)))
+            .collect();
+
+        for sink in &mut self.sinks {
+            let started = Instant::now();
+            sink.write_batch(&transaction).await?;
+            self.metrics.sink_latency(sink.name(), started.elapsed());
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        for sink in &mut self.sinks {
+            sink.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn acknowledge(&mut self, lsn: Lsn) -> Result<(), SinkError> {
+        for sink in &mut self.sinks {
+            sink.acknowledge(lsn).await?;
+        }
+
+        Ok(())
+    }
+
+    // every sink gets closed, the first error is reported
+    async fn close(&mut self) -> Result<(), SinkError> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            let closed = sink.close().await;
+            if result.is_ok() {
+                result = closed;
+            }
+        }
+
+        result
+    }
+}
+
+// Runs every pipeline on its own thread until shutdown or a fatal error, returns the failed pipelines
+pub fn run_pipelines(pipelines: Vec<Pipeline>, shutdown: &ShutdownSignal) -> Vec<(String, ReplicationError)> {
+    let handles: Vec<_> = pipelines.into_iter().map(|pipeline| {
+        let shutdown = shutdown.clone();
+        let name = pipeline.name.clone();
+        let handle = thread::spawn(move || {
+            let metrics = PipelineMetrics::new(&pipeline.name);
+            let sink = pipeline.sink(metrics.clone()).map_err(ReplicationError::Sink)?;
+            // the heartbeat lives as long as its pipeline
+            let heartbeat_stop = ShutdownSignal::new();
+            let heartbeat = pipeline.heartbeat.clone().map(|config| {
+                Heartbeat::new(&pipeline.name, pipeline.source.clone(), config).with_shutdown(heartbeat_stop.clone()).spawn()
+            });
+            let _span = info_span!("pipeline", pipeline = %pipeline.name, slot = %pipeline.source.replication_slot).entered();
+            let mut supervisor = Supervisor::new(pipeline.source.clone())
+                .with_shutdown(shutdown)
+                .with_metrics(metrics)
+                .on_event(|event| match event {
+                    ConnectionEvent::Failed { .. } => error!("Connection event: {}", event),
+                    ConnectionEvent::Disconnected { .. } | ConnectionEvent::Reconnecting { .. }
+                    | ConnectionEvent::TimelineSwitched { .. } => warn!("Connection event: {}", event),
+                    _ => info!("Connection event: {}", event),
+                });
+            if let Some(path) = pipeline.offset_file.clone() {
+                supervisor = supervisor.with_offset_store(OffsetStore::new(path));
+            }
+            let mut supervisor = supervisor.with_sink(Box::new(sink));
+            let result = supervisor.run();
+            heartbeat_stop.trigger();
+            if let Some(heartbeat) = heartbeat {
+                let _ = heartbeat.join();
+            }
+            result
+        });
+        (name, handle)
+    }).collect();
+
+    let mut failures = Vec::new();
+    for (name, handle) in handles {
+        match handle.join() {
+            Ok(Ok(())) => {},
+            Ok(Err(error)) => failures.push((name, error)),
+            Err(_) => failures.push((name, ReplicationError::ProtocolViolation(String::from("pipeline thread panicked")))),
+        }
+    }
+
+    failures
+}
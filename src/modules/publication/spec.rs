@@ -0,0 +1,309 @@
+use crate::modules::db::{quote_identifier, quote_table_name};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// operations a publication can publish, all four by default
+pub const PUBLISH_OPERATIONS: [&str; 4] = ["insert", "update", "delete", "truncate"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSpec {
+    // schema qualified, "schema.table"
+    pub name: String,
+    // column list, PostgreSQL 15+
+    pub columns: Option<Vec<String>>,
+    // row filter expression, PostgreSQL 15+
+    pub row_filter: Option<String>,
+}
+
+impl TableSpec {
+    pub fn new(name: &str) -> TableSpec {
+        let name = if name.contains('.') { name.to_owned() } else { format!("public.{}", name) };
+        TableSpec { name, columns: None, row_filter: None }
+    }
+
+    fn sql(&self) -> String {
+        let mut sql = format!("TABLE {}", quote_table_name(&self.name));
+        if let Some(columns) = &self.columns {
+            let columns: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+            sql.push_str(&format!(" ({})", columns.join(", ")));
+        }
+        if let Some(filter) = &self.row_filter {
+            sql.push_str(&format!(" WHERE ({})", filter));
+        }
+        sql
+    }
+
+    fn same_as(&self, other: &TableSpec) -> bool {
+        let sorted = |columns: &Option<Vec<String>>| columns.as_ref().map(|c| {
+            let mut c = c.clone();
+            c.sort();
+            c
+        });
+        sorted(&self.columns) == sorted(&other.columns)
+            && self.row_filter.as_deref().map(normalize_filter) == other.row_filter.as_deref().map(normalize_filter)
+    }
+}
+
+// The server prints filters in its own form, "(active = true)" for "active=true"; comparing without
+// whitespace, case and outer parentheses avoids re-applying unchanged filters in the common cases.
+// Quoted literals and identifiers are kept as they are, 'Active' is not 'active'
+fn normalize_filter(filter: &str) -> String {
+    let mut normalized = String::with_capacity(filter.len());
+    let mut quote: Option<char> = None;
+    for c in filter.chars() {
+        match quote {
+            Some(open) => {
+                normalized.push(c);
+                if c == open {
+                    quote = None;
+                }
+            },
+            None if c == '\'' || c == '"' => {
+                normalized.push(c);
+                quote = Some(c);
+            },
+            None if c.is_whitespace() => {},
+            None => normalized.extend(c.to_lowercase()),
+        }
+    }
+    while normalized.starts_with('(') && normalized.ends_with(')') && balanced(&normalized[1..normalized.len() - 1]) {
+        normalized = normalized[1..normalized.len() - 1].to_owned();
+    }
+    normalized
+}
+
+// whether the parentheses outside quotes match up
+fn balanced(expression: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    for c in expression.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {},
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            _ => {},
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublicationTarget {
+    AllTables,
+    Objects { tables: Vec<TableSpec>, schemas: Vec<String> },
+}
+
+// desired or current state of a publication
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicationSpec {
+    pub name: String,
+    pub target: PublicationTarget,
+    pub publish: Vec<String>,
+}
+
+impl PublicationSpec {
+    // whether the publication needs PostgreSQL 15 (column lists, row filters, TABLES IN SCHEMA)
+    pub fn needs_pg15(&self) -> bool {
+        match &self.target {
+            PublicationTarget::AllTables => false,
+            PublicationTarget::Objects { tables, schemas } => !schemas.is_empty()
+                || tables.iter().any(|t| t.columns.is_some() || t.row_filter.is_some()),
+        }
+    }
+
+    fn publish_option(&self) -> String {
+        format!("WITH (publish = '{}')", self.publish.join(", "))
+    }
+
+    fn create_sql(&self) -> String {
+        let target = match &self.target {
+            PublicationTarget::AllTables => String::from(" FOR ALL TABLES"),
+            PublicationTarget::Objects { tables, schemas } => {
+                let objects: Vec<String> = tables.iter().map(TableSpec::sql)
+                    .chain(schemas.iter().map(|s| format!("TABLES IN SCHEMA {}", quote_identifier(s))))
+                    .collect();
+                if objects.is_empty() { String::new() } else { format!(" FOR {}", objects.join(", ")) }
+            },
+        };
+        format!("CREATE PUBLICATION {}{} {}", quote_identifier(&self.name), target, self.publish_option())
+    }
+}
+
+// one step from the current publication towards the desired one
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublicationChange {
+    Create(PublicationSpec),
+    Drop(String),
+    SetPublish { publication: String, publish: Vec<String> },
+    AddTable { publication: String, table: TableSpec },
+    DropTable { publication: String, table: String },
+    AddSchema { publication: String, schema: String },
+    DropSchema { publication: String, schema: String },
+}
+
+impl PublicationChange {
+    pub fn sql(&self) -> String {
+        match self {
+            PublicationChange::Create(spec) => spec.create_sql(),
+            PublicationChange::Drop(name) => format!("DROP PUBLICATION {}", quote_identifier(name)),
+            PublicationChange::SetPublish { publication, publish } =>
+                format!("ALTER PUBLICATION {} SET (publish = '{}')", quote_identifier(publication), publish.join(", ")),
+            PublicationChange::AddTable { publication, table } =>
+                format!("ALTER PUBLICATION {} ADD {}", quote_identifier(publication), table.sql()),
+            PublicationChange::DropTable { publication, table } =>
+                format!("ALTER PUBLICATION {} DROP TABLE {}", quote_identifier(publication), quote_table_name(table)),
+            PublicationChange::AddSchema { publication, schema } =>
+                format!("ALTER PUBLICATION {} ADD TABLES IN SCHEMA {}", quote_identifier(publication), quote_identifier(schema)),
+            PublicationChange::DropSchema { publication, schema } =>
+                format!("ALTER PUBLICATION {} DROP TABLES IN SCHEMA {}", quote_identifier(publication), quote_identifier(schema)),
+        }
+    }
+}
+
+impl fmt::Display for PublicationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicationChange::Create(spec) => write!(f, "+ publication {}", spec.name),
+            PublicationChange::Drop(name) => write!(f, "- publication {}", name),
+            PublicationChange::SetPublish { publication, publish } => write!(f, "~ {} publish = {}", publication, publish.join(", ")),
+            PublicationChange::AddTable { publication, table } => write!(f, "+ {} {}", publication, table.sql()),
+            PublicationChange::DropTable { publication, table } => write!(f, "- {} TABLE {}", publication, table),
+            PublicationChange::AddSchema { publication, schema } => write!(f, "+ {} TABLES IN SCHEMA {}", publication, schema),
+            PublicationChange::DropSchema { publication, schema } => write!(f, "- {} TABLES IN SCHEMA {}", publication, schema),
+        }
+    }
+}
+
+// Changes that turn current into desired. Switching between ALL TABLES and a table list cannot be
+// done with ALTER, the publication is recreated; a table whose columns or filter changed is dropped and re-added
+pub fn diff_publication(current: Option<&PublicationSpec>, desired: &PublicationSpec) -> Vec<PublicationChange> {
+    let current = match current {
+        Some(current) => current,
+        None => return vec![PublicationChange::Create(desired.clone())],
+    };
+    let name = desired.name.clone();
+    let mut changes = Vec::new();
+    match (&current.target, &desired.target) {
+        (PublicationTarget::AllTables, PublicationTarget::AllTables) => {},
+        (PublicationTarget::Objects { tables: current_tables, schemas: current_schemas },
+            PublicationTarget::Objects { tables: desired_tables, schemas: desired_schemas }) => {
+            let current_by_name: BTreeMap<&str, &TableSpec> = current_tables.iter().map(|t| (t.name.as_str(), t)).collect();
+            let desired_by_name: BTreeMap<&str, &TableSpec> = desired_tables.iter().map(|t| (t.name.as_str(), t)).collect();
+            for (table, spec) in &current_by_name {
+                if desired_by_name.get(table).is_none_or(|d| !d.same_as(spec)) {
+                    changes.push(PublicationChange::DropTable { publication: name.clone(), table: table.to_string() });
+                }
+            }
+            for (table, spec) in &desired_by_name {
+                if current_by_name.get(table).is_none_or(|c| !c.same_as(spec)) {
+                    changes.push(PublicationChange::AddTable { publication: name.clone(), table: (*spec).clone() });
+                }
+            }
+            for schema in current_schemas.iter().filter(|s| !desired_schemas.contains(s)) {
+                changes.push(PublicationChange::DropSchema { publication: name.clone(), schema: schema.clone() });
+            }
+            for schema in desired_schemas.iter().filter(|s| !current_schemas.contains(s)) {
+                changes.push(PublicationChange::AddSchema { publication: name.clone(), schema: schema.clone() });
+            }
+        },
+        _ => return vec![PublicationChange::Drop(name), PublicationChange::Create(desired.clone())],
+    }
+
+    let mut current_publish = current.publish.clone();
+    let mut desired_publish = desired.publish.clone();
+    current_publish.sort();
+    desired_publish.sort();
+    if current_publish != desired_publish {
+        changes.push(PublicationChange::SetPublish { publication: name, publish: desired.publish.clone() });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str, row_filter: Option<&str>) -> TableSpec {
+        TableSpec { row_filter: row_filter.map(str::to_owned), ..TableSpec::new(name) }
+    }
+
+    fn publication(tables: Vec<TableSpec>, publish: &[&str]) -> PublicationSpec {
+        PublicationSpec {
+            name: String::from("cdc"),
+            target: PublicationTarget::Objects { tables, schemas: Vec::new() },
+            publish: publish.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn creates_a_missing_publication() {
+        let desired = publication(vec![table("orders", None)], &PUBLISH_OPERATIONS);
+        let changes = diff_publication(None, &desired);
+        assert_eq!(changes, vec![PublicationChange::Create(desired)]);
+        assert_eq!(changes[0].sql(),
+                   "CREATE PUBLICATION \"cdc\" FOR TABLE \"public\".\"orders\" WITH (publish = 'insert, update, delete, truncate')");
+    }
+
+    #[test]
+    fn recreates_when_switching_to_all_tables() {
+        let current = publication(vec![table("orders", None)], &PUBLISH_OPERATIONS);
+        let desired = PublicationSpec { target: PublicationTarget::AllTables, ..current.clone() };
+        assert_eq!(diff_publication(Some(&current), &desired),
+                   vec![PublicationChange::Drop(String::from("cdc")), PublicationChange::Create(desired.clone())]);
+        assert!(diff_publication(Some(&desired), &desired).is_empty());
+    }
+
+    #[test]
+    fn adds_and_drops_tables_and_schemas() {
+        let current = publication(vec![table("orders", None), table("audit.log", None)], &PUBLISH_OPERATIONS);
+        let mut desired = publication(vec![table("public.orders", None), table("customers", None)], &PUBLISH_OPERATIONS);
+        if let PublicationTarget::Objects { schemas, .. } = &mut desired.target {
+            schemas.push(String::from("sales"));
+        }
+        let changes: Vec<String> = diff_publication(Some(&current), &desired).iter().map(PublicationChange::sql).collect();
+        assert_eq!(changes, [
+            "ALTER PUBLICATION \"cdc\" DROP TABLE \"audit\".\"log\"",
+            "ALTER PUBLICATION \"cdc\" ADD TABLE \"public\".\"customers\"",
+            "ALTER PUBLICATION \"cdc\" ADD TABLES IN SCHEMA \"sales\"",
+        ]);
+    }
+
+    #[test]
+    fn re_adds_a_table_whose_filter_changed() {
+        let current = publication(vec![table("orders", Some("(status = 'Active'::text)"))], &PUBLISH_OPERATIONS);
+        let unchanged = publication(vec![table("orders", Some("STATUS='Active'::text"))], &PUBLISH_OPERATIONS);
+        assert!(diff_publication(Some(&current), &unchanged).is_empty());
+
+        let changed = publication(vec![table("orders", Some("status = 'active'::text"))], &PUBLISH_OPERATIONS);
+        assert_eq!(diff_publication(Some(&current), &changed), vec![
+            PublicationChange::DropTable { publication: String::from("cdc"), table: String::from("public.orders") },
+            PublicationChange::AddTable { publication: String::from("cdc"), table: table("orders", Some("status = 'active'::text")) },
+        ]);
+    }
+
+    #[test]
+    fn changes_the_published_operations() {
+        let current = publication(Vec::new(), &["insert", "update"]);
+        assert!(diff_publication(Some(&current), &publication(Vec::new(), &["update", "insert"])).is_empty());
+        let changes = diff_publication(Some(&current), &publication(Vec::new(), &["insert"]));
+        assert_eq!(changes.iter().map(PublicationChange::sql).collect::<Vec<_>>(),
+                   ["ALTER PUBLICATION \"cdc\" SET (publish = 'insert')"]);
+    }
+
+    #[test]
+    fn normalizes_filters_outside_quotes_only() {
+        assert_eq!(normalize_filter("((active = true))"), "active=true");
+        assert_eq!(normalize_filter("(a > 1) AND (b < 2)"), "(a>1)and(b<2)");
+        assert_eq!(normalize_filter("name = 'a b'"), "name='a b'");
+        assert_ne!(normalize_filter("name = 'a b'"), normalize_filter("name = 'ab'"));
+        assert_eq!(normalize_filter("\"Mixed Case\" = 'X'"), "\"Mixed Case\"='X'");
+        assert_eq!(normalize_filter("(note = ')')"), "note=')'");
+        assert_eq!(normalize_filter("note = 'it''s'"), "note='it''s'");
+    }
+}
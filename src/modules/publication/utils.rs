@@ -0,0 +1,118 @@
+use crate::modules::db::{quote_identifier, quote_table_name, server_version_num};
+use crate::modules::publication::spec::{PublicationChange, PublicationSpec, PublicationTarget, TableSpec, PUBLISH_OPERATIONS};
+use postgres::Client;
+use std::error::Error;
+
+pub struct PublicationInfo {
+    pub name: String,
+    pub all_tables: bool,
+    pub tables: Vec<String>,
+}
+
+pub fn list_publications(client: &mut Client) -> Result<Vec<PublicationInfo>, Box<dyn Error>> {
+    let rows = client.query("SELECT p.pubname::text, p.puballtables, \
+        coalesce(array_agg(t.schemaname || '.' || t.tablename ORDER BY t.schemaname, t.tablename) \
+            FILTER (WHERE t.tablename IS NOT NULL), '{}') \
+        FROM pg_publication p LEFT JOIN pg_publication_tables t ON t.pubname = p.pubname \
+        GROUP BY p.pubname, p.puballtables ORDER BY p.pubname", &[])?;
+
+    Ok(rows.iter().map(|row| PublicationInfo {
+        name: row.get(0),
+        all_tables: row.get(1),
+        tables: row.get(2),
+    }).collect())
+}
+
+pub fn publication_exists(client: &mut Client, name: &str) -> Result<bool, Box<dyn Error>> {
+    let row = client.query_one("SELECT exists(SELECT 1 FROM pg_publication WHERE pubname = $1)", &[&name])?;
+
+    Ok(row.get(0))
+}
+
+// an empty table list creates a publication for all tables
+pub fn create_publication(client: &mut Client, name: &str, tables: &[String]) -> Result<(), Box<dyn Error>> {
+    let target = if tables.is_empty() {
+        String::from("FOR ALL TABLES")
+    } else {
+        let tables: Vec<String> = tables.iter().map(|t| quote_table_name(t)).collect();
+        format!("FOR TABLE {}", tables.join(", "))
+    };
+    client.batch_execute(&format!("CREATE PUBLICATION {} {}", quote_identifier(name), target))?;
+
+    Ok(())
+}
+
+pub fn drop_publication(client: &mut Client, name: &str) -> Result<(), Box<dyn Error>> {
+    client.batch_execute(&format!("DROP PUBLICATION {}", quote_identifier(name)))?;
+
+    Ok(())
+}
+
+// current membership from pg_publication_rel and pg_publication_namespace, None when it does not exist
+pub fn read_publication(client: &mut Client, name: &str) -> Result<Option<PublicationSpec>, Box<dyn Error>> {
+    let row = match client.query_opt("SELECT oid, puballtables, pubinsert, pubupdate, pubdelete, pubtruncate \
+        FROM pg_publication WHERE pubname = $1", &[&name])? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let oid: postgres::types::Oid = row.get(0);
+    let publish = PUBLISH_OPERATIONS.iter().enumerate()
+        .filter(|(i, _)| row.get::<_, bool>(i + 2))
+        .map(|(_, operation)| operation.to_string())
+        .collect();
+    if row.get::<_, bool>(1) {
+        return Ok(Some(PublicationSpec { name: name.to_owned(), target: PublicationTarget::AllTables, publish }));
+    }
+
+    let pg15 = server_version_num(client)? >= 150000;
+    let table_query = if pg15 {
+        "SELECT n.nspname || '.' || c.relname, \
+            (SELECT array_agg(a.attname::text ORDER BY a.attnum) FROM pg_attribute a \
+                WHERE a.attrelid = r.prrelid AND a.attnum = ANY(r.prattrs)), \
+            pg_get_expr(r.prqual, r.prrelid) \
+        FROM pg_publication_rel r JOIN pg_class c ON c.oid = r.prrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+        WHERE r.prpubid = $1 ORDER BY 1"
+    } else {
+        "SELECT n.nspname || '.' || c.relname, NULL::text[], NULL::text \
+        FROM pg_publication_rel r JOIN pg_class c ON c.oid = r.prrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+        WHERE r.prpubid = $1 ORDER BY 1"
+    };
+    let tables = client.query(table_query, &[&oid])?.iter().map(|row| TableSpec {
+        name: row.get(0),
+        columns: row.get(1),
+        row_filter: row.get(2),
+    }).collect();
+    let schemas = if pg15 {
+        client.query("SELECT n.nspname::text FROM pg_publication_namespace p JOIN pg_namespace n ON n.oid = p.pnnspid \
+            WHERE p.pnpubid = $1 ORDER BY 1", &[&oid])?.iter().map(|row| row.get(0)).collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Some(PublicationSpec { name: name.to_owned(), target: PublicationTarget::Objects { tables, schemas }, publish }))
+}
+
+// all changes in one transaction, the publication is never left half reconciled
+pub fn apply_publication_changes(client: &mut Client, changes: &[PublicationChange]) -> Result<(), Box<dyn Error>> {
+    let needs_pg15 = changes.iter().any(|change| match change {
+        PublicationChange::Create(spec) => spec.needs_pg15(),
+        PublicationChange::AddTable { table, .. } => table.columns.is_some() || table.row_filter.is_some(),
+        PublicationChange::AddSchema { .. } | PublicationChange::DropSchema { .. } => true,
+        _ => false,
+    });
+    if needs_pg15 && server_version_num(client)? < 150000 {
+        return Err("column lists, row filters and TABLES IN SCHEMA require PostgreSQL 15 or later".into());
+    }
+
+    let mut transaction = client.transaction()?;
+    for change in changes {
+        let sql = change.sql();
+        transaction.batch_execute(&sql).map_err(|e| match e.as_db_error() {
+            Some(db_error) => format!("{}: {}", sql, db_error.message()),
+            None => format!("{}: {}", sql, e),
+        })?;
+    }
+    transaction.commit()?;
+
+    Ok(())
+}
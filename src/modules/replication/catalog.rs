@@ -0,0 +1,44 @@
+use crate::dto::DBConfig;
+use crate::modules::db::connect_to;
+use crate::modules::replication::replication_error::ReplicationError;
+use postgres::Client;
+use tracing::debug;
+
+const PRIMARY_KEY_QUERY: &str = "SELECT a.attname FROM pg_catalog.pg_index i \
+                                 JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY (i.indkey) \
+                                 WHERE i.indrelid = $1 AND i.indisprimary";
+
+// Looks up what pgoutput does not send on a normal connection to the source, opened on first use
+pub struct Catalog {
+    source: DBConfig,
+    client: Option<Client>,
+}
+
+impl Catalog {
+    pub fn new(source: DBConfig) -> Catalog {
+        Catalog { source, client: None }
+    }
+
+    // names of the primary key columns of a relation, empty for a table without one
+    pub fn primary_key(&mut self, relation_id: u32) -> Result<Vec<String>, ReplicationError> {
+        if self.client.is_none() {
+            let client = connect_to(&self.source)
+                .map_err(|e| ReplicationError::ConnectionLost(format!("could not connect to look up primary keys: {}", e)))?;
+            self.client = Some(client);
+        }
+        let rows = match self.client.as_mut().unwrap().query(PRIMARY_KEY_QUERY, &[&relation_id]) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.client = None;
+                return Err(match e.code() {
+                    Some(code) => ReplicationError::ServerError { code: code.code().to_owned(), message: e.to_string() },
+                    None => ReplicationError::ConnectionLost(format!("could not look up primary keys: {}", e)),
+                });
+            },
+        };
+        let columns: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        debug!(relation_id, primary_key = ?columns, "Looked up primary key");
+
+        Ok(columns)
+    }
+}
@@ -1,17 +1,59 @@
-pub fn start_replication_command(slot_name: &str, replication_type: &str,
+use crate::modules::replication::lsn::Lsn;
+use tracing::debug;
+
+pub fn start_replication_command(slot_name: &str, replication_type: &str, start_lsn: Lsn,
                                  proto_version: &str, publication_names: &[&str]) -> Vec<u8> {
+    let payload = format!("START_REPLICATION SLOT {} {} {} (proto_version '{}', \
+        publication_names '{}')", slot_name, replication_type, start_lsn, proto_version, publication_names.join(","));
+    debug!(command = %payload, "Starting replication");
+
+    query_message(&payload)
+}
+
+pub fn query_message(query: &str) -> Vec<u8> {
     let mut command: Vec<u8> = Vec::new();
-    command.push('Q' as u8);
-    let payload = format!("START_REPLICATION SLOT {} {} 0/0 (proto_version '{}', \
-        publication_names '{}')", slot_name, replication_type, proto_version, publication_names.join(","));
-    println!("Payload: {}", payload);
-    let payload_bytes = payload.as_bytes();
-    //command.push("SLOT" as u8);
+    command.push(b'Q');
+    let payload_bytes = query.as_bytes();
     let length: i32 = (4 + payload_bytes.len() + 1) as i32;
-    let length_bytes = length.to_be_bytes();
-    command.extend_from_slice(&length_bytes);
-    command.extend_from_slice(&payload_bytes);
+    command.extend_from_slice(&length.to_be_bytes());
+    command.extend_from_slice(payload_bytes);
     command.push(0);
 
     command
-}
\ No newline at end of file
+}
+
+// Standby Status Update, sent inside a CopyData message
+pub fn standby_status_update(written: Lsn, flushed: Lsn, applied: Lsn, client_time: i64, reply_requested: bool) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(34);
+    payload.push(b'r');
+    payload.extend_from_slice(&written.to_be_bytes());
+    payload.extend_from_slice(&flushed.to_be_bytes());
+    payload.extend_from_slice(&applied.to_be_bytes());
+    payload.extend_from_slice(&client_time.to_be_bytes());
+    payload.push(reply_requested as u8);
+
+    copy_data_message(&payload)
+}
+
+pub fn copy_done_message() -> Vec<u8> {
+    let mut message = vec![b'c'];
+    message.extend_from_slice(&4i32.to_be_bytes());
+
+    message
+}
+
+pub fn terminate_message() -> Vec<u8> {
+    let mut message = vec![b'X'];
+    message.extend_from_slice(&4i32.to_be_bytes());
+
+    message
+}
+
+fn copy_data_message(payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1 + 4 + payload.len());
+    message.push(b'd');
+    message.extend_from_slice(&((4 + payload.len()) as i32).to_be_bytes());
+    message.extend_from_slice(payload);
+
+    message
+}
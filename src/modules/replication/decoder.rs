@@ -0,0 +1,136 @@
+use crate::modules::replication::catalog::Catalog;
+use crate::modules::replication::dto::{ChangeEvent, ColumnValue, Operation, Relation, Transaction};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::pgoutput::{parse_pgoutput_message, PgOutputMessage};
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::replication_error::ReplicationError::ProtocolViolation;
+use crate::modules::db::quote_identifier;
+use crate::modules::logging::PROTOCOL_TARGET;
+use std::collections::HashMap;
+use tracing::trace;
+
+// Relation messages describe a table once per session (and again after a schema change),
+// row messages only carry the relation id
+#[derive(Default)]
+pub struct RelationCache {
+    relations: HashMap<u32, Relation>,
+}
+
+impl RelationCache {
+    pub fn get(&self, id: u32) -> Option<&Relation> {
+        self.relations.get(&id)
+    }
+
+    // returns the previous definition when the relation was already known
+    pub fn insert(&mut self, relation: Relation) -> Option<Relation> {
+        self.relations.insert(relation.id, relation)
+    }
+}
+
+struct OpenTransaction {
+    xid: u32,
+    events: Vec<ChangeEvent>,
+}
+
+// Turns pgoutput messages into transactions of change events
+#[derive(Default)]
+pub struct Decoder {
+    relations: RelationCache,
+    // names of the types of Type messages, by oid
+    types: HashMap<u32, String>,
+    // primary keys come from here, without it only a default replica identity is taken for one
+    catalog: Option<Catalog>,
+    current: Option<OpenTransaction>,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    pub fn with_catalog(mut self, catalog: Catalog) -> Decoder {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    // between a Begin and its Commit
+    pub fn in_transaction(&self) -> bool {
+        self.current.is_some()
+    }
+
+    // returns the transaction once its commit message was decoded
+    pub fn decode(&mut self, lsn: Lsn, data: &[u8]) -> Result<Option<Transaction>, ReplicationError> {
+        let message = parse_pgoutput_message(data)?;
+        trace!(target: PROTOCOL_TARGET, %lsn, ?message, "pgoutput message");
+        match message {
+            PgOutputMessage::Begin { xid } => {
+                self.current = Some(OpenTransaction { xid, events: Vec::new() });
+            },
+            PgOutputMessage::Commit { commit_lsn, end_lsn, commit_time } => {
+                let transaction = self.current.take()
+                    .ok_or_else(|| ProtocolViolation(format!("commit at {} without begin", commit_lsn)))?;
+                return Ok(Some(Transaction {
+                    xid: transaction.xid,
+                    commit_lsn,
+                    end_lsn,
+                    commit_time,
+                    events: transaction.events,
+                }));
+            },
+            PgOutputMessage::Relation(mut relation) => {
+                let primary_key = match &mut self.catalog {
+                    Some(catalog) => Some(catalog.primary_key(relation.id)?),
+                    None => None,
+                };
+                for column in &mut relation.columns {
+                    column.type_name = self.types.get(&column.type_oid).cloned();
+                    column.primary_key = match &primary_key {
+                        Some(primary_key) => primary_key.contains(&column.name),
+                        None => relation.replica_identity == b'd' && column.key,
+                    };
+                }
+                self.relations.insert(relation);
+            },
+            PgOutputMessage::Type { oid, namespace, name } => {
+                self.types.insert(oid, format!("{}.{}", quote_identifier(&namespace), quote_identifier(&name)));
+            },
+            PgOutputMessage::Insert { relation_id, new } => {
+                self.push_event(lsn, relation_id, Operation::Insert, None, Some(new))?;
+            },
+            PgOutputMessage::Update { relation_id, old, new } => {
+                self.push_event(lsn, relation_id, Operation::Update, old, Some(new))?;
+            },
+            PgOutputMessage::Delete { relation_id, old } => {
+                self.push_event(lsn, relation_id, Operation::Delete, Some(old), None)?;
+            },
+            PgOutputMessage::Truncate { relation_ids } => {
+                for relation_id in relation_ids {
+                    self.push_event(lsn, relation_id, Operation::Truncate, None, None)?;
+                }
+            },
+            PgOutputMessage::Other => {},
+        }
+
+        Ok(None)
+    }
+
+    fn push_event(&mut self, lsn: Lsn, relation_id: u32, operation: Operation,
+                  old: Option<Vec<ColumnValue>>, new: Option<Vec<ColumnValue>>) -> Result<(), ReplicationError> {
+        let relation = self.relations.get(relation_id)
+            .ok_or_else(|| ProtocolViolation(format!("change for unknown relation {}", relation_id)))?;
+        let transaction = self.current.as_mut()
+            .ok_or_else(|| ProtocolViolation(format!("{} on {}.{} outside of a transaction", operation, relation.namespace, relation.name)))?;
+        transaction.events.push(ChangeEvent {
+            lsn,
+            xid: transaction.xid,
+            schema: relation.namespace.clone(),
+            table: relation.name.clone(),
+            operation,
+            columns: relation.columns.clone(),
+            old,
+            new,
+        });
+
+        Ok(())
+    }
+}
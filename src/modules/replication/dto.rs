@@ -0,0 +1,101 @@
+use crate::modules::replication::lsn::Lsn;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Column {
+    pub name: String,
+    pub type_oid: u32,
+    pub type_modifier: i32,
+    // part of the replica identity
+    pub key: bool,
+    // part of the primary key, looked up in the catalog since pgoutput does not send it
+    pub primary_key: bool,
+    // quoted "schema.name" of a type that is not built in, whose oid means nothing on another server
+    pub type_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub id: u32,
+    pub namespace: String,
+    pub name: String,
+    pub replica_identity: u8,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValue {
+    Null,
+    // TOASTed value that did not change, the server does not send it
+    UnchangedToast,
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+    // row of an initial snapshot
+    Read,
+    Truncate,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Insert => write!(f, "INSERT"),
+            Operation::Update => write!(f, "UPDATE"),
+            Operation::Delete => write!(f, "DELETE"),
+            Operation::Read => write!(f, "READ"),
+            Operation::Truncate => write!(f, "TRUNCATE"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    // start of the WAL record that carried the change
+    pub lsn: Lsn,
+    pub xid: u32,
+    pub schema: String,
+    pub table: String,
+    pub operation: Operation,
+    pub columns: Vec<Column>,
+    pub old: Option<Vec<ColumnValue>>,
+    pub new: Option<Vec<ColumnValue>>,
+}
+
+impl fmt::Display for ChangeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}.{} lsn={} xid={}", self.operation, self.schema, self.table, self.lsn, self.xid)?;
+        for (label, values) in [("old", &self.old), ("new", &self.new)] {
+            if let Some(values) = values {
+                write!(f, " {}:", label)?;
+                for (column, value) in self.columns.iter().zip(values) {
+                    match value {
+                        ColumnValue::Null => write!(f, " {}=null", column.name)?,
+                        ColumnValue::UnchangedToast => write!(f, " {}=<unchanged>", column.name)?,
+                        ColumnValue::Text(text) => write!(f, " {}={}", column.name, text)?,
+                        ColumnValue::Binary(bytes) => write!(f, " {}=\\x{}", column.name, hex::encode(bytes))?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// decoded changes of one committed source transaction
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub xid: u32,
+    // LSN of the commit record
+    pub commit_lsn: Lsn,
+    // end of the commit record, what gets acknowledged once the transaction is delivered
+    pub end_lsn: Lsn,
+    pub commit_time: i64,
+    pub events: Vec<ChangeEvent>,
+}
@@ -0,0 +1,82 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+// WAL position, textual form is "XXXXXXXX/XXXXXXXX" (high/low 32 bits in hex)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Lsn(pub u64);
+
+impl Lsn {
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Lsn {
+        Lsn(u64::from_be_bytes(bytes))
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xFFFF_FFFF)
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (high, low) = s.split_once('/')
+            .ok_or_else(|| format!("invalid LSN '{}', expected format X/X", s))?;
+        let high = u32::from_str_radix(high.trim(), 16)
+            .map_err(|e| format!("invalid LSN '{}': {}", s, e))?;
+        let low = u32::from_str_radix(low.trim(), 16)
+            .map_err(|e| format!("invalid LSN '{}': {}", s, e))?;
+
+        Ok(Lsn(((high as u64) << 32) | low as u64))
+    }
+}
+
+// serialized in its textual form, the number is meaningless to readers of JSON
+impl Serialize for Lsn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_pg_lsn() {
+        assert_eq!(Lsn(0).to_string(), "0/0");
+        assert_eq!(Lsn(0x16B3748).to_string(), "0/16B3748");
+        assert_eq!(Lsn(0x1_0000_0000).to_string(), "1/0");
+        assert_eq!(Lsn(u64::MAX).to_string(), "FFFFFFFF/FFFFFFFF");
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        for lsn in [0, 0x16B3748, 0x2A_0000_00FF, u64::MAX] {
+            assert_eq!(Lsn(lsn).to_string().parse::<Lsn>(), Ok(Lsn(lsn)));
+        }
+        assert_eq!("0/16b3748".parse::<Lsn>(), Ok(Lsn(0x16B3748)));
+        assert_eq!("1 / 2".parse::<Lsn>(), Ok(Lsn(0x1_0000_0002)));
+    }
+
+    #[test]
+    fn rejects_malformed_positions() {
+        for lsn in ["", "16B3748", "0/", "/0", "G/0", "0/1/2", "100000000/0", "-1/0"] {
+            assert!(lsn.parse::<Lsn>().is_err(), "{}", lsn);
+        }
+    }
+
+    #[test]
+    fn converts_to_and_from_wire_bytes() {
+        let lsn = Lsn(0x0102_0304_0506_0708);
+        assert_eq!(lsn.to_be_bytes(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(Lsn::from_be_bytes(lsn.to_be_bytes()), lsn);
+        assert_eq!(serde_json::to_string(&lsn).unwrap(), "\"1020304/5060708\"");
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::{ErrorKind, Read};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::replication_error::ReplicationError::{ConnectionLost, ProtocolViolation};
+
+// seconds between 1970-01-01 and 2000-01-01, the epoch used by the replication protocol
+const POSTGRES_EPOCH_OFFSET_SECS: u64 = 946_684_800;
+
+// server_time is the server's clock when the message was sent, microseconds since 2000-01-01
+pub enum CopyDataMessage {
+    XLogData { start: Lsn, end: Lsn, server_time: i64, data: Vec<u8> },
+    PrimaryKeepalive { wal_end: Lsn, server_time: i64, reply_requested: bool },
+}
+
+// Buffers bytes read from the socket so that a read timeout never splits a message
+pub struct MessageReader {
+    buffer: Vec<u8>,
+}
+
+impl MessageReader {
+    pub fn new() -> MessageReader {
+        MessageReader { buffer: Vec::new() }
+    }
+
+    // Ok(None) means the read timed out before a full message arrived
+    pub fn next_message(&mut self, stream: &mut TcpStream) -> Result<Option<Vec<u8>>, ReplicationError> {
+        let mut chunk = [0u8; 8192];
+        loop {
+            if let Some(message) = self.take_message() {
+                return Ok(Some(message));
+            }
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(ConnectionLost(String::from("server closed the connection"))),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn take_message(&mut self) -> Option<Vec<u8>> {
+        if self.buffer.len() < 5 {
+            return None;
+        }
+        let length = u32::from_be_bytes([self.buffer[1], self.buffer[2], self.buffer[3], self.buffer[4]]) as usize;
+        if self.buffer.len() < 1 + length {
+            return None;
+        }
+
+        Some(self.buffer.drain(..1 + length).collect())
+    }
+}
+
+impl Default for MessageReader {
+    fn default() -> Self {
+        MessageReader::new()
+    }
+}
+
+// payload is the CopyData body, without the 'd' type byte and length
+pub fn parse_copy_data(payload: &[u8]) -> Result<CopyDataMessage, ReplicationError> {
+    match payload.first() {
+        Some(b'w') if payload.len() >= 25 => Ok(CopyDataMessage::XLogData {
+            start: Lsn::from_be_bytes(payload[1..9].try_into().unwrap()),
+            end: Lsn::from_be_bytes(payload[9..17].try_into().unwrap()),
+            server_time: i64::from_be_bytes(payload[17..25].try_into().unwrap()),
+            data: payload[25..].to_vec(),
+        }),
+        Some(b'k') if payload.len() >= 18 => Ok(CopyDataMessage::PrimaryKeepalive {
+            wal_end: Lsn::from_be_bytes(payload[1..9].try_into().unwrap()),
+            server_time: i64::from_be_bytes(payload[9..17].try_into().unwrap()),
+            reply_requested: payload[17] == 1,
+        }),
+        Some(t) => Err(ProtocolViolation(format!("unexpected CopyData message '{}' ({} bytes)", *t as char, payload.len()))),
+        None => Err(ProtocolViolation(String::from("empty CopyData message"))),
+    }
+}
+
+// microseconds since 2000-01-01, as expected in standby status updates
+pub fn postgres_epoch_micros(time: SystemTime) -> i64 {
+    let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    since_unix.as_micros() as i64 - (POSTGRES_EPOCH_OFFSET_SECS as i64 * 1_000_000)
+}
@@ -1,2 +1,11 @@
 pub mod utils;
-mod command_utils;
\ No newline at end of file
+pub mod catalog;
+pub mod command_utils;
+pub mod decoder;
+pub mod dto;
+pub mod lsn;
+pub mod message_utils;
+pub mod offset_store;
+pub mod pgoutput;
+pub mod replication_error;
+pub mod supervisor;
\ No newline at end of file
@@ -0,0 +1,46 @@
+use crate::modules::replication::lsn::Lsn;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+// Flushed LSN of a pipeline kept in a file, written before the server is told about it
+// so the slot can never be ahead of what we know was delivered
+pub struct OffsetStore {
+    path: PathBuf,
+    stored: Option<Lsn>,
+}
+
+impl OffsetStore {
+    pub fn new(path: PathBuf) -> OffsetStore {
+        OffsetStore { path, stored: None }
+    }
+
+    pub fn load(&mut self) -> io::Result<Option<Lsn>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let lsn = content.trim().parse::<Lsn>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", self.path.display(), e)))?;
+        self.stored = Some(lsn);
+
+        Ok(Some(lsn))
+    }
+
+    // write to a temporary file, fsync and rename, a crash leaves either the old or the new offset
+    pub fn save(&mut self, lsn: Lsn) -> io::Result<()> {
+        if self.stored == Some(lsn) {
+            return Ok(());
+        }
+        let temporary = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary)?;
+        writeln!(file, "{}", lsn)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        self.stored = Some(lsn);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+use crate::modules::replication::dto::{Column, ColumnValue, Relation};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::replication_error::ReplicationError::ProtocolViolation;
+
+// logical replication messages of the pgoutput plugin, protocol version 1
+#[derive(Debug)]
+pub enum PgOutputMessage {
+    Begin { xid: u32 },
+    Commit { commit_lsn: Lsn, end_lsn: Lsn, commit_time: i64 },
+    Relation(Relation),
+    Insert { relation_id: u32, new: Vec<ColumnValue> },
+    Update { relation_id: u32, old: Option<Vec<ColumnValue>>, new: Vec<ColumnValue> },
+    Delete { relation_id: u32, old: Vec<ColumnValue> },
+    Truncate { relation_ids: Vec<u32> },
+    // sent before the first Relation using a type that is not built in
+    Type { oid: u32, namespace: String, name: String },
+    // Origin and logical decoding messages
+    Other,
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ReplicationError> {
+        if self.pos + n > self.data.len() {
+            return Err(ProtocolViolation(format!("pgoutput message truncated at byte {}", self.pos)));
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplicationError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReplicationError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplicationError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, ReplicationError> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, ReplicationError> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn lsn(&mut self) -> Result<Lsn, ReplicationError> {
+        Ok(Lsn::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn cstring(&mut self) -> Result<String, ReplicationError> {
+        let end = self.data[self.pos..].iter().position(|&b| b == 0)
+            .ok_or_else(|| ProtocolViolation(String::from("unterminated string in pgoutput message")))?;
+        let value = String::from_utf8_lossy(&self.data[self.pos..self.pos + end]).to_string();
+        self.pos += end + 1;
+
+        Ok(value)
+    }
+}
+
+pub fn parse_pgoutput_message(data: &[u8]) -> Result<PgOutputMessage, ReplicationError> {
+    let mut cursor = Cursor { data, pos: 0 };
+    let message_type = cursor.u8()?;
+    match message_type {
+        b'B' => {
+            // final LSN and commit time are repeated in the commit message
+            let _final_lsn = cursor.lsn()?;
+            let _commit_time = cursor.i64()?;
+            Ok(PgOutputMessage::Begin { xid: cursor.u32()? })
+        },
+        b'C' => {
+            let _flags = cursor.u8()?;
+            Ok(PgOutputMessage::Commit {
+                commit_lsn: cursor.lsn()?,
+                end_lsn: cursor.lsn()?,
+                commit_time: cursor.i64()?,
+            })
+        },
+        b'R' => {
+            let id = cursor.u32()?;
+            let namespace = cursor.cstring()?;
+            let name = cursor.cstring()?;
+            let replica_identity = cursor.u8()?;
+            let column_count = cursor.u16()?;
+            let mut columns = Vec::with_capacity(column_count as usize);
+            for _ in 0..column_count {
+                let flags = cursor.u8()?;
+                columns.push(Column {
+                    name: cursor.cstring()?,
+                    type_oid: cursor.u32()?,
+                    type_modifier: cursor.i32()?,
+                    key: flags & 1 == 1,
+                    // not sent, the decoder fills it in
+                    primary_key: false,
+                    type_name: None,
+                });
+            }
+            // an empty namespace means pg_catalog
+            let namespace = if namespace.is_empty() { String::from("pg_catalog") } else { namespace };
+            Ok(PgOutputMessage::Relation(Relation { id, namespace, name, replica_identity, columns }))
+        },
+        b'I' => {
+            let relation_id = cursor.u32()?;
+            expect_tuple_marker(&mut cursor, b'N')?;
+            Ok(PgOutputMessage::Insert { relation_id, new: read_tuple(&mut cursor)? })
+        },
+        b'U' => {
+            let relation_id = cursor.u32()?;
+            let mut old = None;
+            let mut marker = cursor.u8()?;
+            if marker == b'K' || marker == b'O' {
+                old = Some(read_tuple(&mut cursor)?);
+                marker = cursor.u8()?;
+            }
+            if marker != b'N' {
+                return Err(ProtocolViolation(format!("unexpected tuple marker '{}' in update", marker as char)));
+            }
+            Ok(PgOutputMessage::Update { relation_id, old, new: read_tuple(&mut cursor)? })
+        },
+        b'D' => {
+            let relation_id = cursor.u32()?;
+            let marker = cursor.u8()?;
+            if marker != b'K' && marker != b'O' {
+                return Err(ProtocolViolation(format!("unexpected tuple marker '{}' in delete", marker as char)));
+            }
+            Ok(PgOutputMessage::Delete { relation_id, old: read_tuple(&mut cursor)? })
+        },
+        b'T' => {
+            let relation_count = cursor.u32()?;
+            let _options = cursor.u8()?;
+            let mut relation_ids = Vec::with_capacity(relation_count as usize);
+            for _ in 0..relation_count {
+                relation_ids.push(cursor.u32()?);
+            }
+            Ok(PgOutputMessage::Truncate { relation_ids })
+        },
+        b'Y' => {
+            let oid = cursor.u32()?;
+            let namespace = cursor.cstring()?;
+            let namespace = if namespace.is_empty() { String::from("pg_catalog") } else { namespace };
+            Ok(PgOutputMessage::Type { oid, namespace, name: cursor.cstring()? })
+        },
+        _ => Ok(PgOutputMessage::Other),
+    }
+}
+
+fn expect_tuple_marker(cursor: &mut Cursor, expected: u8) -> Result<(), ReplicationError> {
+    let marker = cursor.u8()?;
+    if marker != expected {
+        return Err(ProtocolViolation(format!("expected tuple marker '{}', got '{}'", expected as char, marker as char)));
+    }
+
+    Ok(())
+}
+
+fn read_tuple(cursor: &mut Cursor) -> Result<Vec<ColumnValue>, ReplicationError> {
+    let column_count = cursor.u16()?;
+    let mut values = Vec::with_capacity(column_count as usize);
+    for _ in 0..column_count {
+        let value = match cursor.u8()? {
+            b'n' => ColumnValue::Null,
+            b'u' => ColumnValue::UnchangedToast,
+            b't' => {
+                let length = cursor.u32()? as usize;
+                ColumnValue::Text(String::from_utf8_lossy(cursor.take(length)?).to_string())
+            },
+            b'b' => {
+                let length = cursor.u32()? as usize;
+                ColumnValue::Binary(cursor.take(length)?.to_vec())
+            },
+            other => return Err(ProtocolViolation(format!("unknown tuple value kind '{}'", other as char))),
+        };
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // builds a message like the server sends it
+    #[derive(Default)]
+    struct Message(Vec<u8>);
+
+    impl Message {
+        fn new(kind: u8) -> Message {
+            Message(vec![kind])
+        }
+
+        fn u8(mut self, value: u8) -> Message {
+            self.0.push(value);
+            self
+        }
+
+        fn u16(mut self, value: u16) -> Message {
+            self.0.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn u32(mut self, value: u32) -> Message {
+            self.0.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn u64(mut self, value: u64) -> Message {
+            self.0.extend_from_slice(&value.to_be_bytes());
+            self
+        }
+
+        fn cstring(mut self, value: &str) -> Message {
+            self.0.extend_from_slice(value.as_bytes());
+            self.0.push(0);
+            self
+        }
+
+        fn text(self, value: &str) -> Message {
+            let mut message = self.u8(b't').u32(value.len() as u32);
+            message.0.extend_from_slice(value.as_bytes());
+            message
+        }
+    }
+
+    fn text(value: &str) -> ColumnValue {
+        ColumnValue::Text(value.to_owned())
+    }
+
+    #[test]
+    fn parses_begin_and_commit() {
+        let begin = Message::new(b'B').u64(0x20).u64(1_000).u32(42);
+        assert!(matches!(parse_pgoutput_message(&begin.0), Ok(PgOutputMessage::Begin { xid: 42 })));
+
+        let commit = Message::new(b'C').u8(0).u64(0x20).u64(0x48).u64(1_000);
+        match parse_pgoutput_message(&commit.0).unwrap() {
+            PgOutputMessage::Commit { commit_lsn, end_lsn, commit_time } => {
+                assert_eq!((commit_lsn, end_lsn, commit_time), (Lsn(0x20), Lsn(0x48), 1_000));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_relations() {
+        let relation = Message::new(b'R').u32(16_390).cstring("public").cstring("orders").u8(b'd').u16(2)
+            .u8(1).cstring("id").u32(23).u32(u32::MAX)
+            .u8(0).cstring("note").u32(1043).u32(36);
+        let PgOutputMessage::Relation(relation) = parse_pgoutput_message(&relation.0).unwrap() else { panic!("not a relation") };
+        assert_eq!((relation.id, relation.namespace.as_str(), relation.name.as_str(), relation.replica_identity),
+                   (16_390, "public", "orders", b'd'));
+        assert_eq!(relation.columns, vec![
+            Column { name: String::from("id"), type_oid: 23, type_modifier: -1, key: true, primary_key: false, type_name: None },
+            Column { name: String::from("note"), type_oid: 1043, type_modifier: 36, key: false, primary_key: false, type_name: None },
+        ]);
+
+        // the key of REPLICA IDENTITY FULL is every column
+        let full = Message::new(b'R').u32(1).cstring("").cstring("t").u8(b'f').u16(1).u8(1).cstring("id").u32(23).u32(u32::MAX);
+        let PgOutputMessage::Relation(full) = parse_pgoutput_message(&full.0).unwrap() else { panic!("not a relation") };
+        assert_eq!(full.namespace, "pg_catalog");
+        assert!(full.columns[0].key);
+    }
+
+    #[test]
+    fn parses_row_changes() {
+        let insert = Message::new(b'I').u32(7).u8(b'N').u16(3).text("1").u8(b'n').u8(b'u');
+        match parse_pgoutput_message(&insert.0).unwrap() {
+            PgOutputMessage::Insert { relation_id: 7, new } => assert_eq!(new, vec![text("1"), ColumnValue::Null, ColumnValue::UnchangedToast]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let update = Message::new(b'U').u32(7).u8(b'N').u16(1).text("2");
+        match parse_pgoutput_message(&update.0).unwrap() {
+            PgOutputMessage::Update { relation_id: 7, old: None, new } => assert_eq!(new, vec![text("2")]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let key_changed = Message::new(b'U').u32(7).u8(b'K').u16(1).text("1").u8(b'N').u16(1).text("2");
+        match parse_pgoutput_message(&key_changed.0).unwrap() {
+            PgOutputMessage::Update { old: Some(old), new, .. } => assert_eq!((old, new), (vec![text("1")], vec![text("2")])),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let delete = Message::new(b'D').u32(7).u8(b'O').u16(1).text("2");
+        match parse_pgoutput_message(&delete.0).unwrap() {
+            PgOutputMessage::Delete { relation_id: 7, old } => assert_eq!(old, vec![text("2")]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut binary = Message::new(b'I').u32(7).u8(b'N').u16(1).u8(b'b').u32(2);
+        binary.0.extend_from_slice(&[0xde, 0xad]);
+        match parse_pgoutput_message(&binary.0).unwrap() {
+            PgOutputMessage::Insert { new, .. } => assert_eq!(new, vec![ColumnValue::Binary(vec![0xde, 0xad])]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_truncate_and_type() {
+        let truncate = Message::new(b'T').u32(2).u8(1).u32(7).u32(8);
+        match parse_pgoutput_message(&truncate.0).unwrap() {
+            PgOutputMessage::Truncate { relation_ids } => assert_eq!(relation_ids, vec![7, 8]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let kind = Message::new(b'Y').u32(16_400).cstring("public").cstring("mood");
+        match parse_pgoutput_message(&kind.0).unwrap() {
+            PgOutputMessage::Type { oid, namespace, name } => assert_eq!((oid, namespace.as_str(), name.as_str()), (16_400, "public", "mood")),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let origin = Message::new(b'O').u64(0x20).cstring("node");
+        assert!(matches!(parse_pgoutput_message(&origin.0), Ok(PgOutputMessage::Other)));
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let truncated = Message::new(b'I').u32(7).u8(b'N').u16(1).u8(b't').u32(10).cstring("short");
+        let unterminated = Message(b"Y\0\0\0\x01public".to_vec());
+        let wrong_marker = Message::new(b'I').u32(7).u8(b'K').u16(0);
+        let wrong_delete = Message::new(b'D').u32(7).u8(b'N').u16(0);
+        let unknown_kind = Message::new(b'I').u32(7).u8(b'N').u16(1).u8(b'x');
+        for message in [truncated, unterminated, wrong_marker, wrong_delete, unknown_kind, Message::default()] {
+            assert!(matches!(parse_pgoutput_message(&message.0), Err(ProtocolViolation(_))), "{:?}", message.0);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+use std::fmt::Formatter;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::sasl::authentication_error::AuthenticationError;
+use crate::modules::sink::sink_error::SinkError;
+
+#[derive(Debug)]
+pub enum ReplicationError {
+    ConnectionLost(String),
+    Authentication(AuthenticationError),
+    SlotNotFound(String),
+    ServerError { code: String, message: String },
+    ProtocolViolation(String),
+    // the slot confirmed more than the persisted offset, the changes in between would never be delivered
+    SlotAheadOfOffset { slot: String, confirmed_flush: Lsn, offset: Lsn },
+    OffsetPersistence(String),
+    Sink(SinkError),
+}
+
+impl ReplicationError {
+    // retryable errors are worth a reconnect, fatal ones need an operator
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ReplicationError::ConnectionLost(_) => true,
+            ReplicationError::Authentication(AuthenticationError::ConnectionFailed(_)) => true,
+            ReplicationError::Authentication(_) => false,
+            ReplicationError::SlotNotFound(_) => false,
+            ReplicationError::ServerError { code, .. } => is_retryable_sqlstate(code),
+            ReplicationError::ProtocolViolation(_) => false,
+            ReplicationError::SlotAheadOfOffset { .. } => false,
+            ReplicationError::OffsetPersistence(_) => false,
+            ReplicationError::Sink(e) => e.is_retryable(),
+        }
+    }
+}
+
+pub fn is_retryable_sqlstate(code: &str) -> bool {
+    // 08: connection exception, 53: insufficient resources, 57: operator intervention (shutdown, crash),
+    // 55006: slot is active for another process, 40001/40P01: serialization failure/deadlock
+    code.starts_with("08") || code.starts_with("53") || code.starts_with("57")
+        || code == "55006" || code == "40001" || code == "40P01"
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationError::ConnectionLost(msg) => write!(f, "Connection lost: {}", msg),
+            ReplicationError::Authentication(e) => write!(f, "Authentication failed: {}", e),
+            ReplicationError::SlotNotFound(msg) => write!(f, "Replication slot not found: {}", msg),
+            ReplicationError::ServerError { code, message } => write!(f, "Server error {}: {}", code, message),
+            ReplicationError::ProtocolViolation(msg) => write!(f, "Protocol violation: {}", msg),
+            ReplicationError::SlotAheadOfOffset { slot, confirmed_flush, offset } => write!(f,
+                "Replication slot {} confirmed up to {} but the persisted offset is {}, changes in between would be lost",
+                slot, confirmed_flush, offset),
+            ReplicationError::OffsetPersistence(msg) => write!(f, "Could not persist the offset: {}", msg),
+            ReplicationError::Sink(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplicationError {}
+
+impl From<AuthenticationError> for ReplicationError {
+    fn from(e: AuthenticationError) -> Self {
+        ReplicationError::Authentication(e)
+    }
+}
+
+impl From<SinkError> for ReplicationError {
+    fn from(e: SinkError) -> Self {
+        ReplicationError::Sink(e)
+    }
+}
+
+impl From<std::io::Error> for ReplicationError {
+    fn from(e: std::io::Error) -> Self {
+        ReplicationError::ConnectionLost(e.to_string())
+    }
+}
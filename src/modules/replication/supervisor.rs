@@ -0,0 +1,257 @@
+use crate::dto::DBConfig;
+use crate::modules::metrics::registry::PipelineMetrics;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::offset_store::OffsetStore;
+use crate::modules::replication::utils::{identify_system, slot_position, start_replication, stream_changes, ReplicationProgress};
+use crate::modules::sasl::authentication_error::AuthenticationError;
+use crate::modules::sasl::utils::sasl_authentication;
+use crate::modules::shutdown::ShutdownSignal;
+use crate::modules::sink::stdout::StdoutSink;
+use crate::modules::sink::traits::Sink;
+use crate::modules::sink::utils::SinkDriver;
+use rand::Rng;
+use std::fmt;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, info_span};
+
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    Connecting { attempt: u32 },
+    Authenticated { server: String },
+    Streaming { start_lsn: Lsn },
+    // the server is on a new timeline since the previous session, a standby was promoted
+    TimelineSwitched { from: u32, to: u32 },
+    Disconnected { error: String, retryable: bool },
+    Reconnecting { attempt: u32, delay: Duration },
+    Failed { error: String },
+    Stopped { flushed_lsn: Lsn },
+}
+
+impl fmt::Display for ConnectionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionEvent::Connecting { attempt } => write!(f, "connecting (attempt {})", attempt),
+            ConnectionEvent::Authenticated { server } => write!(f, "authenticated at {}", server),
+            ConnectionEvent::Streaming { start_lsn } => write!(f, "streaming from {}", start_lsn),
+            ConnectionEvent::TimelineSwitched { from, to } => write!(f, "timeline switched from {} to {}", from, to),
+            ConnectionEvent::Disconnected { error, retryable } => write!(f, "disconnected ({}): {}",
+                                                                       if *retryable { "retryable" } else { "fatal" }, error),
+            ConnectionEvent::Reconnecting { attempt, delay } => write!(f, "reconnecting in {:?} (attempt {})", delay, attempt),
+            ConnectionEvent::Failed { error } => write!(f, "failed: {}", error),
+            ConnectionEvent::Stopped { flushed_lsn } => write!(f, "stopped at flushed LSN {}", flushed_lsn),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+}
+
+impl Backoff {
+    // exponential delay for the attempt, with "equal jitter": half fixed, half random
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let capped = (self.initial.as_secs_f64() * self.multiplier.powi(exponent)).min(self.max.as_secs_f64());
+        let half = capped / 2.0;
+        let jitter = rand::rng().random_range(0.0..=half);
+
+        Duration::from_secs_f64(half + jitter)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+        }
+    }
+}
+
+type EventListener = Box<dyn Fn(&ConnectionEvent) + Send>;
+
+// Keeps a replication connection alive: reconnects on retryable errors and
+// resumes from the last flushed LSN, gives up on fatal ones
+pub struct Supervisor {
+    config: DBConfig,
+    backoff: Backoff,
+    progress: ReplicationProgress,
+    offsets: Option<OffsetStore>,
+    // timeline of the previous session
+    timeline: Option<u32>,
+    listeners: Vec<EventListener>,
+    shutdown: ShutdownSignal,
+    metrics: PipelineMetrics,
+    sinks: SinkDriver,
+}
+
+impl Supervisor {
+    pub fn new(config: DBConfig) -> Supervisor {
+        Supervisor {
+            metrics: PipelineMetrics::new(&config.replication_slot),
+            sinks: SinkDriver::new(Box::new(StdoutSink::new(&config.replication_slot))),
+            config,
+            backoff: Backoff::default(),
+            progress: ReplicationProgress::default(),
+            offsets: None,
+            timeline: None,
+            listeners: Vec::new(),
+            shutdown: ShutdownSignal::new(),
+        }
+    }
+
+    pub fn on_event<F>(mut self, listener: F) -> Supervisor
+    where F: Fn(&ConnectionEvent) + Send + 'static {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: ShutdownSignal) -> Supervisor {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: PipelineMetrics) -> Supervisor {
+        self.metrics = metrics;
+        self
+    }
+
+    // resume from the offset in the store and keep it up to date
+    pub fn with_offset_store(mut self, offsets: OffsetStore) -> Supervisor {
+        self.offsets = Some(offsets);
+        self
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn Sink>) -> Supervisor {
+        self.sinks = SinkDriver::new(sink);
+        self
+    }
+
+    // supervises sessions until shutdown or a fatal error, then closes the sink
+    pub fn run(&mut self) -> Result<(), ReplicationError> {
+        let result = self.supervise();
+        let closed = self.sinks.close().map_err(ReplicationError::Sink);
+        // the session error is what gets reported, a failed close must not go unnoticed
+        if result.is_err()
+            && let Err(e) = &closed {
+            error!(sink = self.sinks.name(), "Could not close the sink: {}", e);
+        }
+
+        result.and(closed)
+    }
+
+    fn supervise(&mut self) -> Result<(), ReplicationError> {
+        self.metrics.register(&self.config.replication_slot);
+        if let Some(store) = &mut self.offsets {
+            let offset = store.load().map_err(|e| ReplicationError::OffsetPersistence(e.to_string()))?;
+            if let Some(offset) = offset {
+                info!(%offset, "Resuming from persisted offset");
+                self.progress.flushed_lsn = self.progress.flushed_lsn.max(offset);
+            }
+        }
+        let mut attempt: u32 = 0;
+        loop {
+            if self.shutdown.is_triggered() {
+                self.emit(ConnectionEvent::Stopped { flushed_lsn: self.progress.flushed_lsn });
+                return Ok(());
+            }
+            attempt += 1;
+            self.emit(ConnectionEvent::Connecting { attempt });
+            let received_before = self.progress.received_lsn;
+            let error = match self.connect_and_stream() {
+                Ok(()) => {
+                    self.emit(ConnectionEvent::Stopped { flushed_lsn: self.progress.flushed_lsn });
+                    return Ok(());
+                },
+                Err(e) => e,
+            };
+            if let ReplicationError::Authentication(e) = &error
+                && !matches!(e, AuthenticationError::ConnectionFailed(_) | AuthenticationError::ServerRejected(_)) {
+                self.metrics.auth_failure();
+            }
+            // a session that made progress starts the backoff over
+            if self.progress.received_lsn > received_before {
+                attempt = 1;
+            }
+
+            let retryable = error.is_retryable() && !self.shutdown.is_triggered();
+            self.emit(ConnectionEvent::Disconnected { error: error.to_string(), retryable });
+            if !retryable {
+                self.emit(ConnectionEvent::Failed { error: error.to_string() });
+                return Err(error);
+            }
+
+            let delay = self.backoff.delay(attempt);
+            self.metrics.reconnect();
+            self.emit(ConnectionEvent::Reconnecting { attempt, delay });
+            self.sleep_unless_shutdown(delay);
+        }
+    }
+
+    fn sleep_unless_shutdown(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        while !self.shutdown.is_triggered() && Instant::now() < deadline {
+            thread::sleep(deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(200)));
+        }
+    }
+
+    fn connect_and_stream(&mut self) -> Result<(), ReplicationError> {
+        let mut stream = sasl_authentication(&self.config)?;
+        let server = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        let _session = info_span!("session", server = %server).entered();
+        self.emit(ConnectionEvent::Authenticated { server });
+        let system = identify_system(&mut stream)?;
+        info!(system_id = %system.system_id, timeline = system.timeline, wal_position = %system.wal_position, "Identified system");
+        if let Some(previous) = self.timeline
+            && previous != system.timeline {
+            self.emit(ConnectionEvent::TimelineSwitched { from: previous, to: system.timeline });
+        }
+        self.timeline = Some(system.timeline);
+        self.sinks.identify(&system.system_id);
+        self.verify_slot(&mut stream)?;
+        let mut replication = start_replication(&mut stream, &self.config, &self.progress)?;
+        self.emit(ConnectionEvent::Streaming { start_lsn: self.progress.flushed_lsn });
+        stream_changes(&mut stream, &mut replication, &mut self.progress, &mut self.offsets, &self.shutdown, &self.metrics,
+                       &mut self.sinks)
+    }
+
+    // After a failover the slot is the copy synchronized to the promoted standby. If it confirmed
+    // more than we flushed, the server would skip the changes in between without telling anyone
+    fn verify_slot(&self, stream: &mut TcpStream) -> Result<(), ReplicationError> {
+        let offset = self.progress.flushed_lsn;
+        if offset == Lsn::default() {
+            return Ok(());
+        }
+        let slot = slot_position(stream, &self.config.replication_slot)?;
+        if slot.synced {
+            info!("Replication slot {} was synchronized from the former primary", self.config.replication_slot);
+        }
+        match slot.confirmed_flush_lsn {
+            Some(confirmed_flush) if confirmed_flush > offset => Err(ReplicationError::SlotAheadOfOffset {
+                slot: self.config.replication_slot.clone(),
+                confirmed_flush,
+                offset,
+            }),
+            Some(confirmed_flush) if confirmed_flush < offset => {
+                info!("Replication slot {} confirmed up to {}, changes up to {} were already delivered",
+                      self.config.replication_slot, confirmed_flush, offset);
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        self.metrics.connection_event(&event);
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+}
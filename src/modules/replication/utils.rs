@@ -1,46 +1,257 @@
-use crate::modules::debug_utils::bytes_to_utfstring;
-use crate::modules::replication::command_utils::start_replication_command;
-use crate::modules::sasl::server_response_utils::decode;
-use std::io::{Read, Write};
+use crate::dto::DBConfig;
+use crate::modules::logging::PROTOCOL_TARGET;
+use crate::modules::metrics::registry::PipelineMetrics;
+use crate::modules::replication::command_utils::{copy_done_message, query_message, standby_status_update, start_replication_command, terminate_message};
+use crate::modules::replication::catalog::Catalog;
+use crate::modules::replication::decoder::Decoder;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::message_utils::{parse_copy_data, postgres_epoch_micros, CopyDataMessage, MessageReader};
+use crate::modules::replication::replication_error::ReplicationError;
+use crate::modules::replication::offset_store::OffsetStore;
+use crate::modules::replication::replication_error::ReplicationError::{ConnectionLost, OffsetPersistence, ProtocolViolation, ServerError, SlotNotFound};
+use crate::modules::sasl::server_response_utils::{decode, parse_data_row, parse_error_response, wait_for_ready_for_query};
+use crate::modules::shutdown::ShutdownSignal;
+use crate::modules::sink::utils::SinkDriver;
+use crate::modules::tcp::utils::close_tcp_connection;
+use std::io::Write;
 use std::net::TcpStream;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info, trace, warn};
 
-pub fn replication(tcp_stream: &mut TcpStream)  {
-    println!("Starting replication for");
-    start_replication_step(tcp_stream);
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+// socket read timeout, bounds how long a shutdown request can go unnoticed
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplicationProgress {
+    pub received_lsn: Lsn,
+    pub flushed_lsn: Lsn,
+}
+
+// IDENTIFY_SYSTEM result, the timeline changes when a standby is promoted
+#[derive(Debug, Clone)]
+pub struct SystemIdentity {
+    pub system_id: String,
+    pub timeline: u32,
+    pub wal_position: Lsn,
+}
+
+// what the server knows about our slot, synced is only reported by PostgreSQL 17 and later
+#[derive(Debug, Clone)]
+pub struct SlotPosition {
+    pub confirmed_flush_lsn: Option<Lsn>,
+    pub synced: bool,
+}
+
+pub fn identify_system(stream: &mut TcpStream) -> Result<SystemIdentity, ReplicationError> {
+    let rows = simple_query(stream, "IDENTIFY_SYSTEM")?;
+    let row = rows.first().ok_or_else(|| ProtocolViolation(String::from("IDENTIFY_SYSTEM returned no row")))?;
+    let column = |i: usize| row.get(i).cloned().flatten()
+        .ok_or_else(|| ProtocolViolation(format!("IDENTIFY_SYSTEM column {} is missing", i)));
+
+    Ok(SystemIdentity {
+        system_id: column(0)?,
+        timeline: column(1)?.parse().map_err(|_| ProtocolViolation(String::from("invalid timeline in IDENTIFY_SYSTEM")))?,
+        wal_position: column(2)?.parse().map_err(ProtocolViolation)?,
+    })
 }
 
+pub fn slot_position(stream: &mut TcpStream, slot_name: &str) -> Result<SlotPosition, ReplicationError> {
+    // to_jsonb keeps the query valid on servers without the synced column
+    let query = format!("SELECT confirmed_flush_lsn::text, to_jsonb(s)->>'synced' FROM pg_catalog.pg_replication_slots s \
+                         WHERE slot_name = '{}'", slot_name.replace('\'', "''"));
+    let rows = simple_query(stream, &query)?;
+    let row = rows.first().ok_or_else(|| SlotNotFound(format!("replication slot \"{}\" does not exist", slot_name)))?;
+    let confirmed_flush_lsn = match row.first().cloned().flatten() {
+        Some(lsn) => Some(lsn.parse().map_err(ProtocolViolation)?),
+        None => None,
+    };
 
-fn start_replication_step(stream: &mut TcpStream) -> () {
-    //let message = "START_REPLICATION SLOT scopes_slot LOGICAL 0/0 (proto_version '1', publication_names 'scopes_pub')";
-    //stream.write_all(message.as_bytes()).expect("Failed to write to server");
-    let publication_names = ["scopes_pub"];
-    let command = start_replication_command("scopes_slot", "LOGICAL",
+    Ok(SlotPosition { confirmed_flush_lsn, synced: row.get(1).cloned().flatten().as_deref() == Some("true") })
+}
+
+// rows of a simple query on a replication connection that is not streaming yet
+fn simple_query(stream: &mut TcpStream, query: &str) -> Result<Vec<Vec<Option<String>>>, ReplicationError> {
+    stream.write_all(&query_message(query))?;
+    let mut rows = Vec::new();
+    loop {
+        let m = decode(stream)?;
+        match m[0] {
+            b'D' => rows.push(parse_data_row(&m).ok_or_else(|| ProtocolViolation(String::from("malformed DataRow")))?),
+            b'E' => {
+                let error = server_error(&m);
+                // the connection is still usable, but the query's error is what matters
+                wait_for_ready_for_query(stream)?;
+                return Err(error);
+            },
+            b'Z' => return Ok(rows),
+            // RowDescription, CommandComplete and notices
+            _ => {},
+        }
+    }
+}
+
+// a started replication stream: the framing of its messages and the decoder of its changes
+pub struct ReplicationStream {
+    reader: MessageReader,
+    decoder: Decoder,
+}
+
+pub fn start_replication(stream: &mut TcpStream, config: &DBConfig, progress: &ReplicationProgress) -> Result<ReplicationStream, ReplicationError> {
+    info!(start_lsn = %progress.flushed_lsn, "Starting replication");
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut reader = MessageReader::new();
+    let publication_names: Vec<&str> = config.publication_name.split(',').map(str::trim).collect();
+    let command = start_replication_command(&config.replication_slot, "LOGICAL", progress.flushed_lsn,
                                             "1", &publication_names);
+    stream.write_all(&command)?;
+    debug!("START_REPLICATION sent");
+
+    loop {
+        match reader.next_message(stream)? {
+            Some(m) if m[0] == b'W' => {
+                trace!(target: PROTOCOL_TARGET, bytes = ?m, "CopyBothResponse");
+                return Ok(ReplicationStream { reader, decoder: Decoder::new().with_catalog(Catalog::new(config.clone())) });
+            },
+            Some(m) if m[0] == b'E' => return Err(server_error(&m)),
+            Some(_) => {}, // notices
+            None => {},
+        }
+    }
+}
 
-    match stream.write_all(&command) {
-        Ok(_) => {
-            println!("Successfully send START_REPLICATION message");
-            let message = decode(stream);
-            match message {
-                Ok(m) => {
-                    println!("Server START_REPLICATION response ({} bytes): {:?}", m.len(), m);
-                    println!("Server START_REPLICATION response as string: {}", bytes_to_utfstring(m.as_slice()).unwrap());
+// Decodes the stream into transactions and writes them to the sinks, a transaction's end LSN
+// is flushed only once the sinks made it durable
+pub fn stream_changes(stream: &mut TcpStream, replication: &mut ReplicationStream, progress: &mut ReplicationProgress,
+                      offsets: &mut Option<OffsetStore>, shutdown: &ShutdownSignal, metrics: &PipelineMetrics,
+                      sinks: &mut SinkDriver) -> Result<(), ReplicationError> {
+    let ReplicationStream { reader, decoder } = replication;
+    let mut last_status = Instant::now();
+    // for the lag metrics, taken from the server so the local clock does not matter
+    let mut server_wal_end = Lsn::default();
+    let mut server_time: i64 = 0;
+    let mut last_commit_time: Option<i64> = None;
+    loop {
+        if shutdown.is_triggered() {
+            return finish_replication(stream, reader, progress, offsets, sinks);
+        }
+        let message = reader.next_message(stream)?;
+        let mut reply_requested = false;
+        if let Some(m) = message {
+            metrics.received(m.len());
+            match m[0] {
+                b'd' => match parse_copy_data(&m[5..])? {
+                    CopyDataMessage::XLogData { start, end, server_time: time, data } => {
+                        progress.received_lsn = progress.received_lsn.max(end).max(start);
+                        server_wal_end = server_wal_end.max(end);
+                        server_time = server_time.max(time);
+                        trace!(target: PROTOCOL_TARGET, %start, %end, bytes = data.len(), "XLogData");
+                        if let Some(transaction) = decoder.decode(start, &data)? {
+                            let (end_lsn, commit_time) = (transaction.end_lsn, transaction.commit_time);
+                            metrics.transaction(&transaction);
+                            sinks.write(&transaction)?;
+                            last_commit_time = Some(commit_time);
+                            metrics.applied(end_lsn);
+                        }
+                    },
+                    CopyDataMessage::PrimaryKeepalive { wal_end, server_time: time, reply_requested: reply } => {
+                        progress.received_lsn = progress.received_lsn.max(wal_end);
+                        server_wal_end = server_wal_end.max(wal_end);
+                        server_time = server_time.max(time);
+                        // everything decoded before wal_end was sent ahead of the keepalive, with no transaction
+                        // open and nothing waiting for a sink flush, nothing up to it is pending and the slot can
+                        // advance while published tables are idle
+                        if !decoder.in_transaction() && !sinks.has_unflushed() {
+                            progress.flushed_lsn = progress.flushed_lsn.max(wal_end);
+                        }
+                        reply_requested = reply;
+                        trace!(target: PROTOCOL_TARGET, %wal_end, reply_requested, "Primary keepalive");
+                    },
                 },
-                Err(e) => {
-                    println!("Error while decoding START_REPLICATION response: {}", e);
-                }
+                b'c' => return Err(ConnectionLost(String::from("server ended the replication stream"))),
+                b'E' => return Err(server_error(&m)),
+                b'N' => {},
+                t => return Err(ProtocolViolation(format!("unexpected message '{}' while streaming", t as char))),
             }
-        },
-        Err(e) => println!("Error while sending START_REPLICATION message: {}", e)
+            // caught up means no lag, otherwise it is how old the last delivered commit is by the server's clock
+            let lag = match last_commit_time {
+                Some(commit_time) if progress.flushed_lsn < server_wal_end => (server_time - commit_time).max(0) as u64,
+                _ => 0,
+            };
+            metrics.positions(progress.received_lsn, progress.flushed_lsn, server_wal_end, Duration::from_micros(lag));
+        }
+
+        let status_due = reply_requested || last_status.elapsed() >= STATUS_INTERVAL;
+        if status_due || sinks.flush_due() {
+            flush_sinks(progress, sinks)?;
+        }
+        if status_due {
+            send_status_update(stream, progress, offsets)?;
+            sinks.acknowledge(progress.flushed_lsn)?;
+            last_status = Instant::now();
+        }
+    }
+}
+
+fn flush_sinks(progress: &mut ReplicationProgress, sinks: &mut SinkDriver) -> Result<(), ReplicationError> {
+    if let Some(lsn) = sinks.flush()? {
+        progress.flushed_lsn = progress.flushed_lsn.max(lsn);
+    }
+
+    Ok(())
+}
+
+// Stops streaming: flushes the sinks, acknowledges what they made durable, ends the copy and closes the session
+fn finish_replication(stream: &mut TcpStream, reader: &mut MessageReader, progress: &mut ReplicationProgress,
+                      offsets: &mut Option<OffsetStore>, sinks: &mut SinkDriver) -> Result<(), ReplicationError> {
+    flush_sinks(progress, sinks)?;
+    info!(flushed_lsn = %progress.flushed_lsn, "Stopping replication");
+    send_status_update(stream, progress, offsets)?;
+    sinks.acknowledge(progress.flushed_lsn)?;
+    stream.write_all(&copy_done_message())?;
+
+    // the server may still send WAL until it answers with CopyDone, CommandComplete and ReadyForQuery,
+    // anything past the flushed LSN is not acknowledged and will be streamed again on restart
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    loop {
+        match reader.next_message(stream)? {
+            Some(m) if m[0] == b'Z' => break,
+            Some(m) if m[0] == b'E' => return Err(server_error(&m)),
+            _ if Instant::now() >= deadline => {
+                warn!("Server did not end the replication stream within {:?}", SHUTDOWN_TIMEOUT);
+                break;
+            },
+            _ => {},
+        }
     }
 
+    stream.write_all(&terminate_message())?;
+    close_tcp_connection(stream)?;
+    info!("Replication connection closed");
+
+    Ok(())
+}
+
+// the offset is persisted first, the server must never confirm more than we can resume from
+fn send_status_update(stream: &mut TcpStream, progress: &ReplicationProgress,
+                      offsets: &mut Option<OffsetStore>) -> Result<(), ReplicationError> {
+    if let Some(store) = offsets {
+        store.save(progress.flushed_lsn).map_err(|e| OffsetPersistence(e.to_string()))?;
+    }
+    let update = standby_status_update(progress.received_lsn, progress.flushed_lsn, progress.flushed_lsn,
+                                       postgres_epoch_micros(SystemTime::now()), false);
+    trace!(target: PROTOCOL_TARGET, received = %progress.received_lsn, flushed = %progress.flushed_lsn, "Standby status update");
+    stream.write_all(&update)?;
 
-    let mut buffer = vec![0; 1024]; // Create a buffer to store received data
-    let mut bytes_read = stream.read(&mut buffer).expect("Failed to read from stream");
+    Ok(())
+}
 
-    while bytes_read > 0 {
-        let received_data = String::from_utf8_lossy(&buffer[..bytes_read]);
-        println!("Received: {}", received_data);
-        bytes_read = stream.read(&mut buffer).expect("Failed to read from stream");
+fn server_error(m: &[u8]) -> ReplicationError {
+    let error = parse_error_response(m);
+    match error.code.as_str() {
+        // undefined_object is what the server reports for a missing slot
+        "42704" => SlotNotFound(error.message),
+        _ => ServerError { code: error.code, message: error.message },
     }
 }
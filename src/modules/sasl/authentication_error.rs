@@ -5,11 +5,12 @@ use std::fmt::Formatter;
 #[derive(Debug)]
 pub enum AuthenticationError {
     UnsupportedMechanism(String),
-    ScramPreparationFailed(String),
     ClientKeyGenerationFailed(String),
     IllegalState(String),
     ConnectionFailed(String),
     SASLAuthenticationFailed(String),
+    // an error of the server that is neither about the credentials nor worth a retry, e.g. no such database
+    ServerRejected(String),
     GenericError(String),
 }
 
@@ -17,12 +18,14 @@ impl fmt::Display for AuthenticationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             AuthenticationError::UnsupportedMechanism(msg) => write!(f, "Unsupported mechanism: {}", msg),
-            AuthenticationError::ScramPreparationFailed(msg) => write!(f, "SCRAM preparation failed: {}", msg),
             AuthenticationError::ClientKeyGenerationFailed(msg) => write!(f, "Client key generation failed: {}", msg),
             AuthenticationError::IllegalState(msg) => write!(f, "Missing details: {}", msg),
             AuthenticationError::ConnectionFailed(msg) => write!(f, "Connection failed: {}", msg),
             AuthenticationError::SASLAuthenticationFailed(msg) => write!(f, "SASL authentication failed: {}", msg),
+            AuthenticationError::ServerRejected(msg) => write!(f, "Server rejected the connection: {}", msg),
             AuthenticationError::GenericError(msg) => write!(f, "Generic error: {}", msg),
         }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for AuthenticationError {}
\ No newline at end of file
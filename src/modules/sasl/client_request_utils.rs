@@ -3,16 +3,16 @@ use base64::engine::general_purpose;
 use hmac::{Hmac, Mac};
 use pbkdf2::pbkdf2_hmac;
 use rand::distr::Alphanumeric;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use sha2::{Digest, Sha256};
-use crate::config::CONFIG;
 use crate::modules::sasl::authentication_error::AuthenticationError;
 use crate::modules::sasl::authentication_error::AuthenticationError::{ClientKeyGenerationFailed, IllegalState};
 use crate::modules::sasl::dto::ClientSecondMessage;
 use crate::modules::sasl::rsi::Rsi;
+use crate::modules::secret::{SecretBytes, SecretString};
 
 type HmacSha256 = Hmac<Sha256>;
-pub fn prepare_client_second_message(client_first_message: &[u8], mechanism: &str, rsi: &Rsi, password: &str)
+pub fn prepare_client_second_message(client_first_message: &[u8], mechanism: &str, rsi: &Rsi, password: &SecretString)
     -> Result<ClientSecondMessage, AuthenticationError> {
     match extract_client_nonce_from_first_message(client_first_message) {
         Some(client_nonce) => {
@@ -22,23 +22,23 @@ pub fn prepare_client_second_message(client_first_message: &[u8], mechanism: &st
     }
 }
 
-fn process_client_first_message(client_nonce: &str, mechanism: &str, rsi: &Rsi, password: &str)
+fn process_client_first_message(client_nonce: &str, mechanism: &str, rsi: &Rsi, password: &SecretString)
     ->  Result<ClientSecondMessage, AuthenticationError> {
-    if(!client_nonce.as_bytes().starts_with("n,,".as_ref())) {
+    if !client_nonce.as_bytes().starts_with("n,,".as_ref()) {
         return Err(IllegalState(String::from("Client first message missing prefix n,,")));
     }
     match prepare_salted_password(mechanism, rsi, password) {
         Ok(salted_password) => {
-            match HmacSha256::new_from_slice(salted_password.as_slice()) {
-                Ok(mut hmac) => {
+            match HmacSha256::new_from_slice(salted_password.expose()) {
+                Ok(hmac) => {
                     let client_key = compute_client_key(hmac);
-                    let stored_key = Sha256::digest(&client_key);
+                    let stored_key = SecretBytes::take_from(&mut Sha256::digest(client_key.expose()));
                     let (auth_message, client_final_message_without_proof) =
                         compute_client_second_auth_message(client_nonce.as_bytes(), rsi);
-                    let client_signature = compute_client_signature(&stored_key, auth_message.as_bytes())?;
-                    let client_proof = compute_client_proof(&client_key, &client_signature);
-                    let final_message = create_client_final_message(&client_final_message_without_proof, &client_proof);
-                    let framed = build_password_message(&final_message);
+                    let client_signature = compute_client_signature(stored_key.expose(), auth_message.as_bytes())?;
+                    let client_proof = compute_client_proof(client_key.expose(), client_signature.expose());
+                    let final_message = create_client_final_message(&client_final_message_without_proof, client_proof.expose());
+                    let framed = build_password_message(final_message.expose());
                     Ok(ClientSecondMessage::new(salted_password, auth_message, framed))
                     //Ok(framed)
                 },
@@ -49,7 +49,7 @@ fn process_client_first_message(client_nonce: &str, mechanism: &str, rsi: &Rsi,
     }
 }
 
-fn build_password_message(payload: &str) -> Vec<u8> {
+fn build_password_message(payload: &str) -> SecretBytes {
     let payload_bytes = payload.as_bytes();
     let mut msg = Vec::with_capacity(1 + 4 + payload_bytes.len());
     msg.push(b'p'); // message type
@@ -59,12 +59,12 @@ fn build_password_message(payload: &str) -> Vec<u8> {
     msg.extend_from_slice(&len.to_be_bytes());
 
     msg.extend_from_slice(payload_bytes);
-    msg
+    SecretBytes::new(msg)
 }
 
-fn create_client_final_message(client_final_message_without_proof: &str, client_proof: &[u8]) -> String {
-    let proof_b64 = base64::engine::general_purpose::STANDARD.encode(client_proof);
-    format!("{},p={}", client_final_message_without_proof, proof_b64)
+fn create_client_final_message(client_final_message_without_proof: &str, client_proof: &[u8]) -> SecretString {
+    let proof_b64 = SecretString::new(base64::engine::general_purpose::STANDARD.encode(client_proof));
+    SecretString::new(format!("{},p={}", client_final_message_without_proof, proof_b64.expose()))
 }
 
 fn extract_client_nonce_from_first_message(message: &[u8]) -> Option<&str> {
@@ -78,14 +78,14 @@ fn extract_client_nonce_from_first_message(message: &[u8]) -> Option<&str> {
     std::str::from_utf8(&message[pos..]).ok()
 }
 
-fn compute_client_proof(client_key: &[u8], client_signature: &[u8]) -> Vec<u8> {
+fn compute_client_proof(client_key: &[u8], client_signature: &[u8]) -> SecretBytes {
     assert_eq!(client_key.len(), 32);
     assert_eq!(client_signature.len(), 32);
 
-    client_key.iter()
+    SecretBytes::new(client_key.iter()
         .zip(client_signature.iter())
         .map(|(a, b)| a ^ b)
-        .collect()
+        .collect())
 }
 
 fn compute_client_second_auth_message(client_first_message: &[u8], rsi: &Rsi) -> (String, String) {
@@ -109,29 +109,24 @@ fn compute_client_second_auth_message(client_first_message: &[u8], rsi: &Rsi) ->
 }
 
 
-fn compute_client_signature(stored_key: &[u8], auth_message: &[u8]) -> Result<Vec<u8>, AuthenticationError> { {}
+fn compute_client_signature(stored_key: &[u8], auth_message: &[u8]) -> Result<SecretBytes, AuthenticationError> { {}
     match HmacSha256::new_from_slice(stored_key) {
             Ok(mut hmac) => {
                 hmac.update(auth_message);
-                let result = hmac.finalize().into_bytes();
-                let mut signature = [0u8; 32];
-                signature.copy_from_slice(&result);
-
-                Ok(Vec::from(signature))
+                Ok(SecretBytes::take_from(&mut hmac.finalize().into_bytes()))
             },
             Err(e) => Err(ClientKeyGenerationFailed(e.to_string()))
     }
 }
 
-fn compute_client_key(mut hmac: HmacSha256) -> Vec<u8> {
+fn compute_client_key(mut hmac: HmacSha256) -> SecretBytes {
     hmac.update(b"Client Key");
     let result = hmac.finalize();
-    let bytes = result.into_bytes();
 
-    bytes.to_vec()
+    SecretBytes::take_from(&mut result.into_bytes())
 }
 
-pub fn prepare_salted_password(mechanism: &str, rsi: &Rsi, password: &str) -> Result<Vec<u8>, AuthenticationError> {
+pub fn prepare_salted_password(mechanism: &str, rsi: &Rsi, password: &SecretString) -> Result<SecretBytes, AuthenticationError> {
     match mechanism {
         "SCRAM-SHA-256" => {
             let salt = general_purpose::STANDARD.decode(&rsi.salt)
@@ -139,23 +134,17 @@ pub fn prepare_salted_password(mechanism: &str, rsi: &Rsi, password: &str) -> Re
             // allocate space for the derived key
             let mut salted_password = [0u8; 32]; // SCRAM-SHA-256 always outputs 32 bytes
             pbkdf2_hmac::<Sha256>(
-                password.as_bytes(),
+                password.expose().as_bytes(),
                 &salt,
                 rsi.iter_count,
                 &mut salted_password,
             );
-
-            Ok(Vec::from(salted_password))
+            Ok(SecretBytes::take_from(&mut salted_password))
         },
         _ => Err(AuthenticationError::UnsupportedMechanism(mechanism.to_owned())),
     }
 }
 
-pub struct ClientFirstMessage {
-    message: String,
-    client_nonce: String
-}
-
 pub fn prepare_client_first_message(mechanism: &str) -> Result<Vec<u8>, AuthenticationError> {
     match mechanism {
         "SCRAM-SHA-256" => {
@@ -168,7 +157,7 @@ pub fn prepare_client_first_message(mechanism: &str) -> Result<Vec<u8>, Authenti
 }
 
 fn generate_nonce(length: usize) -> String {
-    thread_rng()
+    rand::rng()
         .sample_iter(&Alphanumeric)
         .take(length)
         .map(char::from)
@@ -178,7 +167,7 @@ fn generate_nonce(length: usize) -> String {
 fn create_password_message(client_nonce: &str) -> Vec<u8> {
     let mut message = Vec::new();
 
-    let message_type = 'p' as u8;
+    let message_type = b'p';
     message.push(message_type); // p
 
     let mechanism = "SCRAM-SHA-256\0"; // sasl authentication mechanism
@@ -190,26 +179,26 @@ fn create_password_message(client_nonce: &str) -> Vec<u8> {
 
     message.extend_from_slice(&(client_nonce.len() as u32).to_be_bytes()); // client first message length
 
-    message.extend_from_slice(&client_nonce.as_bytes()); // client first message
+    message.extend_from_slice(client_nonce.as_bytes()); // client first message
 
     // ownership moved out
     message
 }
 
 // TODO: take protocol version as input
-pub fn prepare_handshake_message(user: &str) -> Vec<u8> {
-    let params = prepare_handshake_params(user);
+pub fn prepare_handshake_message(user: &str, database: &str) -> Vec<u8> {
+    let params = prepare_handshake_params(user, database);
     // calculate full length = 4 (length field itself) + 4 (protocol) + params.len()
     let len = 4 + 4 + params.len();
     let mut message = Vec::new();
     message.extend_from_slice(&(len as i32).to_be_bytes());       // length
-    message.extend_from_slice(&(196608 as i32).to_be_bytes());    // protocol 3.0
+    message.extend_from_slice(&196608_i32.to_be_bytes());    // protocol 3.0
     message.extend_from_slice(&params);
 
     message
 }
 
-fn prepare_handshake_params(user: &str) -> Vec<u8> {
+fn prepare_handshake_params(user: &str, database: &str) -> Vec<u8> {
     let mut params = Vec::new();
 
     // user parameter
@@ -219,7 +208,7 @@ fn prepare_handshake_params(user: &str) -> Vec<u8> {
 
     // database parameter
     params.extend_from_slice(b"database\0");
-    params.extend_from_slice(format!("{}\0",&*CONFIG.db_name).as_bytes());
+    params.extend_from_slice(format!("{}\0", database).as_bytes());
 
     // replication = true
     params.extend_from_slice(b"replication\0");
@@ -228,18 +217,5 @@ fn prepare_handshake_params(user: &str) -> Vec<u8> {
     // terminator
     params.push(0);
 
-    params
-}
-
-
-fn prepare_handshake_params1(user: &str) -> Vec<u8> {
-    let mut params = Vec::new();
-    params.extend_from_slice(b"user\0");
-    params.extend_from_slice(user.as_bytes());
-    params.push(0);
-    params.extend_from_slice(b"replication\0");
-    params.extend_from_slice(b"true\0");
-    params.push(0); // terminator
-
     params
 }
\ No newline at end of file
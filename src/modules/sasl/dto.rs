@@ -1,11 +1,14 @@
+use crate::modules::secret::SecretBytes;
+
 pub struct ClientSecondMessage {
-    salted_password: Vec<u8>,
+    salted_password: SecretBytes,
     auth_message: String,
-    password: Vec<u8>
+    // framed client-final message, carries the client proof
+    password: SecretBytes
 }
 
 impl ClientSecondMessage {
-    pub(crate) fn new(p0: Vec<u8>, p1: String, p2: Vec<u8>) -> ClientSecondMessage {
+    pub(crate) fn new(p0: SecretBytes, p1: String, p2: SecretBytes) -> ClientSecondMessage {
         ClientSecondMessage {
             salted_password: p0,
             auth_message: p1,
@@ -13,14 +16,14 @@ impl ClientSecondMessage {
         }
     }
 
-    pub fn get_salted_password(&self) -> &Vec<u8> {
+    pub fn get_salted_password(&self) -> &SecretBytes {
         &self.salted_password
     }
 
     pub fn get_auth_message(&self) -> &String {
         &self.auth_message
     }
-    pub fn get_password(&self) -> &Vec<u8> {
+    pub fn get_password(&self) -> &SecretBytes {
         &self.password
     }
 
@@ -7,6 +7,10 @@ use sha2::Sha256;
 use crate::modules::sasl::authentication_error::AuthenticationError;
 use crate::modules::sasl::authentication_error::AuthenticationError::{ClientKeyGenerationFailed, IllegalState};
 use crate::modules::sasl::rsi::Rsi;
+use crate::modules::logging::PROTOCOL_TARGET;
+use crate::modules::replication::replication_error::is_retryable_sqlstate;
+use crate::modules::secret::SecretBytes;
+use tracing::trace;
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -58,7 +62,7 @@ fn read_authentication_mechanism(authentication_code: u32, bytes: &[u8]) -> Stri
         10 => { //Authentication SASL
             let mechanisms = payload_parts(bytes);
             // TODO: read from environment, in descending order of choice
-            let supported_sasl_mechanism = vec!["SCRAM-SHA-256"];
+            let supported_sasl_mechanism = ["SCRAM-SHA-256"];
             let chosen = mechanisms
                 .iter()
                 .find(|m| supported_sasl_mechanism.contains(&m.as_str()));
@@ -77,27 +81,21 @@ fn read_authentication_mechanism(authentication_code: u32, bytes: &[u8]) -> Stri
 }
 
 pub fn process_server_handshake_response(m: &[u8]) -> String {
-    println!("Server handshake response ({} bytes): {:?}", m.len(), m);
     let message_type = read_message_type(m[0]);
-    println!("Message type: {}", message_type);
     let length = read_message_length(&m[1..5]);
-    println!("Message length: {}", length - 4);
     let authentication_type = read_authentication_type(&m[5..9]);
-    println!("Authentication type: {}", authentication_type);
     let authentication_mechanism = read_authentication_mechanism(authentication_type, &m[9..]);
-    println!("Authentication mechanism: {}", authentication_mechanism);
+    trace!(target: PROTOCOL_TARGET, bytes = ?m, %message_type, length = length - 4, authentication_type, %authentication_mechanism,
+           "Server handshake response");
 
     authentication_mechanism
 }
 
 pub fn process_server_first_response(m: &[u8]) -> Rsi {
-    println!("Server first response ({} bytes): {:?}", m.len(), m);
     let message_type = read_message_type(m[0]);
-    println!("Message type: {}", message_type);
     let length = read_message_length(&m[1..5]);
-    println!("Message length: {}", length - 4);
     let authentication_type = read_authentication_type(&m[5..9]);
-    println!("Authentication type: {}", authentication_type);
+    trace!(target: PROTOCOL_TARGET, bytes = ?m, %message_type, length = length - 4, authentication_type, "Server first response");
     let r_s_i: Vec<String> = read_authentication_mechanism(authentication_type, &m[9..])
         .split(",")
         .map(|s| s.to_string())
@@ -117,9 +115,9 @@ pub fn process_server_first_response(m: &[u8]) -> Rsi {
     }
 
     Rsi {
-        nonce: nonce,
-        salt: salt,
-        iter_count: iter_count
+        nonce,
+        salt,
+        iter_count
     }
 }
 
@@ -128,9 +126,9 @@ pub fn verify_server_signature(salted_password: &[u8], auth_message: &str, serve
     let mut hmac = HmacSha256::new_from_slice(salted_password)
         .map_err(|e| ClientKeyGenerationFailed(e.to_string()))?;
     hmac.update(b"Server Key");
-    let server_key = hmac.finalize().into_bytes();
+    let server_key = SecretBytes::take_from(&mut hmac.finalize().into_bytes());
 
-    let mut hmac2 = HmacSha256::new_from_slice(&server_key)
+    let mut hmac2 = HmacSha256::new_from_slice(server_key.expose())
         .map_err(|e| ClientKeyGenerationFailed(e.to_string()))?;
     hmac2.update(auth_message.as_bytes());
     let expected_signature = hmac2.finalize().into_bytes();
@@ -175,3 +173,112 @@ pub fn extract_server_signature_bytes(full_message: &[u8]) -> Result<Vec<u8>, St
 
     Ok(sig_bytes)
 }
+
+pub struct ErrorResponse {
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    // 28xxx refuses the credentials, a server starting up, shutting down or out of connection slots
+    // (57P03, 53300) is worth another attempt, anything else needs an operator
+    pub fn to_authentication_error(&self, message: String) -> AuthenticationError {
+        if self.code.starts_with("28") {
+            AuthenticationError::SASLAuthenticationFailed(message)
+        } else if is_retryable_sqlstate(&self.code) {
+            AuthenticationError::ConnectionFailed(message)
+        } else {
+            AuthenticationError::ServerRejected(message)
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.severity, self.code, self.message)
+    }
+}
+
+pub fn parse_error_response(full_message: &[u8]) -> ErrorResponse {
+    let mut response = ErrorResponse {
+        severity: String::new(),
+        code: String::new(),
+        message: String::new(),
+    };
+    if full_message.len() <= 5 {
+        return response;
+    }
+    // fields are: 1 byte field type followed by a null terminated string, list ends with a null byte
+    for field in full_message[5..].split(|&b| b == 0).filter(|f| !f.is_empty()) {
+        let value = String::from_utf8_lossy(&field[1..]).to_string();
+        match field[0] {
+            b'S' => response.severity = value,
+            b'C' => response.code = value,
+            b'M' => response.message = value,
+            _ => {}
+        }
+    }
+
+    response
+}
+
+// text column values of a DataRow message, None for NULL
+pub fn parse_data_row(full_message: &[u8]) -> Option<Vec<Option<String>>> {
+    let body = full_message.get(5..)?;
+    let count = u16::from_be_bytes(body.get(0..2)?.try_into().ok()?) as usize;
+    let mut values = Vec::with_capacity(count);
+    let mut offset = 2;
+    for _ in 0..count {
+        let length = i32::from_be_bytes(body.get(offset..offset + 4)?.try_into().ok()?);
+        offset += 4;
+        if length < 0 {
+            values.push(None);
+            continue;
+        }
+        let end = offset + length as usize;
+        values.push(Some(String::from_utf8_lossy(body.get(offset..end)?).into_owned()));
+        offset = end;
+    }
+
+    Some(values)
+}
+
+pub fn wait_for_ready_for_query(stream: &mut TcpStream) -> Result<(), AuthenticationError> {
+    loop {
+        let m = decode(stream).map_err(|e| AuthenticationError::ConnectionFailed(e.to_string()))?;
+        match m[0] {
+            b'Z' => return Ok(()),
+            b'E' => {
+                let error = parse_error_response(&m);
+                return Err(error.to_authentication_error(error.to_string()));
+            },
+            // AuthenticationOk, ParameterStatus, BackendKeyData and notices
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_response(code: &str) -> Vec<u8> {
+        let fields = format!("SFATAL\0C{}\0Mfailed\0\0", code);
+        let mut message = vec![b'E'];
+        message.extend_from_slice(&(fields.len() as u32 + 4).to_be_bytes());
+        message.extend_from_slice(fields.as_bytes());
+        message
+    }
+
+    #[test]
+    fn classifies_startup_errors_by_sqlstate() {
+        let error = |code| parse_error_response(&error_response(code)).to_authentication_error(String::from("failed"));
+        assert_eq!(parse_error_response(&error_response("28P01")).to_string(), "FATAL 28P01: failed");
+        assert!(matches!(error("28P01"), AuthenticationError::SASLAuthenticationFailed(_)));
+        assert!(matches!(error("28000"), AuthenticationError::SASLAuthenticationFailed(_)));
+        assert!(matches!(error("57P03"), AuthenticationError::ConnectionFailed(_)));
+        assert!(matches!(error("53300"), AuthenticationError::ConnectionFailed(_)));
+        assert!(matches!(error("3D000"), AuthenticationError::ServerRejected(_)));
+    }
+}
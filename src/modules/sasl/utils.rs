@@ -1,125 +1,192 @@
+use crate::dto::DBConfig;
 use crate::modules::debug_utils::bytes_to_utfstring;
+use crate::modules::logging::PROTOCOL_TARGET;
 use crate::modules::sasl::client_request_utils::{prepare_client_first_message, prepare_client_second_message, prepare_handshake_message};
-use crate::modules::sasl::server_response_utils::{decode, extract_server_signature_bytes, process_server_first_response, process_server_handshake_response, verify_server_signature};
-use crate::modules::tcp::utils::get_tcp_connection;
-use base64::Engine;
-use std::fmt::Debug;
+use crate::modules::sasl::server_response_utils::{decode, extract_server_signature_bytes, parse_data_row, parse_error_response, process_server_first_response, process_server_handshake_response, verify_server_signature, wait_for_ready_for_query};
+use crate::modules::conninfo::target::{candidate_hosts, SessionState, TargetSessionAttrs, SESSION_STATE_QUERY};
+use crate::modules::replication::command_utils::{query_message, terminate_message};
+use crate::modules::tcp::utils::{close_tcp_connection, get_tcp_connection};
 use std::io::Write;
-use std::env;
 use std::net::TcpStream;
+use tracing::{debug, info, trace, warn};
 use crate::modules::sasl::authentication_error::AuthenticationError;
 use crate::modules::sasl::authentication_error::AuthenticationError::{ConnectionFailed, GenericError, IllegalState, SASLAuthenticationFailed};
 
-pub fn sasl_authentication(host: &str, port: u16, user: &str) -> Result<TcpStream, AuthenticationError> {
+// Authenticates against the first host that accepts the login and matches target_session_attrs,
+// every reconnect walks the list again so the connector follows the primary after a failover
+pub fn sasl_authentication(config: &DBConfig) -> Result<TcpStream, AuthenticationError> {
+    let candidates = candidate_hosts(config).map_err(ConnectionFailed)?;
+    let mut last_error = ConnectionFailed(format!("No host to connect to for user: {}", config.db_user));
+    for attrs in config.target_session_attrs.passes() {
+        for (host, port) in &candidates {
+            let mut stream = match authenticate(config, host, *port) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(host = %host, port, error = %e, "Could not authenticate");
+                    last_error = e;
+                    continue;
+                },
+            };
+            if attrs == TargetSessionAttrs::Any {
+                return Ok(stream);
+            }
+            match read_session_state(&mut stream) {
+                Ok(state) if attrs.accepts(&state) => return Ok(stream),
+                Ok(state) => {
+                    info!(host = %host, port, in_recovery = state.in_recovery, read_only = state.read_only,
+                          "Skipping server, target_session_attrs is {}", config.target_session_attrs);
+                    last_error = ConnectionFailed(format!("No server matching target_session_attrs={} for user: {}",
+                                                          config.target_session_attrs, config.db_user));
+                },
+                Err(e) => last_error = e,
+            }
+            let _ = stream.write_all(&terminate_message());
+            let _ = close_tcp_connection(&stream);
+        }
+    }
+
+    Err(last_error)
+}
+
+// pg_is_in_recovery() and transaction_read_only of an authenticated connection
+fn read_session_state(stream: &mut TcpStream) -> Result<SessionState, AuthenticationError> {
+    stream.write_all(&query_message(SESSION_STATE_QUERY)).map_err(|e| ConnectionFailed(e.to_string()))?;
+    let mut state = None;
+    loop {
+        let m = decode(stream).map_err(|e| ConnectionFailed(e.to_string()))?;
+        match m[0] {
+            b'D' => state = parse_data_row(&m).and_then(|values| match values.as_slice() {
+                [Some(in_recovery), Some(read_only)] => Some(SessionState::from_text(in_recovery, read_only)),
+                _ => None,
+            }),
+            b'E' => return Err(ConnectionFailed(parse_error_response(&m).to_string())),
+            b'Z' => return state.ok_or_else(|| IllegalState(String::from("Server did not report its session state"))),
+            // RowDescription, CommandComplete and notices
+            _ => {},
+        }
+    }
+}
+
+fn authenticate(config: &DBConfig, host: &str, port: u16) -> Result<TcpStream, AuthenticationError> {
+    let user = config.db_user.as_str();
     let mut stream = match get_tcp_connection(host, port) {
         Ok(strm) => strm,
         Err(e) => {
-            eprintln!("Could not connect to PostgreSQL server: {}", e);
+            debug!(error = %e, "Could not connect to PostgreSQL server");
             return Err(ConnectionFailed(format!("Connection failed for user: {}, address: {}:{}", user, host, port)))
         }
     };
 
-    let handshake_message = prepare_handshake_message(user);
+    let handshake_message = prepare_handshake_message(user, &config.db_name);
     match stream.write_all(&handshake_message) {
         Ok(_) => {
-            println!("Handshake message sent successfully");
+            debug!("Handshake message sent");
             let message = decode(&mut stream);
             match message {
+                Ok(m) if m[0] == b'E' => {
+                    let error = parse_error_response(&m);
+                    debug!(%error, "Server rejected handshake");
+                    Err(error.to_authentication_error(format!("{} for user: {}", error, user)))
+                },
                 Ok(m) => {
                     let authentication_mechanism = process_server_handshake_response(&m);
                     match prepare_client_first_message(&authentication_mechanism) {
                         Ok(client_first_message) => {
-                            println!("Client first message: {:?}", String::from_utf8_lossy(&*client_first_message));
+                            trace!(target: PROTOCOL_TARGET, content = ?String::from_utf8_lossy(&client_first_message), "Client first message");
                             match stream.write_all(client_first_message.as_slice()) {
                                 Ok(_) => {
                                     let server_first_response = decode(&mut stream);
                                     match server_first_response {
                                         Ok(m) => {
-                                            println!("Server first response ({} bytes): {:?}", m.len(), m);
-                                            println!("Server first response as string: {}", bytes_to_utfstring(m.as_slice()).unwrap());
+                                            trace!(target: PROTOCOL_TARGET, bytes = m.len(), content = ?bytes_to_utfstring(&m).unwrap_or_default(),
+                                                   "Server first response");
                                             let rsi = process_server_first_response(&m);
-                                            println!("RSI: {}", rsi);
-                                            let password = env::var("DB_PASSWORD").unwrap_or_else(|_| "password".to_string());
+                                            trace!(target: PROTOCOL_TARGET, %rsi, "Server nonce, salt and iterations");
                                             match prepare_client_second_message(&client_first_message, &authentication_mechanism,
-                                                                                &rsi, &password[..]) {
+                                                                                &rsi, &config.db_password) {
                                                 Ok(client_second_message) => {
-                                                    println!("Client second message as string: {}", String::from_utf8_lossy(&*client_second_message.get_password()));
-                                                    match stream.write_all(client_second_message.get_password()) {
+                                                    trace!(target: PROTOCOL_TARGET, content = ?client_second_message.get_password(),
+                                                           "Client second message");
+                                                    match stream.write_all(client_second_message.get_password().expose()) {
                                                         Ok(_) => {
                                                             let server_second_response = decode(&mut stream);
                                                             match server_second_response {
+                                                                Ok(m) if m[0] == b'E' => {
+                                                                    let error = parse_error_response(&m);
+                                                                    debug!(%error, "Server rejected client second message");
+                                                                    Err(error.to_authentication_error(format!("{} for user: {}", error, user)))
+                                                                },
                                                                 Ok(m) => {
-                                                                    println!("Server second response: ({} bytes): {:?}", m.len(), m);
-                                                                    println!("Server second response as string: {}", bytes_to_utfstring(m.as_slice()).unwrap());
+                                                                    trace!(target: PROTOCOL_TARGET, bytes = m.len(), content = ?bytes_to_utfstring(&m).unwrap_or_default(),
+                                                                           "Server second response");
                                                                     match extract_server_signature_bytes(m.as_slice()) {
                                                                         Ok(signature) => {
-                                                                            match verify_server_signature(client_second_message.get_salted_password(),
+                                                                            match verify_server_signature(client_second_message.get_salted_password().expose(),
                                                                                                     client_second_message.get_auth_message(),
                                                                                                     &signature) {
                                                                                 Ok(_) => {
-                                                                                    println!("Server signature valid");
-                                                                                    //drop(stream)
-                                                                                    //close_tcp_connection(&stream);
+                                                                                    debug!("Server signature valid");
+                                                                                    wait_for_ready_for_query(&mut stream)?;
                                                                                     Ok(stream)
                                                                                 },
                                                                                 Err(e) => {
-                                                                                    println!("Server signature invalid: {}", e);
+                                                                                    debug!(error = %e, "Server signature invalid");
                                                                                     Err(SASLAuthenticationFailed(format!("Server signature invalid for user: {}", user)))
                                                                                 },
                                                                             }
                                                                         },
                                                                         Err(e) => {
-                                                                            eprintln!("Server signature invalid: {}", e);
+                                                                            debug!(error = %e, "Server signature invalid");
                                                                             Err(IllegalState(format!("Error while extracting server signature from server \
                                                                             second response for user: {}", user)))
                                                                         }
                                                                     }
                                                                 },
                                                                 Err(e) => {
-                                                                    eprintln!("Error while decoding server second response: {}", e);
+                                                                    debug!(error = %e, "Error while decoding server second response");
                                                                     Err(IllegalState(format!("Error while decoding server second response for user {}", user)))
                                                                 }
                                                             }
                                                         },
                                                         Err(e) => {
-                                                            eprintln!("Error while sending client second message: {}", e);
+                                                            debug!(error = %e, "Error while sending client second message");
                                                             Err(ConnectionFailed(format!("Error while sending client second message for user: {}", user)))
                                                         },
                                                     }
                                                 },
                                                 Err(e) => {
-                                                    eprintln!("Could not prepare client second message response: {}", e);
+                                                    debug!(error = %e, "Could not prepare client second message response");
                                                     Err(GenericError(format!("Error while preparing client second message for user: {}", user)))
                                                 },
                                             }
                                         },
                                         Err(e) => {
-                                            eprintln!("Error while decoding client first message: {}", e);
+                                            debug!(error = %e, "Error while decoding server first response");
                                             Err(IllegalState(format!("Error while decoding server first response for user {}", user)))
                                         },
                                     }
                                 },
                                 Err(e) => {
-                                    eprintln!("Could not send message: {}", e);
+                                    debug!(error = %e, "Could not send client first message");
                                     Err(ConnectionFailed(format!("Error while sending client first message for user: {}", user)))
                                 },
                             }
                         },
                         Err(e) => {
-                            eprintln!("Failed to prepare client first message: {}", e);
+                            debug!(error = %e, "Failed to prepare client first message");
                             Err(GenericError(format!("Error while preparing client first message for user: {}", user)))
                         }
                     }
                 },
                 Err(e) => {
-                    eprintln!("Error while decoding handshake response: {}", e);
+                    debug!(error = %e, "Error while decoding handshake response");
                     Err(IllegalState(format!("Error while decoding handshake response for user {}", user)))
                 },
             }
 
         },
         Err(e) => {
-            eprintln!("Failed to send handshake message: {}", e);
+            debug!(error = %e, "Failed to send handshake message");
             Err(ConnectionFailed(format!("Error while sending handshake message for user: {}", user)))
         },
     }
@@ -0,0 +1,53 @@
+use std::fmt;
+use zeroize::Zeroize;
+
+// Credential material: never printed, wiped from memory when dropped
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T: Zeroize>(T);
+
+pub type SecretString = Secret<String>;
+pub type SecretBytes = Secret<Vec<u8>>;
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    // the only way to the value, keep what is derived from it short lived or secret too
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl SecretBytes {
+    // copies the bytes and wipes the source, e.g. a digest on the stack
+    pub fn take_from(bytes: &mut [u8]) -> SecretBytes {
+        let secret = Secret(bytes.to_vec());
+        bytes.zeroize();
+        secret
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
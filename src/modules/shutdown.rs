@@ -0,0 +1,54 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+// exit status when a second signal arrives while draining
+const FORCED_EXIT_CODE: i32 = 130;
+
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    triggered: Arc<AtomicBool>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> ShutdownSignal {
+        ShutdownSignal::default()
+    }
+
+    pub fn trigger(&self) {
+        self.triggered.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::SeqCst)
+    }
+}
+
+// First SIGINT/SIGTERM asks for a graceful shutdown, a second one exits immediately
+pub fn install_signal_handlers(shutdown: &ShutdownSignal) -> io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let mut sigterm = runtime.block_on(async { signal(SignalKind::terminate()) })?;
+    let mut sigint = runtime.block_on(async { signal(SignalKind::interrupt()) })?;
+    let shutdown = shutdown.clone();
+
+    thread::spawn(move || runtime.block_on(async move {
+        let name = tokio::select! {
+            _ = sigint.recv() => "SIGINT",
+            _ = sigterm.recv() => "SIGTERM",
+        };
+        info!("Received {}, shutting down gracefully", name);
+        shutdown.trigger();
+
+        tokio::select! {
+            _ = sigint.recv() => {},
+            _ = sigterm.recv() => {},
+        }
+        warn!("Received second signal, exiting without final acknowledgement");
+        std::process::exit(FORCED_EXIT_CODE);
+    }));
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::sink::sink_error::SinkError;
+use tracing::debug;
+
+// The end LSN of the last source transaction a database sink committed, stored in the same
+// transaction as its changes. Transactions at or before it were applied before a restart
+#[derive(Debug, Default)]
+pub struct AppliedLsn {
+    lsn: Option<Lsn>,
+}
+
+impl AppliedLsn {
+    // the LSN read from the offsets table, none for a new pipeline
+    pub fn load(&mut self, stored: Option<String>) -> Result<Option<Lsn>, SinkError> {
+        self.lsn = stored.map(|lsn| lsn.parse::<Lsn>()).transpose().map_err(SinkError::Rejected)?;
+        Ok(self.lsn)
+    }
+
+    pub fn contains(&self, batch: &Transaction) -> bool {
+        let applied = self.lsn.is_some_and(|applied| applied >= batch.end_lsn);
+        if applied {
+            debug!(lsn = %batch.end_lsn, "Transaction already applied");
+        }
+        applied
+    }
+
+    pub fn advance(&mut self, batch: &Transaction) {
+        self.lsn = Some(batch.end_lsn);
+    }
+}
+
+// the new row of inserts, reads and updates, the old one of deletes
+pub fn row<'a>(event: &ChangeEvent, values: Option<&'a Vec<ColumnValue>>) -> Result<&'a [ColumnValue], SinkError> {
+    values.map(Vec::as_slice)
+        .ok_or_else(|| SinkError::Rejected(format!("{} event for {}.{} without row", event.operation, event.schema, event.table)))
+}
+
+// the row an update or delete is matched with: the old key when it changed or with REPLICA
+// IDENTITY FULL, the new row's otherwise
+pub fn identity(event: &ChangeEvent) -> Result<&[ColumnValue], SinkError> {
+    match event.operation {
+        Operation::Update => row(event, event.old.as_ref().or(event.new.as_ref())),
+        _ => row(event, event.old.as_ref()),
+    }
+}
+
+// the text form of a value, None for null
+pub fn text_value(value: &ColumnValue) -> Result<Option<&str>, SinkError> {
+    match value {
+        ColumnValue::Null => Ok(None),
+        ColumnValue::Text(text) => Ok(Some(text)),
+        ColumnValue::Binary(_) => Err(SinkError::Rejected(String::from("binary column values cannot be applied, only text"))),
+        ColumnValue::UnchangedToast => Err(SinkError::Rejected(String::from("unchanged TOAST value in a replica identity column"))),
+    }
+}
+
+// " WHERE" over the replica identity columns, condition renders one with its position and value
+pub fn where_clause<F>(event: &ChangeEvent, values: &[ColumnValue], mut condition: F) -> Result<String, SinkError>
+    where F: FnMut(usize, &Column, &ColumnValue) -> Result<String, SinkError> {
+    let mut conditions = Vec::new();
+    for (i, (column, value)) in event.columns.iter().zip(values).enumerate() {
+        if column.key {
+            conditions.push(condition(i, column, value)?);
+        }
+    }
+    if conditions.is_empty() {
+        return Err(SinkError::Rejected(format!("{}.{} has no replica identity columns", event.schema, event.table)));
+    }
+
+    Ok(format!(" WHERE {}", conditions.join(" AND ")))
+}
+
+// the source's primary key columns, for the tables a sink creates
+pub fn primary_key(columns: &[Column]) -> Vec<&Column> {
+    columns.iter().filter(|c| c.primary_key).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, key: bool) -> Column {
+        Column { name: name.to_owned(), type_oid: 25, type_modifier: -1, key, primary_key: key, type_name: None }
+    }
+
+    fn event(operation: Operation, old: Option<Vec<ColumnValue>>, new: Option<Vec<ColumnValue>>) -> ChangeEvent {
+        ChangeEvent {
+            lsn: Lsn(1),
+            xid: 1,
+            schema: String::from("public"),
+            table: String::from("t"),
+            operation,
+            columns: vec![column("id", true), column("name", false)],
+            old,
+            new,
+        }
+    }
+
+    fn text(value: &str) -> ColumnValue {
+        ColumnValue::Text(value.to_owned())
+    }
+
+    #[test]
+    fn applied_lsn_skips_transactions_up_to_the_stored_one() {
+        let batch = |end_lsn| Transaction { xid: 1, commit_lsn: Lsn(end_lsn - 1), end_lsn: Lsn(end_lsn), commit_time: 0, events: Vec::new() };
+        let mut applied = AppliedLsn::default();
+        assert!(!applied.contains(&batch(0x10)));
+
+        assert_eq!(applied.load(Some(String::from("0/20"))).unwrap(), Some(Lsn(0x20)));
+        assert!(applied.contains(&batch(0x10)));
+        assert!(applied.contains(&batch(0x20)));
+        assert!(!applied.contains(&batch(0x30)));
+
+        applied.advance(&batch(0x30));
+        assert!(applied.contains(&batch(0x30)));
+        assert!(applied.load(Some(String::from("nonsense"))).is_err());
+    }
+
+    #[test]
+    fn identity_is_the_old_key_when_sent() {
+        let new = vec![text("2"), text("b")];
+        let update = event(Operation::Update, None, Some(new.clone()));
+        assert_eq!(identity(&update).unwrap(), new.as_slice());
+
+        let old = vec![text("1"), ColumnValue::Null];
+        let update = event(Operation::Update, Some(old.clone()), Some(new));
+        assert_eq!(identity(&update).unwrap(), old.as_slice());
+
+        let error = identity(&event(Operation::Delete, None, None)).unwrap_err();
+        assert_eq!(error.to_string(), "Sink rejected the data: DELETE event for public.t without row");
+    }
+
+    #[test]
+    fn where_clause_covers_the_key_columns() {
+        let update = event(Operation::Update, None, Some(vec![text("1"), text("a")]));
+        let clause = where_clause(&update, update.new.as_ref().unwrap(), |i, column, value| {
+            Ok(format!("{} = {} ({})", column.name, text_value(value)?.unwrap(), i))
+        }).unwrap();
+        assert_eq!(clause, " WHERE id = 1 (0)");
+
+        let mut keyless = update.clone();
+        keyless.columns[0].key = false;
+        assert!(where_clause(&keyless, keyless.new.as_ref().unwrap(), |_, _, _| Ok(String::new())).is_err());
+    }
+
+    #[test]
+    fn text_value_rejects_what_cannot_be_applied() {
+        assert_eq!(text_value(&text("a")).unwrap(), Some("a"));
+        assert_eq!(text_value(&ColumnValue::Null).unwrap(), None);
+        assert!(text_value(&ColumnValue::Binary(vec![1])).is_err());
+        assert!(text_value(&ColumnValue::UnchangedToast).is_err());
+    }
+}
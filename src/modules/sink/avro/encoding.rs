@@ -0,0 +1,183 @@
+use crate::modules::replication::dto::{ChangeEvent, ColumnValue, Transaction};
+use crate::modules::sink::avro::schema::{AvroType, TableSchemas};
+use crate::modules::sink::format::{operation_name, parse_date, parse_time, parse_timestamp, unix_micros};
+use std::borrow::Cow;
+
+// Confluent's wire format: magic byte 0, the schema id, then the Avro binary encoding
+pub fn frame(schema_id: i32, encoded: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(encoded.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&schema_id.to_be_bytes());
+    framed.extend_from_slice(encoded);
+    framed
+}
+
+// the Key record, the primary key columns of the new row or of the old one for deletes
+pub fn encode_key(schemas: &TableSchemas, event: &ChangeEvent) -> Option<Vec<u8>> {
+    schemas.key.as_ref()?;
+    let values = event.new.as_ref().or(event.old.as_ref())?;
+    let mut buf = Vec::new();
+    for ((column, value), kind) in event.columns.iter().zip(values).zip(&schemas.types) {
+        if column.primary_key {
+            put_column(&mut buf, *kind, value);
+        }
+    }
+
+    Some(buf)
+}
+
+// the Envelope record, fields in schema order
+pub fn encode_value(schemas: &TableSchemas, event: &ChangeEvent, transaction: &Transaction) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for row in [&event.old, &event.new] {
+        match row {
+            Some(values) => {
+                put_long(&mut buf, 1);
+                for (value, kind) in values.iter().zip(&schemas.types) {
+                    put_column(&mut buf, *kind, value);
+                }
+            },
+            None => put_long(&mut buf, 0),
+        }
+    }
+    put_string(&mut buf, operation_name(event.operation));
+    put_long(&mut buf, event.lsn.0 as i64);
+    put_long(&mut buf, transaction.commit_lsn.0 as i64);
+    put_long(&mut buf, transaction.xid as i64);
+    put_long(&mut buf, unix_micros(transaction.commit_time));
+    put_string(&mut buf, &event.schema);
+    put_string(&mut buf, &event.table);
+
+    buf
+}
+
+// a ["null", type] union; values that do not fit the type, like dates BC, are null
+fn put_column(buf: &mut Vec<u8>, kind: AvroType, value: &ColumnValue) {
+    let text = match value {
+        ColumnValue::Null | ColumnValue::UnchangedToast => return put_long(buf, 0),
+        ColumnValue::Binary(bytes) if kind == AvroType::Bytes => {
+            put_long(buf, 1);
+            return put_bytes(buf, bytes);
+        },
+        ColumnValue::Binary(bytes) => String::from_utf8_lossy(bytes),
+        ColumnValue::Text(text) => Cow::Borrowed(text.as_str()),
+    };
+    let text = text.as_ref();
+    let mut encoded = Vec::new();
+    let fits = match kind {
+        AvroType::Boolean => {
+            encoded.push((text == "t") as u8);
+            true
+        },
+        AvroType::Int => text.parse::<i32>().map(|v| put_long(&mut encoded, v as i64)).is_ok(),
+        AvroType::Long => text.parse::<i64>().map(|v| put_long(&mut encoded, v)).is_ok(),
+        AvroType::Float => text.parse::<f32>().map(|v| encoded.extend_from_slice(&v.to_le_bytes())).is_ok(),
+        AvroType::Double => text.parse::<f64>().map(|v| encoded.extend_from_slice(&v.to_le_bytes())).is_ok(),
+        AvroType::Bytes => text.strip_prefix("\\x").and_then(|hex| hex::decode(hex).ok())
+            .map(|bytes| put_bytes(&mut encoded, &bytes)).is_some(),
+        AvroType::String => {
+            put_string(&mut encoded, text);
+            true
+        },
+        AvroType::Date => infinity(text, i32::MAX as i64, i32::MIN as i64).or_else(|| parse_date(text))
+            .map(|days| put_long(&mut encoded, days)).is_some(),
+        AvroType::TimeMicros => parse_time(text).map(|micros| put_long(&mut encoded, micros)).is_some(),
+        AvroType::TimestampMicros | AvroType::LocalTimestampMicros => infinity(text, i64::MAX, i64::MIN)
+            .or_else(|| parse_timestamp(text, kind == AvroType::TimestampMicros))
+            .map(|micros| put_long(&mut encoded, micros)).is_some(),
+    };
+    if fits {
+        put_long(buf, 1);
+        buf.extend_from_slice(&encoded);
+    } else {
+        put_long(buf, 0);
+    }
+}
+
+// PostgreSQL's infinite dates and timestamps as the extremes of the type
+fn infinity(text: &str, max: i64, min: i64) -> Option<i64> {
+    match text {
+        "infinity" => Some(max),
+        "-infinity" => Some(min),
+        _ => None,
+    }
+}
+
+// int and long alike: zigzag, then a varint
+fn put_long(buf: &mut Vec<u8>, value: i64) {
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    while v >= 0x80 {
+        buf.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &str) {
+    put_bytes(buf, value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::replication::dto::{Column, Operation};
+    use crate::modules::replication::lsn::Lsn;
+
+    fn long(value: i64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_long(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn longs_are_zigzag_varints() {
+        assert_eq!(long(0), [0x00]);
+        assert_eq!(long(-1), [0x01]);
+        assert_eq!(long(1), [0x02]);
+        assert_eq!(long(-64), [0x7f]);
+        assert_eq!(long(64), [0x80, 0x01]);
+        assert_eq!(long(-8193), [0x81, 0x80, 0x01]);
+        assert_eq!(long(i64::MAX), [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        assert_eq!(long(i64::MIN), [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn strings_are_length_prefixed() {
+        let mut buf = Vec::new();
+        put_string(&mut buf, "héllo");
+        assert_eq!(buf, [0x0c, b'h', 0xc3, 0xa9, b'l', b'l', b'o']);
+    }
+
+    #[test]
+    fn frames_with_magic_byte_and_schema_id() {
+        assert_eq!(frame(258, &[0xaa, 0xbb]), [0, 0, 0, 1, 2, 0xaa, 0xbb]);
+        assert_eq!(frame(-1, &[]), [0, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn keys_hold_the_primary_key_columns() {
+        // REPLICA IDENTITY FULL: both columns are key, id alone is the primary key
+        let columns = vec![
+            Column { name: String::from("id"), type_oid: 20, type_modifier: -1, key: true, primary_key: true, type_name: None },
+            Column { name: String::from("note"), type_oid: 25, type_modifier: -1, key: true, primary_key: false, type_name: None },
+        ];
+        let schemas = TableSchemas::new("inv.public.orders", &columns).unwrap();
+        let delete = ChangeEvent {
+            lsn: Lsn(1),
+            xid: 1,
+            schema: String::from("public"),
+            table: String::from("orders"),
+            operation: Operation::Delete,
+            columns,
+            old: Some(vec![ColumnValue::Text(String::from("3")), ColumnValue::Text(String::from("a"))]),
+            new: None,
+        };
+        // union branch 1, then the long 3
+        assert_eq!(encode_key(&schemas, &delete), Some(vec![0x02, 0x06]));
+    }
+}
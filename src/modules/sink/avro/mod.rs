@@ -0,0 +1,4 @@
+pub mod encoding;
+pub mod registry;
+pub mod schema;
+pub mod serializer;
\ No newline at end of file
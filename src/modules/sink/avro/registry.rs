@@ -0,0 +1,132 @@
+use crate::modules::sink::http_client::{percent_encode, HttpClient, HttpResponse};
+use crate::modules::sink::sink_error::SinkError;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+const CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+pub const COMPATIBILITY_LEVELS: [&str; 7] = ["BACKWARD", "BACKWARD_TRANSITIVE", "FORWARD", "FORWARD_TRANSITIVE", "FULL",
+    "FULL_TRANSITIVE", "NONE"];
+
+// Registers schemas with a Confluent-compatible schema registry and remembers their ids. The
+// registry returns the id of an identical schema that is already registered, so registering
+// again after a restart is harmless
+pub struct SchemaRegistry {
+    client: HttpClient,
+    // set on every subject before its first registration, the registry default otherwise
+    compatibility: Option<String>,
+    configured: HashSet<String>,
+    // by subject and schema text
+    ids: HashMap<(String, String), i32>,
+}
+
+impl SchemaRegistry {
+    pub fn new(client: HttpClient, compatibility: Option<String>) -> SchemaRegistry {
+        SchemaRegistry { client, compatibility, configured: HashSet::new(), ids: HashMap::new() }
+    }
+
+    pub async fn register(&mut self, subject: &str, schema: &Value) -> Result<i32, SinkError> {
+        let schema = schema.to_string();
+        if let Some(id) = self.ids.get(&(subject.to_owned(), schema.clone())) {
+            return Ok(*id);
+        }
+        let path = format!("/subjects/{}", percent_encode(subject));
+        if let Some(compatibility) = self.compatibility.clone()
+            && !self.configured.contains(subject) {
+            let body = json!({ "compatibility": compatibility }).to_string();
+            let response = self.send("PUT", &format!("/config/{}", percent_encode(subject)), &body).await?;
+            check(&response, &format!("setting compatibility {} on subject {}", compatibility, subject))?;
+            self.configured.insert(subject.to_owned());
+        }
+
+        let response = self.send("POST", &format!("{}/versions", path), &json!({ "schema": schema }).to_string()).await?;
+        // 409: the schema breaks the compatibility rules of the subject
+        check(&response, &format!("registering a schema for subject {}", subject))?;
+        let id = serde_json::from_slice::<Value>(&response.body).ok()
+            .and_then(|body| body["id"].as_i64())
+            .ok_or_else(|| SinkError::Unavailable(format!("schema registry returned no id for subject {}", subject)))?;
+        info!(subject, id, "Registered schema");
+        self.ids.insert((subject.to_owned(), schema), id as i32);
+
+        Ok(id as i32)
+    }
+
+    async fn send(&mut self, method: &str, path: &str, body: &str) -> Result<HttpResponse, SinkError> {
+        let headers = [(String::from("Content-Type"), CONTENT_TYPE.to_owned()), (String::from("Accept"), CONTENT_TYPE.to_owned())];
+        self.client.request(method, path, &headers, body.as_bytes()).await
+    }
+}
+
+// a rejected schema or bad credentials are not worth a retry
+fn check(response: &HttpResponse, context: &str) -> Result<(), SinkError> {
+    if response.is_success() {
+        return Ok(());
+    }
+    let message = serde_json::from_slice::<Value>(&response.body).ok()
+        .and_then(|body| body["message"].as_str().map(str::to_owned))
+        .unwrap_or_else(|| String::from_utf8_lossy(&response.body).trim().to_owned());
+    Err(response.error(format!("schema registry failed {} with status {}: {}", context, response.status, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::replication::dto::Column;
+    use crate::modules::sink::avro::schema::TableSchemas;
+    use crate::modules::sink::http_client::mock::{response, MockServer};
+    use std::time::Duration;
+
+    async fn registry(responses: Vec<String>, compatibility: Option<&str>) -> (MockServer, SchemaRegistry) {
+        let server = MockServer::start(responses).await;
+        let client = HttpClient::new(&server.url, Duration::from_secs(5)).unwrap();
+        let registry = SchemaRegistry::new(client, compatibility.map(str::to_owned));
+        (server, registry)
+    }
+
+    fn schema(columns: &[&str]) -> Value {
+        let columns: Vec<Column> = columns.iter()
+            .map(|name| Column { name: name.to_string(), type_oid: 25, type_modifier: -1, key: *name == "id", primary_key: *name == "id", type_name: None })
+            .collect();
+        TableSchemas::new("inv.public.t", &columns).unwrap().value
+    }
+
+    #[tokio::test]
+    async fn registers_once_and_caches_the_id() {
+        let (server, mut registry) = registry(vec![response(200, r#"{"compatibility":"BACKWARD"}"#), response(200, r#"{"id":7}"#)],
+                                              Some("BACKWARD")).await;
+        let value = schema(&["id", "name"]);
+        assert_eq!(registry.register("inv.public.t-value", &value).await.unwrap(), 7);
+        assert_eq!(registry.register("inv.public.t-value", &value).await.unwrap(), 7);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], r#"PUT /config/inv.public.t-value {"compatibility":"BACKWARD"}"#);
+        assert!(requests[1].starts_with("POST /subjects/inv.public.t-value/versions {\"schema\":"));
+    }
+
+    #[tokio::test]
+    async fn registers_a_new_version_after_a_column_change() {
+        let (server, mut registry) = registry(vec![response(200, r#"{"id":1}"#), response(200, r#"{"id":2}"#)], None).await;
+        assert_eq!(registry.register("t-value", &schema(&["id", "name"])).await.unwrap(), 1);
+        assert_eq!(registry.register("t-value", &schema(&["id", "name", "note"])).await.unwrap(), 2);
+        assert_eq!(registry.register("t-value", &schema(&["id", "name"])).await.unwrap(), 1);
+        assert_eq!(server.requests().len(), 2);
+        assert!(server.requests()[1].contains("note"));
+    }
+
+    #[tokio::test]
+    async fn incompatible_schemas_are_rejected() {
+        let body = r#"{"error_code":409,"message":"Schema being registered is incompatible"}"#;
+        let (_server, mut registry) = registry(vec![response(409, body)], None).await;
+        match registry.register("t-value", &schema(&["id"])).await {
+            Err(SinkError::Rejected(message)) => assert!(message.ends_with("status 409: Schema being registered is incompatible"), "{}", message),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retryable() {
+        let (_server, mut registry) = registry(vec![response(503, "down"), response(200, r#"{"id":3}"#)], None).await;
+        assert!(matches!(registry.register("t-value", &schema(&["id"])).await, Err(SinkError::Unavailable(_))));
+        assert_eq!(registry.register("t-value", &schema(&["id"])).await.unwrap(), 3);
+    }
+}
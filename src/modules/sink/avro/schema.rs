@@ -0,0 +1,199 @@
+use crate::modules::replication::dto::Column;
+use crate::modules::sink::format::{BOOL_OID, BYTEA_OID, DATE_OID, FLOAT4_OID, FLOAT8_OID, INT2_OID, INT4_OID, INT8_OID, OID_OID,
+                                   TIMESTAMPTZ_OID, TIMESTAMP_OID, TIME_OID};
+use std::collections::HashMap;
+use serde_json::{json, Value};
+
+// Avro type of a column, with the logical type for dates and times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvroType {
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    // days since 1970-01-01
+    Date,
+    // microseconds since midnight
+    TimeMicros,
+    // microseconds since 1970-01-01 UTC, timestamptz
+    TimestampMicros,
+    // microseconds since 1970-01-01 in no particular zone, timestamp
+    LocalTimestampMicros,
+}
+
+impl AvroType {
+    pub fn of(column: &Column) -> AvroType {
+        match column.type_oid {
+            BOOL_OID => AvroType::Boolean,
+            INT2_OID | INT4_OID => AvroType::Int,
+            INT8_OID | OID_OID => AvroType::Long,
+            FLOAT4_OID => AvroType::Float,
+            FLOAT8_OID => AvroType::Double,
+            BYTEA_OID => AvroType::Bytes,
+            DATE_OID => AvroType::Date,
+            TIME_OID => AvroType::TimeMicros,
+            TIMESTAMP_OID => AvroType::LocalTimestampMicros,
+            TIMESTAMPTZ_OID => AvroType::TimestampMicros,
+            // numeric keeps its exact text, a decimal would need a fixed scale
+            _ => AvroType::String,
+        }
+    }
+
+    fn schema(self) -> Value {
+        match self {
+            AvroType::Boolean => json!("boolean"),
+            AvroType::Int => json!("int"),
+            AvroType::Long => json!("long"),
+            AvroType::Float => json!("float"),
+            AvroType::Double => json!("double"),
+            AvroType::Bytes => json!("bytes"),
+            AvroType::String => json!("string"),
+            AvroType::Date => json!({ "type": "int", "logicalType": "date" }),
+            AvroType::TimeMicros => json!({ "type": "long", "logicalType": "time-micros" }),
+            AvroType::TimestampMicros => json!({ "type": "long", "logicalType": "timestamp-micros" }),
+            AvroType::LocalTimestampMicros => json!({ "type": "long", "logicalType": "local-timestamp-micros" }),
+        }
+    }
+}
+
+// Key and value schemas of one table version, generated from the columns of its relation
+#[derive(Debug, Clone)]
+pub struct TableSchemas {
+    pub key: Option<Value>,
+    pub value: Value,
+    pub types: Vec<AvroType>,
+}
+
+impl TableSchemas {
+    // namespace is e.g. "inventory.public.orders", every part a valid Avro name; the key is the
+    // primary key, with REPLICA IDENTITY FULL the replica identity would be the whole row
+    pub fn new(namespace: &str, columns: &[Column]) -> Result<TableSchemas, String> {
+        let types: Vec<AvroType> = columns.iter().map(AvroType::of).collect();
+        let names = avro_names(columns)?;
+        // every column is nullable: unchanged TOAST values and the non-key columns of an old key tuple are null
+        let fields = |key_only: bool| -> Vec<Value> {
+            columns.iter().zip(&types).zip(&names)
+                .filter(|((column, _), _)| !key_only || column.primary_key)
+                .map(|((_, kind), name)| json!({ "name": name, "type": ["null", kind.schema()], "default": null }))
+                .collect()
+        };
+        let key_fields = fields(true);
+        let key = (!key_fields.is_empty())
+            .then(|| json!({ "type": "record", "name": "Key", "namespace": namespace, "fields": key_fields }));
+        let row = json!({ "type": "record", "name": "Value", "fields": fields(false) });
+        let value = json!({
+            "type": "record",
+            "name": "Envelope",
+            "namespace": namespace,
+            "fields": [
+                { "name": "before", "type": ["null", row], "default": null },
+                { "name": "after", "type": ["null", "Value"], "default": null },
+                { "name": "op", "type": "string" },
+                { "name": "lsn", "type": "long" },
+                { "name": "commit_lsn", "type": "long" },
+                { "name": "xid", "type": "long" },
+                { "name": "commit_time", "type": { "type": "long", "logicalType": "timestamp-micros" } },
+                { "name": "schema", "type": "string" },
+                { "name": "table", "type": "string" },
+            ],
+        });
+
+        Ok(TableSchemas { key, value, types })
+    }
+}
+
+// Avro names are [A-Za-z_][A-Za-z0-9_]*, anything else becomes '_'
+pub fn avro_name(name: &str) -> String {
+    let mut avro: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if !avro.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        avro.insert(0, '_');
+    }
+    avro
+}
+
+// the Avro names of a relation's columns, an error when two of them map to the same name
+pub fn avro_names(columns: &[Column]) -> Result<Vec<String>, String> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    columns.iter().map(|column| {
+        let name = avro_name(&column.name);
+        match seen.insert(name.clone(), &column.name) {
+            Some(other) => Err(format!("columns \"{}\" and \"{}\" would both be named {}, rename one of them", other, column.name, name)),
+            None => Ok(name),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::sink::format::NUMERIC_OID;
+
+    fn column(name: &str, type_oid: u32, key: bool) -> Column {
+        Column { name: name.to_owned(), type_oid, type_modifier: -1, key, primary_key: key, type_name: None }
+    }
+
+    fn schemas(columns: &[Column]) -> TableSchemas {
+        TableSchemas::new("inv.public.orders", columns).unwrap()
+    }
+
+    #[test]
+    fn generates_key_and_envelope_schemas() {
+        let columns = [column("id", 20, true), column("created-at", 1184, false), column("day", 1082, false),
+            column("price", NUMERIC_OID, false)];
+        let schemas = schemas(&columns);
+        assert_eq!(schemas.types, [AvroType::Long, AvroType::TimestampMicros, AvroType::Date, AvroType::String]);
+        assert_eq!(schemas.key, Some(json!({
+            "type": "record", "name": "Key", "namespace": "inv.public.orders",
+            "fields": [{ "name": "id", "type": ["null", "long"], "default": null }],
+        })));
+        let row = json!({
+            "type": "record", "name": "Value",
+            "fields": [
+                { "name": "id", "type": ["null", "long"], "default": null },
+                { "name": "created_at", "type": ["null", { "type": "long", "logicalType": "timestamp-micros" }], "default": null },
+                { "name": "day", "type": ["null", { "type": "int", "logicalType": "date" }], "default": null },
+                { "name": "price", "type": ["null", "string"], "default": null },
+            ],
+        });
+        assert_eq!(schemas.value["name"], "Envelope");
+        assert_eq!(schemas.value["namespace"], "inv.public.orders");
+        assert_eq!(schemas.value["fields"][0], json!({ "name": "before", "type": ["null", row], "default": null }));
+        assert_eq!(schemas.value["fields"][1], json!({ "name": "after", "type": ["null", "Value"], "default": null }));
+        let names: Vec<&str> = schemas.value["fields"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["before", "after", "op", "lsn", "commit_lsn", "xid", "commit_time", "schema", "table"]);
+    }
+
+    #[test]
+    fn tables_without_key_columns_have_no_key_schema() {
+        assert_eq!(schemas(&[column("v", 25, false)]).key, None);
+    }
+
+    #[test]
+    fn keys_are_the_primary_key_with_full_replica_identity() {
+        // REPLICA IDENTITY FULL flags every column as key
+        let mut columns = [column("id", 20, true), column("note", 25, true)];
+        columns[1].primary_key = false;
+        let key = schemas(&columns).key.unwrap();
+        assert_eq!(key["fields"], json!([{ "name": "id", "type": ["null", "long"], "default": null }]));
+
+        columns[0].primary_key = false;
+        assert_eq!(schemas(&columns).key, None);
+    }
+
+    #[test]
+    fn avro_names_replace_invalid_characters() {
+        assert_eq!(avro_name("order id"), "order_id");
+        assert_eq!(avro_name("1st"), "_1st");
+        assert_eq!(avro_name("größe"), "gr__e");
+    }
+
+    #[test]
+    fn columns_with_the_same_avro_name_are_rejected() {
+        let columns = [column("id", 20, true), column("order id", 25, false), column("order_id", 25, false)];
+        assert_eq!(TableSchemas::new("inv.public.orders", &columns).unwrap_err(),
+                   "columns \"order id\" and \"order_id\" would both be named order_id, rename one of them");
+    }
+}
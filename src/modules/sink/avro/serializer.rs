@@ -0,0 +1,108 @@
+use crate::modules::replication::dto::{ChangeEvent, Column, Transaction};
+use crate::modules::secret::SecretString;
+use crate::modules::sink::avro::encoding::{encode_key, encode_value, frame};
+use crate::modules::sink::avro::registry::SchemaRegistry;
+use crate::modules::sink::avro::schema::{avro_name, TableSchemas};
+use crate::modules::sink::http_client::HttpClient;
+use crate::modules::sink::sink_error::SinkError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::info;
+
+// how subjects are named, as Confluent's serializers do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectStrategy {
+    // <topic>-key and <topic>-value
+    #[default]
+    Topic,
+    // the fully qualified record name, e.g. inventory.public.orders.Envelope
+    Record,
+    // <topic>-<record name>, for topics that carry several tables
+    TopicRecord,
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaRegistryConfig {
+    // http://host:port, with a path prefix if the registry is behind one
+    pub url: String,
+    // basic authentication
+    pub credentials: Option<(String, SecretString)>,
+    // BACKWARD, FORWARD, FULL, their _TRANSITIVE variants or NONE
+    pub compatibility: Option<String>,
+    pub subject_strategy: SubjectStrategy,
+}
+
+// schemas of the current columns of a table and their ids
+struct Registered {
+    columns: Vec<Column>,
+    schemas: TableSchemas,
+    key_id: Option<i32>,
+    value_id: i32,
+}
+
+// Encodes events as Avro in Confluent's wire format. The schemas follow the columns of the
+// relation: when a Relation message changes them, the next event registers a new version
+pub struct AvroSerializer {
+    pipeline: String,
+    strategy: SubjectStrategy,
+    registry: SchemaRegistry,
+    // by topic, schema and table
+    registered: HashMap<(String, String, String), Registered>,
+}
+
+impl AvroSerializer {
+    pub fn new(pipeline: &str, config: &SchemaRegistryConfig, timeout: Duration) -> Result<AvroSerializer, SinkError> {
+        let mut client = HttpClient::new(&config.url, timeout).map_err(SinkError::Rejected)?;
+        if let Some((username, password)) = &config.credentials {
+            client = client.with_basic_auth(username, password);
+        }
+
+        Ok(AvroSerializer {
+            pipeline: pipeline.to_owned(),
+            strategy: config.subject_strategy,
+            registry: SchemaRegistry::new(client, config.compatibility.clone()),
+            registered: HashMap::new(),
+        })
+    }
+
+    // the framed key, None without key columns, and the framed value
+    pub async fn encode(&mut self, topic: &str, event: &ChangeEvent, transaction: &Transaction)
+                        -> Result<(Option<Vec<u8>>, Vec<u8>), SinkError> {
+        let table = (topic.to_owned(), event.schema.clone(), event.table.clone());
+        if self.registered.get(&table).is_none_or(|registered| registered.columns != event.columns) {
+            let registered = self.register(topic, event).await?;
+            self.registered.insert(table.clone(), registered);
+        }
+        let registered = &self.registered[&table];
+        let key = registered.key_id.zip(encode_key(&registered.schemas, event)).map(|(id, key)| frame(id, &key));
+        let value = frame(registered.value_id, &encode_value(&registered.schemas, event, transaction));
+
+        Ok((key, value))
+    }
+
+    async fn register(&mut self, topic: &str, event: &ChangeEvent) -> Result<Registered, SinkError> {
+        let namespace = format!("{}.{}.{}", avro_name(&self.pipeline), avro_name(&event.schema), avro_name(&event.table));
+        if self.registered.keys().any(|(t, schema, table)| t == topic && schema == &event.schema && table == &event.table) {
+            info!(table = %format!("{}.{}", event.schema, event.table), topic, "Columns changed, registering new schema versions");
+        }
+        let schemas = TableSchemas::new(&namespace, &event.columns)
+            .map_err(|e| SinkError::Rejected(format!("no Avro schema for {}.{}: {}", event.schema, event.table, e)))?;
+        let key_id = match &schemas.key {
+            Some(key) => Some(self.registry.register(&self.subject(topic, &namespace, "Key", "key"), key).await?),
+            None => None,
+        };
+        let value_id = self.registry.register(&self.subject(topic, &namespace, "Envelope", "value"), &schemas.value).await?;
+
+        Ok(Registered { columns: event.columns.clone(), schemas, key_id, value_id })
+    }
+
+    fn subject(&self, topic: &str, namespace: &str, record: &str, suffix: &str) -> String {
+        match self.strategy {
+            SubjectStrategy::Topic => format!("{}-{}", topic, suffix),
+            SubjectStrategy::Record => format!("{}.{}", namespace, record),
+            SubjectStrategy::TopicRecord => format!("{}-{}.{}", topic, namespace, record),
+        }
+    }
+}
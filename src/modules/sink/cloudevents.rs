@@ -0,0 +1,40 @@
+use crate::modules::replication::dto::{ChangeEvent, Transaction};
+use crate::modules::sink::format::{format_timestamp, operation_name, JsonEvent};
+use crate::modules::sink::http_client::percent_encode;
+use serde_json::{Map, Value};
+
+pub const SPEC_VERSION: &str = "1.0";
+// of the data, the JSON event
+pub const DATA_CONTENT_TYPE: &str = "application/json";
+// of a structured mode event, attributes and data in one JSON object
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+// The context attributes of a change. source names the server by its system identifier, the
+// database and the table; id is the commit LSN of the transaction and the position of the event
+// in it, so a change delivered again after a restart keeps its id and consumers can deduplicate
+pub fn cloud_event_attributes(event: &ChangeEvent, transaction: &Transaction, index: usize, system_id: &str,
+                              database: &str) -> Vec<(&'static str, String)> {
+    let source = ["postgresql", system_id, database, &event.schema, &event.table].iter()
+        .map(|segment| percent_encode(segment))
+        .collect::<Vec<_>>()
+        .join("/");
+
+    vec![
+        ("specversion", SPEC_VERSION.to_owned()),
+        ("id", format!("{}:{}", transaction.commit_lsn, index)),
+        ("source", format!("/{}", source)),
+        ("type", format!("io.cyphercdc.{}", operation_name(event.operation))),
+        ("time", format_timestamp(transaction.commit_time)),
+    ]
+}
+
+// the JSON format of CloudEvents: the attributes, the content type and the event as data
+pub fn structured_event(attributes: &[(&'static str, String)], data: JsonEvent) -> Result<Value, serde_json::Error> {
+    let mut event: Map<String, Value> = attributes.iter()
+        .map(|(name, value)| (name.to_string(), Value::String(value.clone())))
+        .collect();
+    event.insert(String::from("datacontenttype"), Value::String(DATA_CONTENT_TYPE.to_owned()));
+    event.insert(String::from("data"), serde_json::to_value(data)?);
+
+    Ok(Value::Object(event))
+}
@@ -0,0 +1,217 @@
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::sink::format::{format_unix_micros, parse_date, parse_time, parse_timestamp, unix_millis, BOOL_OID, BYTEA_OID,
+                                   DATE_OID, FLOAT4_OID, FLOAT8_OID, INT2_OID, INT4_OID, INT8_OID, JSONB_OID, JSON_OID, OID_OID,
+                                   TIMESTAMPTZ_OID, TIMESTAMP_OID, TIME_OID, UUID_OID};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Debezium's placeholder for TOASTed values the server did not send
+const UNAVAILABLE_VALUE: &str = "__debezium_unavailable_value";
+
+// Debezium's PostgreSQL connector envelope: before, after, source, op and ts_ms, with the
+// schema section of the JSON converter when schemas are enabled. name is the logical server
+// name, Debezium's topic.prefix
+pub fn debezium_value(event: &ChangeEvent, transaction: &Transaction, name: &str, database: &str, schemas: bool) -> Value {
+    let row = |values: &Option<Vec<ColumnValue>>| values.as_ref().map(|values| row_value(&event.columns, values));
+    let payload = json!({
+        "before": row(&event.old),
+        "after": row(&event.new),
+        "source": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "connector": "postgresql",
+            "name": name,
+            "ts_ms": unix_millis(transaction.commit_time),
+            "snapshot": if event.operation == Operation::Read { "true" } else { "false" },
+            "db": database,
+            "sequence": json!([Value::Null, event.lsn.0.to_string()]).to_string(),
+            "schema": event.schema,
+            "table": event.table,
+            "txId": transaction.xid,
+            "lsn": event.lsn.0,
+            "xmin": Value::Null,
+        },
+        "op": operation_code(event.operation),
+        "ts_ms": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default(),
+        "transaction": Value::Null,
+    });
+    if !schemas {
+        return payload;
+    }
+    let prefix = format!("{}.{}.{}", name, event.schema, event.table);
+    let row_schema = |field: &str| {
+        let mut schema = struct_schema(&format!("{}.Value", prefix), true, columns_schema(&event.columns, false));
+        schema["field"] = json!(field);
+        schema
+    };
+    let mut source = struct_schema("io.debezium.connector.postgresql.Source", false, source_fields());
+    source["field"] = json!("source");
+    let fields = vec![row_schema("before"), row_schema("after"), source, field("op", "string", false, None),
+                      field("ts_ms", "int64", true, None),
+                      json!({ "type": "struct", "optional": true, "field": "transaction", "fields": [
+                          field("id", "string", false, None), field("total_order", "int64", false, None),
+                          field("data_collection_order", "int64", false, None)] })];
+
+    json!({ "schema": struct_schema(&format!("{}.Envelope", prefix), false, fields), "payload": payload })
+}
+
+// the primary key columns like Debezium, whatever the replica identity; None without a primary
+// key or for truncates
+pub fn debezium_key(event: &ChangeEvent, name: &str, schemas: bool) -> Option<Value> {
+    let values = event.new.as_ref().or(event.old.as_ref())?;
+    let (columns, values): (Vec<Column>, Vec<ColumnValue>) = event.columns.iter().zip(values)
+        .filter(|(column, _)| column.primary_key)
+        .map(|(column, value)| (column.clone(), value.clone()))
+        .unzip();
+    if columns.is_empty() {
+        return None;
+    }
+    let payload = row_value(&columns, &values);
+    if !schemas {
+        return Some(payload);
+    }
+    let schema = struct_schema(&format!("{}.{}.{}.Key", name, event.schema, event.table), false, columns_schema(&columns, true));
+
+    Some(json!({ "schema": schema, "payload": payload }))
+}
+
+fn operation_code(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Insert => "c",
+        Operation::Update => "u",
+        Operation::Delete => "d",
+        Operation::Read => "r",
+        Operation::Truncate => "t",
+    }
+}
+
+fn row_value(columns: &[Column], values: &[ColumnValue]) -> Value {
+    let row: Map<String, Value> = columns.iter().zip(values)
+        .map(|(column, value)| (column.name.clone(), column_value(column, value)))
+        .collect();
+
+    Value::Object(row)
+}
+
+// Values as Debezium's defaults produce them: time.precision.mode=adaptive_time_microseconds,
+// binary.handling.mode=bytes; numerics as with decimal.handling.mode=string
+fn column_value(column: &Column, value: &ColumnValue) -> Value {
+    let text = match value {
+        ColumnValue::Null => return Value::Null,
+        ColumnValue::UnchangedToast => return json!(UNAVAILABLE_VALUE),
+        ColumnValue::Binary(bytes) => return json!(STANDARD.encode(bytes)),
+        ColumnValue::Text(text) => text,
+    };
+    let converted = match column.type_oid {
+        BOOL_OID => Some(json!(text == "t")),
+        INT8_OID | INT2_OID | INT4_OID | OID_OID => text.parse::<i64>().ok().map(Value::from),
+        FLOAT4_OID | FLOAT8_OID => text.parse::<f64>().ok().filter(|f| f.is_finite()).map(Value::from),
+        // bytea in its hex text form
+        BYTEA_OID => text.strip_prefix("\\x").and_then(|hex| hex::decode(hex).ok()).map(|bytes| json!(STANDARD.encode(bytes))),
+        DATE_OID => parse_date(text).map(Value::from),
+        TIME_OID => parse_time(text).map(Value::from),
+        TIMESTAMP_OID => parse_timestamp(text, false).map(Value::from),
+        TIMESTAMPTZ_OID => parse_timestamp(text, true).map(|micros| json!(format_unix_micros(micros))),
+        _ => None,
+    };
+
+    converted.unwrap_or_else(|| json!(text))
+}
+
+// Kafka Connect type and Debezium logical type name of a column
+fn column_type(column: &Column) -> (&'static str, Option<&'static str>) {
+    match column.type_oid {
+        BOOL_OID => ("boolean", None),
+        INT2_OID => ("int16", None),
+        INT4_OID => ("int32", None),
+        INT8_OID | OID_OID => ("int64", None),
+        FLOAT4_OID => ("float", None),
+        FLOAT8_OID => ("double", None),
+        BYTEA_OID => ("bytes", None),
+        DATE_OID => ("int32", Some("io.debezium.time.Date")),
+        TIME_OID => ("int64", Some("io.debezium.time.MicroTime")),
+        TIMESTAMP_OID => ("int64", Some("io.debezium.time.MicroTimestamp")),
+        TIMESTAMPTZ_OID => ("string", Some("io.debezium.time.ZonedTimestamp")),
+        JSON_OID | JSONB_OID => ("string", Some("io.debezium.data.Json")),
+        UUID_OID => ("string", Some("io.debezium.data.Uuid")),
+        _ => ("string", None),
+    }
+}
+
+// pgoutput does not report NOT NULL, only primary key columns are known to be required
+fn columns_schema(columns: &[Column], key: bool) -> Vec<Value> {
+    columns.iter().map(|column| {
+        let (kind, name) = column_type(column);
+        field(&column.name, kind, !(key || column.primary_key), name)
+    }).collect()
+}
+
+fn source_fields() -> Vec<Value> {
+    vec![field("version", "string", false, None), field("connector", "string", false, None),
+         field("name", "string", false, None), field("ts_ms", "int64", false, None),
+         field("snapshot", "string", true, Some("io.debezium.data.Enum")), field("db", "string", false, None),
+         field("sequence", "string", true, None), field("schema", "string", false, None),
+         field("table", "string", false, None), field("txId", "int64", true, None),
+         field("lsn", "int64", true, None), field("xmin", "int64", true, None)]
+}
+
+fn field(name: &str, kind: &str, optional: bool, logical: Option<&str>) -> Value {
+    let mut field = json!({ "type": kind, "optional": optional, "field": name });
+    if let Some(logical) = logical {
+        field["name"] = json!(logical);
+    }
+    field
+}
+
+fn struct_schema(name: &str, optional: bool, fields: Vec<Value>) -> Value {
+    json!({ "type": "struct", "fields": fields, "optional": optional, "name": name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::replication::lsn::Lsn;
+
+    fn delete_with_full_identity() -> ChangeEvent {
+        // REPLICA IDENTITY FULL flags every column as key, id alone is the primary key
+        let column = |name: &str, type_oid, primary_key| Column {
+            name: name.to_owned(), type_oid, type_modifier: -1, key: true, primary_key, type_name: None,
+        };
+        ChangeEvent {
+            lsn: Lsn(0x16B_3748),
+            xid: 740,
+            schema: String::from("public"),
+            table: String::from("orders"),
+            operation: Operation::Delete,
+            columns: vec![column("id", INT4_OID, true), column("placed", DATE_OID, false)],
+            old: Some(vec![ColumnValue::Text(String::from("7")), ColumnValue::Text(String::from("1970-01-02"))]),
+            new: None,
+        }
+    }
+
+    #[test]
+    fn keys_on_the_primary_key() {
+        let event = delete_with_full_identity();
+        assert_eq!(debezium_key(&event, "inv", false), Some(json!({ "id": 7 })));
+        assert_eq!(debezium_key(&event, "inv", true), Some(json!({
+            "schema": { "type": "struct", "optional": false, "name": "inv.public.orders.Key", "fields": [
+                { "type": "int32", "optional": false, "field": "id" }] },
+            "payload": { "id": 7 },
+        })));
+
+        let mut keyless = event;
+        keyless.columns[0].primary_key = false;
+        assert_eq!(debezium_key(&keyless, "inv", false), None);
+    }
+
+    #[test]
+    fn only_primary_key_columns_are_required() {
+        let event = delete_with_full_identity();
+        assert_eq!(columns_schema(&event.columns, false), vec![
+            json!({ "type": "int32", "optional": false, "field": "id" }),
+            json!({ "type": "int32", "optional": true, "field": "placed", "name": "io.debezium.time.Date" }),
+        ]);
+        assert_eq!(row_value(&event.columns, event.old.as_ref().unwrap()), json!({ "id": 7, "placed": 1 }));
+    }
+}
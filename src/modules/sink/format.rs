@@ -0,0 +1,512 @@
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::sink::cloudevents::{cloud_event_attributes, structured_event, DATA_CONTENT_TYPE, STRUCTURED_CONTENT_TYPE};
+use crate::modules::sink::debezium::{debezium_key, debezium_value};
+use crate::modules::sink::protobuf::descriptor::{DescriptorCache, TableDescriptor};
+use crate::modules::sink::protobuf::encoding::{encode_change, encode_key};
+use crate::modules::sink::sink_error::SinkError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+
+// oids of the built-in types the formats convert, assigned by initdb and the same on every server;
+// in JSON events booleans, numbers and json keep their type, every other type stays in its text form
+pub(crate) const BOOL_OID: u32 = 16;
+pub(crate) const BYTEA_OID: u32 = 17;
+pub(crate) const INT8_OID: u32 = 20;
+pub(crate) const INT2_OID: u32 = 21;
+pub(crate) const INT4_OID: u32 = 23;
+pub(crate) const OID_OID: u32 = 26;
+pub(crate) const JSON_OID: u32 = 114;
+pub(crate) const FLOAT4_OID: u32 = 700;
+pub(crate) const FLOAT8_OID: u32 = 701;
+pub(crate) const DATE_OID: u32 = 1082;
+pub(crate) const TIME_OID: u32 = 1083;
+pub(crate) const TIMESTAMP_OID: u32 = 1114;
+pub(crate) const TIMESTAMPTZ_OID: u32 = 1184;
+pub(crate) const NUMERIC_OID: u32 = 1700;
+pub(crate) const UUID_OID: u32 = 2950;
+pub(crate) const JSONB_OID: u32 = 3802;
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+// days between 1970-01-01 and 2000-01-01
+const POSTGRES_EPOCH_DAYS: i64 = 10_957;
+const POSTGRES_EPOCH_MILLIS: i64 = POSTGRES_EPOCH_DAYS * 86_400_000;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventFormat {
+    // cyphercdc's own flat JSON, see JsonEvent
+    #[default]
+    Json,
+    // the envelope of Debezium's PostgreSQL connector, for existing Debezium consumers; schemas
+    // adds the JSON converter's schema sections
+    Debezium { schemas: bool },
+    // the JSON event wrapped in a CloudEvents 1.0 envelope
+    CloudEvents { mode: CloudEventsMode },
+    // binary Protobuf, a Change message per event with a Row message generated from the table's columns
+    Protobuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudEventsMode {
+    // the whole event is the value, attributes included
+    #[default]
+    Structured,
+    // the value is the data only, attributes travel as ce_ (Kafka) or ce- (HTTP) headers
+    Binary,
+}
+
+// Serializes keys and values of change events in the configured format. name, database and
+// the system identifier of the source server name the source in Debezium and CloudEvents
+#[derive(Debug)]
+pub struct EventEncoder {
+    format: EventFormat,
+    name: String,
+    database: String,
+    system_id: String,
+    // Protobuf only, key, value and headers of an event share one descriptor
+    descriptors: Mutex<DescriptorCache>,
+}
+
+impl EventEncoder {
+    pub fn new(format: EventFormat, name: &str, database: &str) -> EventEncoder {
+        EventEncoder {
+            format,
+            name: name.to_owned(),
+            database: database.to_owned(),
+            system_id: String::new(),
+            descriptors: Mutex::new(DescriptorCache::default()),
+        }
+    }
+
+    // Protobuf field numbers kept in a file instead of in memory
+    pub fn with_descriptors(mut self, descriptors: DescriptorCache) -> EventEncoder {
+        self.descriptors = Mutex::new(descriptors);
+        self
+    }
+
+    pub fn set_system_id(&mut self, system_id: &str) {
+        self.system_id = system_id.to_owned();
+    }
+
+    // index is the position of the event in its transaction
+    pub fn value(&self, event: &ChangeEvent, transaction: &Transaction, index: usize) -> Result<Vec<u8>, SinkError> {
+        match self.format {
+            EventFormat::Json | EventFormat::CloudEvents { mode: CloudEventsMode::Binary } => {
+                Ok(serde_json::to_vec(&json_event(event, transaction))?)
+            },
+            EventFormat::Debezium { schemas } => {
+                Ok(serde_json::to_vec(&debezium_value(event, transaction, &self.name, &self.database, schemas))?)
+            },
+            EventFormat::CloudEvents { mode: CloudEventsMode::Structured } => {
+                Ok(serde_json::to_vec(&structured_event(&self.attributes(event, transaction, index), json_event(event, transaction))?)?)
+            },
+            EventFormat::Protobuf => Ok(encode_change(&*self.descriptor(event)?, event, transaction, &self.name, &self.database)),
+        }
+    }
+
+    pub fn key(&self, event: &ChangeEvent) -> Result<Option<Vec<u8>>, SinkError> {
+        let key = match self.format {
+            EventFormat::Json | EventFormat::CloudEvents { .. } => key_json(event),
+            EventFormat::Debezium { schemas } => debezium_key(event, &self.name, schemas),
+            EventFormat::Protobuf => return Ok(encode_key(&*self.descriptor(event)?, event)),
+        };
+        Ok(key.map(|key| serde_json::to_vec(&key)).transpose()?)
+    }
+
+    // Headers that describe the value: the content type for CloudEvents and Protobuf, plus every
+    // CloudEvents attribute in binary mode, named with prefix ("ce_" for Kafka, "ce-" for HTTP)
+    pub fn headers(&self, event: &ChangeEvent, transaction: &Transaction, index: usize, prefix: &str) -> Result<Vec<(String, String)>, SinkError> {
+        Ok(match self.format {
+            EventFormat::Json | EventFormat::Debezium { .. } => Vec::new(),
+            EventFormat::CloudEvents { mode: CloudEventsMode::Structured } => {
+                vec![(String::from("content-type"), String::from(STRUCTURED_CONTENT_TYPE))]
+            },
+            EventFormat::CloudEvents { mode: CloudEventsMode::Binary } => {
+                let mut headers = vec![(String::from("content-type"), String::from(DATA_CONTENT_TYPE))];
+                headers.extend(self.attributes(event, transaction, index).into_iter()
+                    .map(|(name, value)| (format!("{}{}", prefix, name), value)));
+                headers
+            },
+            // the message type, so consumers of a topic with several tables know which one to decode
+            EventFormat::Protobuf => {
+                let message = self.descriptor(event)?.message_name("Change");
+                vec![(String::from("content-type"), format!("{}; messageType={}", PROTOBUF_CONTENT_TYPE, message))]
+            },
+        })
+    }
+
+    fn descriptor(&self, event: &ChangeEvent) -> Result<Arc<TableDescriptor>, SinkError> {
+        self.descriptors.lock().unwrap()
+            .descriptor(&self.name, &event.schema, &event.table, &event.columns)
+            .map_err(SinkError::Rejected)
+    }
+
+    fn attributes(&self, event: &ChangeEvent, transaction: &Transaction, index: usize) -> Vec<(&'static str, String)> {
+        cloud_event_attributes(event, transaction, index, &self.system_id, &self.database)
+    }
+}
+
+// One JSON object per change: position, transaction, table, operation, key columns and the
+// old and new row. Unchanged TOAST columns are left out of "after", the server did not send them
+#[derive(Serialize)]
+pub struct JsonEvent<'a> {
+    lsn: Lsn,
+    commit_lsn: Lsn,
+    xid: u32,
+    commit_time: String,
+    schema: &'a str,
+    table: &'a str,
+    op: &'static str,
+    key: Vec<&'a str>,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+pub fn json_event<'a>(event: &'a ChangeEvent, transaction: &Transaction) -> JsonEvent<'a> {
+    JsonEvent {
+        lsn: event.lsn,
+        commit_lsn: transaction.commit_lsn,
+        xid: transaction.xid,
+        commit_time: format_timestamp(transaction.commit_time),
+        schema: &event.schema,
+        table: &event.table,
+        op: operation_name(event.operation),
+        key: event.columns.iter().filter(|c| c.key).map(|c| c.name.as_str()).collect(),
+        before: event.old.as_ref().map(|values| row_json(&event.columns, values)),
+        after: event.new.as_ref().map(|values| row_json(&event.columns, values)),
+    }
+}
+
+pub fn operation_name(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Insert => "insert",
+        Operation::Update => "update",
+        Operation::Delete => "delete",
+        Operation::Read => "read",
+        Operation::Truncate => "truncate",
+    }
+}
+
+// primary key columns of the new row, or of the old one for deletes; None without a primary
+// key, the replica identity is no key since with REPLICA IDENTITY FULL it is the whole row
+pub fn key_json(event: &ChangeEvent) -> Option<Value> {
+    let values = event.new.as_ref().or(event.old.as_ref())?;
+    let key: Map<String, Value> = event.columns.iter().zip(values)
+        .filter(|(column, _)| column.primary_key)
+        .map(|(column, value)| (column.name.clone(), column_json(column, value)))
+        .collect();
+
+    if key.is_empty() { None } else { Some(Value::Object(key)) }
+}
+
+pub fn row_json(columns: &[Column], values: &[ColumnValue]) -> Value {
+    let mut row = Map::new();
+    for (column, value) in columns.iter().zip(values) {
+        match value {
+            ColumnValue::UnchangedToast => {},
+            value => {
+                row.insert(column.name.clone(), column_json(column, value));
+            },
+        }
+    }
+
+    Value::Object(row)
+}
+
+pub fn column_json(column: &Column, value: &ColumnValue) -> Value {
+    let text = match value {
+        ColumnValue::Null | ColumnValue::UnchangedToast => return Value::Null,
+        ColumnValue::Binary(bytes) => return Value::String(format!("\\x{}", hex::encode(bytes))),
+        ColumnValue::Text(text) => text,
+    };
+    let typed = match column.type_oid {
+        BOOL_OID => Some(Value::Bool(text == "t")),
+        INT2_OID | INT4_OID | INT8_OID | OID_OID => text.parse::<i64>().ok().map(Value::from),
+        // NaN and Infinity have no JSON number
+        FLOAT4_OID | FLOAT8_OID => text.parse::<f64>().ok().filter(|f| f.is_finite()).map(Value::from),
+        JSON_OID | JSONB_OID => serde_json::from_str(text).ok(),
+        _ => None,
+    };
+
+    typed.unwrap_or_else(|| Value::String(text.clone()))
+}
+
+// milliseconds since 1970-01-01 from microseconds since 2000-01-01
+pub fn unix_millis(postgres_micros: i64) -> i64 {
+    postgres_micros.div_euclid(1000) + POSTGRES_EPOCH_MILLIS
+}
+
+// microseconds since 1970-01-01 from microseconds since 2000-01-01
+pub fn unix_micros(postgres_micros: i64) -> i64 {
+    postgres_micros + POSTGRES_EPOCH_MILLIS * 1000
+}
+
+// RFC 3339 in UTC from microseconds since 2000-01-01, the protocol's timestamps
+pub fn format_timestamp(postgres_micros: i64) -> String {
+    format_unix_micros(unix_micros(postgres_micros))
+}
+
+// RFC 3339 in UTC from microseconds since 1970-01-01
+pub fn format_unix_micros(unix_micros: i64) -> String {
+    let seconds = unix_micros.div_euclid(1_000_000);
+    let micros = unix_micros.rem_euclid(1_000_000);
+    let days = seconds.div_euclid(86_400);
+    let second_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z", year, month, day,
+            second_of_day / 3600, second_of_day % 3600 / 60, second_of_day % 60, micros)
+}
+
+// proleptic Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// days since 1970-01-01 of a proleptic Gregorian date, the inverse of civil_from_days
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+// "2026-10-19", or "0044-03-15 BC", as days since 1970-01-01; None for infinity
+pub fn parse_date(text: &str) -> Option<i64> {
+    // 1 BC is year 0 of the proleptic Gregorian calendar
+    let (text, bc) = match text.strip_suffix(" BC") {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    let mut parts = text.splitn(3, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok().filter(|month| (1..=12).contains(month))?;
+    let day = parts.next()?.parse().ok().filter(|day| (1..=31).contains(day))?;
+    let year = if bc { 1 - year as i64 } else { year as i64 };
+
+    Some(days_from_civil(year, month, day))
+}
+
+// "07:10:56.50228" as microseconds since midnight
+pub fn parse_time(text: &str) -> Option<i64> {
+    let (hms, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let mut parts = hms.splitn(3, ':');
+    // 24:00:00 is a valid time
+    let hours: i64 = parts.next()?.parse().ok().filter(|hours| (0..=24).contains(hours))?;
+    let minutes: i64 = parts.next()?.parse().ok().filter(|minutes| (0..60).contains(minutes))?;
+    let seconds: i64 = parts.next()?.parse().ok().filter(|seconds| (0..=60).contains(seconds))?;
+    let micros: i64 = if fraction.is_empty() { 0 } else { format!("{:0<6}", fraction).get(..6)?.parse().ok()? };
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1_000_000 + micros)
+}
+
+// "2026-10-19 07:10:56.50228", with an offset like "+02" or "-05:30" for timestamptz and " BC"
+// at the end before year 1, as microseconds since 1970-01-01 UTC; None for infinity and for
+// the last years PostgreSQL accepts, beyond what 64 bits of microseconds since 1970 hold
+pub fn parse_timestamp(text: &str, with_zone: bool) -> Option<i64> {
+    let (text, era) = match text.strip_suffix(" BC") {
+        Some(text) => (text, " BC"),
+        None => (text, ""),
+    };
+    let (date, time) = text.split_once(' ')?;
+    let (time, offset_seconds) = if with_zone {
+        let split = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(split);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let mut seconds = 0;
+        for (i, part) in offset[1..].split(':').enumerate() {
+            seconds += part.parse::<i64>().ok()? * [3600, 60, 1].get(i)?;
+        }
+        (time, sign * seconds)
+    } else {
+        (time, 0)
+    };
+
+    parse_date(&format!("{}{}", date, era))?.checked_mul(MICROS_PER_DAY)?
+        .checked_add(parse_time(time)?)?
+        .checked_sub(offset_seconds * 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, type_oid: u32, key: bool, primary_key: bool) -> Column {
+        Column { name: name.to_owned(), type_oid, type_modifier: -1, key, primary_key, type_name: None }
+    }
+
+    fn event(operation: Operation, columns: Vec<Column>, old: Option<Vec<&str>>, new: Option<Vec<&str>>) -> ChangeEvent {
+        let row = |values: Vec<&str>| values.into_iter().map(|v| ColumnValue::Text(v.to_owned())).collect();
+        ChangeEvent {
+            lsn: Lsn(0x16B_3748),
+            xid: 740,
+            schema: String::from("public"),
+            table: String::from("orders"),
+            operation,
+            columns,
+            old: old.map(row),
+            new: new.map(row),
+        }
+    }
+
+    #[test]
+    fn keys_are_the_primary_key_whatever_the_replica_identity() {
+        // REPLICA IDENTITY FULL flags every column as key
+        let full = vec![column("id", INT4_OID, true, true), column("note", 25, true, false)];
+        let update = event(Operation::Update, full.clone(), Some(vec!["1", "a"]), Some(vec!["1", "b"]));
+        assert_eq!(key_json(&update), Some(serde_json::json!({"id": 1})));
+        let delete = event(Operation::Delete, full, Some(vec!["1", "b"]), None);
+        assert_eq!(key_json(&delete), Some(serde_json::json!({"id": 1})));
+
+        let keyless = vec![column("id", INT4_OID, true, false), column("note", 25, true, false)];
+        assert_eq!(key_json(&event(Operation::Insert, keyless, None, Some(vec!["1", "a"]))), None);
+    }
+
+    fn transaction(events: Vec<ChangeEvent>) -> Transaction {
+        // one second past the PostgreSQL epoch
+        Transaction { xid: 740, commit_lsn: Lsn(0x1_016B_3748), end_lsn: Lsn(0x1_016B_3780), commit_time: 1_000_000, events }
+    }
+
+    fn cloud_events(mode: CloudEventsMode) -> EventEncoder {
+        let mut encoder = EventEncoder::new(EventFormat::CloudEvents { mode }, "inv", "my db");
+        encoder.set_system_id("7412345678901234567");
+        encoder
+    }
+
+    #[test]
+    fn cloud_event_ids_are_the_commit_lsn_and_the_position() {
+        let columns = vec![column("id", INT4_OID, true, true)];
+        let insert = event(Operation::Insert, columns, None, Some(vec!["1"]));
+        let transaction = transaction(vec![insert.clone(), insert.clone(), insert.clone()]);
+        let id = |index| cloud_event_attributes(&insert, &transaction, index, "1", "db").into_iter()
+            .find(|(name, _)| *name == "id").map(|(_, value)| value);
+        assert_eq!(id(0).as_deref(), Some("1/16B3748:0"));
+        assert_eq!(id(2).as_deref(), Some("1/16B3748:2"));
+    }
+
+    #[test]
+    fn cloud_event_attributes_name_the_source_type_and_commit_time() {
+        let columns = vec![column("id", INT4_OID, true, true)];
+        let delete = event(Operation::Delete, columns, Some(vec!["1"]), None);
+        let transaction = transaction(vec![delete.clone()]);
+        let attributes = cloud_event_attributes(&delete, &transaction, 0, "7412345678901234567", "my db");
+        let attribute = |name| attributes.iter().find(|(n, _)| *n == name).map(|(_, value)| value.as_str());
+        assert_eq!(attribute("specversion"), Some("1.0"));
+        assert_eq!(attribute("source"), Some("/postgresql/7412345678901234567/my%20db/public/orders"));
+        assert_eq!(attribute("type"), Some("io.cyphercdc.delete"));
+        assert_eq!(attribute("time"), Some("2000-01-01T00:00:01.000000Z"));
+    }
+
+    #[test]
+    fn structured_cloud_events_carry_the_attributes_in_the_value() {
+        let columns = vec![column("id", INT4_OID, true, true)];
+        let insert = event(Operation::Insert, columns, None, Some(vec!["1"]));
+        let transaction = transaction(vec![insert.clone()]);
+        let encoder = cloud_events(CloudEventsMode::Structured);
+
+        assert_eq!(encoder.headers(&insert, &transaction, 0, "ce-").unwrap(),
+                   vec![(String::from("content-type"), String::from("application/cloudevents+json"))]);
+        let value: Value = serde_json::from_slice(&encoder.value(&insert, &transaction, 0).unwrap()).unwrap();
+        assert_eq!(value["id"], "1/16B3748:0");
+        assert_eq!(value["type"], "io.cyphercdc.insert");
+        assert_eq!(value["datacontenttype"], "application/json");
+        assert_eq!(value["data"]["after"], serde_json::json!({"id": 1}));
+    }
+
+    #[test]
+    fn binary_cloud_events_carry_the_attributes_in_headers() {
+        let columns = vec![column("id", INT4_OID, true, true)];
+        let insert = event(Operation::Insert, columns, None, Some(vec!["1"]));
+        let transaction = transaction(vec![insert.clone()]);
+        let encoder = cloud_events(CloudEventsMode::Binary);
+
+        let headers = encoder.headers(&insert, &transaction, 0, "ce_").unwrap();
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["content-type", "ce_specversion", "ce_id", "ce_source", "ce_type", "ce_time"]);
+        assert_eq!(headers[0].1, "application/json");
+        assert_eq!(headers[2].1, "1/16B3748:0");
+        // the value is the plain JSON event
+        let json = EventEncoder::new(EventFormat::Json, "inv", "my db");
+        assert_eq!(encoder.value(&insert, &transaction, 0).unwrap(), json.value(&insert, &transaction, 0).unwrap());
+    }
+
+    #[test]
+    fn dates_round_trip_through_civil_days() {
+        for days in [-2_440_588, -719_528, -1, 0, 1, 10_957, 20_745, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-01-01"), Some(10_957));
+        assert_eq!(parse_date("1969-12-31"), Some(-1));
+        // 1 BC is year 0, the earliest date PostgreSQL accepts is julian day 0
+        assert_eq!(parse_date("0001-01-01 BC"), Some(days_from_civil(0, 1, 1)));
+        assert_eq!(parse_date("0044-03-15 BC"), Some(days_from_civil(-43, 3, 15)));
+        assert_eq!(parse_date("4714-11-24 BC"), Some(-2_440_588));
+        assert_eq!(parse_date("5874897-12-31"), Some(days_from_civil(5_874_897, 12, 31)));
+
+        assert_eq!(parse_date("infinity"), None);
+        assert_eq!(parse_date("-infinity"), None);
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("2026-00-01"), None);
+        assert_eq!(parse_date("2026-01-32"), None);
+        assert_eq!(parse_date("99999999999-01-01"), None);
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("00:00:00"), Some(0));
+        assert_eq!(parse_time("07:10:56.50228"), Some(((7 * 60 + 10) * 60 + 56) * 1_000_000 + 502_280));
+        assert_eq!(parse_time("23:59:59.999999"), Some(MICROS_PER_DAY - 1));
+        assert_eq!(parse_time("24:00:00"), Some(MICROS_PER_DAY));
+
+        assert_eq!(parse_time("25:00:00"), None);
+        assert_eq!(parse_time("12:60:00"), None);
+        assert_eq!(parse_time("99999999999999999:00:00"), None);
+        assert_eq!(parse_time("12:00"), None);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00", false), Some(0));
+        assert_eq!(parse_timestamp("2000-01-01 00:00:00.5", false), Some(POSTGRES_EPOCH_MILLIS * 1000 + 500_000));
+        assert_eq!(parse_timestamp("1969-12-31 23:59:59", false), Some(-1_000_000));
+        assert_eq!(parse_timestamp("1970-01-01 02:00:00+02", true), Some(0));
+        assert_eq!(parse_timestamp("1969-12-31 18:30:00-05:30", true), Some(0));
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00.000001+00", true), Some(1));
+        assert_eq!(parse_timestamp("0044-03-15 12:00:00+00 BC", true), Some(days_from_civil(-43, 3, 15) * MICROS_PER_DAY + 43_200_000_000));
+        assert_eq!(parse_timestamp("4714-11-24 00:00:00 BC", false), Some(-2_440_588 * MICROS_PER_DAY));
+        assert_eq!(parse_timestamp("2026-10-19 07:10:56.50228", false).map(format_unix_micros).as_deref(), Some("2026-10-19T07:10:56.502280Z"));
+
+        assert_eq!(parse_timestamp("infinity", false), None);
+        assert_eq!(parse_timestamp("-infinity", true), None);
+        // PostgreSQL goes up to 294276 AD, past the i64 microseconds since 1970
+        assert_eq!(parse_timestamp("294276-12-31 23:59:59.999999", false), None);
+        assert_eq!(parse_timestamp("294276-12-31 23:59:59.999999+00", true), None);
+        assert_eq!(parse_timestamp("2026-10-19 07:10:56", true), None);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::modules::secret::SecretString;
+use crate::modules::sink::sink_error::SinkError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::debug;
+
+// responses larger than this are a broken or hostile server
+const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    // the error of a failed response: server errors, timeouts and rate limits are worth a retry,
+    // anything else is a rejection
+    pub fn error(&self, message: String) -> SinkError {
+        if self.status >= 500 || self.status == 408 || self.status == 429 {
+            SinkError::Unavailable(message)
+        } else {
+            SinkError::Rejected(message)
+        }
+    }
+}
+
+// A minimal HTTP/1.1 client for one plain http:// origin, keeping its connection alive between requests
+pub struct HttpClient {
+    // host:port to connect to
+    address: String,
+    host: String,
+    // path prefix of the URL, without the trailing slash
+    base_path: String,
+    timeout: Duration,
+    authorization: Option<SecretString>,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl HttpClient {
+    pub fn new(url: &str, timeout: Duration) -> Result<HttpClient, String> {
+        let rest = url.strip_prefix("http://")
+            .ok_or_else(|| format!("invalid URL '{}', only http:// is supported", url))?;
+        let (authority, path) = rest.split_once('/').map_or((rest, ""), |(a, p)| (a, p));
+        if authority.is_empty() || authority.contains('@') {
+            return Err(format!("invalid URL '{}', expected http://host[:port][/path]", url));
+        }
+        let address = if authority.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            authority.to_owned()
+        } else {
+            format!("{}:80", authority)
+        };
+
+        Ok(HttpClient {
+            address,
+            host: authority.to_owned(),
+            base_path: format!("/{}", path.trim_end_matches('/')).trim_end_matches('/').to_owned(),
+            timeout,
+            authorization: None,
+            connection: None,
+        })
+    }
+
+    pub fn with_basic_auth(mut self, username: &str, password: &SecretString) -> HttpClient {
+        let credentials = STANDARD.encode(format!("{}:{}", username, password.expose()));
+        self.authorization = Some(SecretString::new(format!("Basic {}", credentials)));
+        self
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.host, self.base_path)
+    }
+
+    // path is relative to the URL the client was created with
+    pub async fn request(&mut self, method: &str, path: &str, headers: &[(String, String)], body: &[u8])
+                         -> Result<HttpResponse, SinkError> {
+        let mut message = format!("{} {}{} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n",
+                                  method, self.base_path, path, self.host, body.len());
+        if let Some(authorization) = &self.authorization {
+            message.push_str(&format!("Authorization: {}\r\n", authorization.expose()));
+        }
+        for (name, value) in headers {
+            message.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        message.push_str("\r\n");
+        let mut message = message.into_bytes();
+        message.extend_from_slice(body);
+
+        // a kept alive connection may have been closed by the server in the meantime, one new connection is tried
+        let reused = self.connection.is_some();
+        let result = match timeout(self.timeout, self.exchange(&message)).await {
+            Ok(result) => result,
+            Err(_) => Err(SinkError::Unavailable(format!("{} did not answer within {:?}", self.url(), self.timeout))),
+        };
+        let result = match result {
+            Err(e) if reused => {
+                debug!(url = %self.url(), "Retrying on a new connection: {}", e);
+                self.connection = None;
+                timeout(self.timeout, self.exchange(&message)).await
+                    .unwrap_or_else(|_| Err(SinkError::Unavailable(format!("{} did not answer within {:?}", self.url(), self.timeout))))
+            },
+            result => result,
+        };
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+
+    async fn exchange(&mut self, message: &[u8]) -> Result<HttpResponse, SinkError> {
+        if self.connection.is_none() {
+            let stream = TcpStream::connect(&self.address).await
+                .map_err(|e| SinkError::Unavailable(format!("could not connect to {}: {}", self.url(), e)))?;
+            stream.set_nodelay(true)?;
+            self.connection = Some(BufReader::new(stream));
+        }
+        let connection = self.connection.as_mut().unwrap();
+        connection.get_mut().write_all(message).await?;
+
+        let status_line = read_line(connection).await?;
+        let status = status_line.split_whitespace().nth(1).and_then(|s| s.parse::<u16>().ok())
+            .ok_or_else(|| SinkError::Unavailable(format!("invalid HTTP status line '{}'", status_line.trim_end())))?;
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(connection).await?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+        let response_header = |name: &str| headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.to_ascii_lowercase());
+        let chunked = response_header("transfer-encoding").is_some_and(|v| v.contains("chunked"));
+        let length = response_header("content-length").and_then(|v| v.parse::<usize>().ok());
+        let close = response_header("connection").is_some_and(|v| v == "close");
+
+        let mut body = Vec::new();
+        if chunked {
+            loop {
+                let line = read_line(connection).await?;
+                let size = usize::from_str_radix(line.split(';').next().unwrap_or_default().trim(), 16)
+                    .map_err(|_| SinkError::Unavailable(String::from("invalid HTTP chunk size")))?;
+                if size == 0 {
+                    // trailers up to the empty line
+                    while !read_line(connection).await?.is_empty() {}
+                    break;
+                }
+                if body.len().checked_add(size).is_none_or(|total| total > MAX_RESPONSE_BYTES) {
+                    return Err(SinkError::Unavailable(String::from("HTTP response too large")));
+                }
+                let start = body.len();
+                body.resize(start + size, 0);
+                connection.read_exact(&mut body[start..]).await?;
+                read_line(connection).await?;
+            }
+        } else if let Some(length) = length {
+            if length > MAX_RESPONSE_BYTES {
+                return Err(SinkError::Unavailable(String::from("HTTP response too large")));
+            }
+            body.resize(length, 0);
+            connection.read_exact(&mut body).await?;
+        } else if status != 204 && status != 304 {
+            // delimited by the end of the connection
+            connection.take(MAX_RESPONSE_BYTES as u64).read_to_end(&mut body).await?;
+            self.connection = None;
+        }
+        if close {
+            self.connection = None;
+        }
+
+        Ok(HttpResponse { status, headers, body })
+    }
+}
+
+// a line without its line break, end of stream is an error
+async fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String, SinkError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(SinkError::Unavailable(String::from("connection closed by the HTTP server")));
+    }
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+// percent-encodes everything but RFC 3986 unreserved characters, for a path segment
+pub fn percent_encode(segment: &str) -> String {
+    segment.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+
+// An HTTP server on a local port for tests: answers the requests with the given raw responses in
+// order, on whichever connection they arrive, and records "METHOD path body" and the headers of
+// each request. an empty response is never answered, for timeouts
+#[cfg(test)]
+pub mod mock {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    type Shared<T> = Arc<Mutex<T>>;
+
+    pub struct MockServer {
+        pub url: String,
+        requests: Shared<Vec<String>>,
+        headers: Shared<Vec<Vec<(String, String)>>>,
+    }
+
+    impl MockServer {
+        pub async fn start(responses: Vec<String>) -> MockServer {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let headers = Arc::new(Mutex::new(Vec::new()));
+            let recorded = (requests.clone(), headers.clone());
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, responses.clone(), recorded.0.clone(), recorded.1.clone()));
+                }
+            });
+
+            MockServer { url, requests, headers }
+        }
+
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        pub fn headers(&self) -> Vec<Vec<(String, String)>> {
+            self.headers.lock().unwrap().clone()
+        }
+    }
+
+    pub fn response(status: u16, body: &str) -> String {
+        format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body)
+    }
+
+    async fn serve(stream: TcpStream, responses: Shared<VecDeque<String>>, requests: Shared<Vec<String>>,
+                   headers: Shared<Vec<Vec<(String, String)>>>) {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let mut length = 0;
+            let mut request_headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    request_headers.push((name.to_owned(), value.trim().to_owned()));
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let target: Vec<&str> = request_line.split_whitespace().take(2).collect();
+            requests.lock().unwrap().push(format!("{} {}", target.join(" "), String::from_utf8_lossy(&body)));
+            headers.lock().unwrap().push(request_headers);
+
+            let Some(response) = responses.lock().unwrap().pop_front() else { return };
+            if response.is_empty() {
+                std::future::pending::<()>().await;
+            }
+            if reader.get_mut().write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{response, MockServer};
+    use super::*;
+
+    #[tokio::test]
+    async fn reuses_the_connection_and_reads_chunked_bodies() {
+        let chunked = String::from("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n");
+        let server = MockServer::start(vec![response(201, "first"), chunked]).await;
+        let mut client = HttpClient::new(&format!("{}/base/", server.url), Duration::from_secs(5)).unwrap();
+        let first = client.request("POST", "/a", &[], b"one").await.unwrap();
+        assert_eq!((first.status, first.body.as_slice()), (201, &b"first"[..]));
+        let second = client.request("GET", "/b", &[], b"").await.unwrap();
+        assert_eq!((second.status, second.body.as_slice()), (200, &b"abcde"[..]));
+        assert_eq!(server.requests(), vec!["POST /base/a one", "GET /base/b "]);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_chunk_size_that_overflows() {
+        let hostile = String::from("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n");
+        let server = MockServer::start(vec![hostile]).await;
+        let mut client = HttpClient::new(&server.url, Duration::from_secs(5)).unwrap();
+        match client.request("GET", "/", &[], b"").await {
+            Err(SinkError::Unavailable(message)) => assert_eq!(message, "HTTP response too large"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn retries_server_errors_timeouts_and_rate_limits() {
+        let error = |status| HttpResponse { status, headers: Vec::new(), body: Vec::new() }.error(String::from("failed")).is_retryable();
+        assert!([500, 502, 503, 408, 429].into_iter().all(error));
+        assert!(![400, 401, 403, 404, 409, 422].into_iter().any(error));
+    }
+}
\ No newline at end of file
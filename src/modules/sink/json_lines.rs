@@ -0,0 +1,338 @@
+use crate::modules::replication::dto::Transaction;
+use crate::modules::replication::message_utils::postgres_epoch_micros;
+use crate::modules::sink::format::{format_timestamp, EventEncoder, EventFormat};
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JsonLinesConfig {
+    // stdout when not set
+    pub path: Option<PathBuf>,
+    pub rotate_size: Option<u64>,
+    pub rotate_interval: Option<Duration>,
+    // applied to rotated files, the active file is always plain
+    pub compression: Compression,
+    pub format: EventFormat,
+}
+
+// Writes one JSON object per change event to stdout or to a file. The file is rotated by size or
+// age between transactions, and flush fsyncs it, so an acknowledged LSN is on disk
+pub struct JsonLinesSink {
+    config: JsonLinesConfig,
+    encoder: EventEncoder,
+    active: Option<ActiveFile>,
+    compressor: Option<Compressor>,
+}
+
+struct ActiveFile {
+    writer: BufWriter<File>,
+    size: u64,
+    opened: Instant,
+}
+
+impl ActiveFile {
+    // appends, events redelivered after a restart follow the ones already written
+    fn open(path: &Path) -> io::Result<ActiveFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(ActiveFile { writer: BufWriter::new(file), size, opened: Instant::now() })
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+}
+
+impl JsonLinesSink {
+    pub fn new(pipeline: &str, database: &str, config: JsonLinesConfig) -> Result<JsonLinesSink, SinkError> {
+        let active = match &config.path {
+            Some(path) => Some(ActiveFile::open(path)?),
+            None => None,
+        };
+        let encoder = EventEncoder::new(config.format, pipeline, database);
+        let compressor = (config.path.is_some() && config.compression != Compression::None).then(|| Compressor::start(config.compression));
+
+        Ok(JsonLinesSink { config, encoder, active, compressor })
+    }
+
+    fn rotation_due(&self) -> bool {
+        let Some(active) = &self.active else { return false };
+        active.size > 0 && (self.config.rotate_size.is_some_and(|size| active.size >= size)
+            || self.config.rotate_interval.is_some_and(|interval| active.opened.elapsed() >= interval))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let (Some(path), Some(active)) = (&self.config.path, &mut self.active) else { return Ok(()) };
+        active.sync()?;
+        // a reopen that failed after the rename left the path free, the old file is rotated already
+        let mut rotated = None;
+        if path.exists() {
+            let target = rotated_path(path);
+            fs::rename(path, &target)?;
+            info!(file = %target.display(), "Rotated JSON lines file");
+            rotated = Some(target);
+        }
+        self.active = Some(ActiveFile::open(path)?);
+        sync_directory(path)?;
+
+        // the rotated file is complete on disk, compressing it does not hold up replication
+        if let (Some(compressor), Some(rotated)) = (&self.compressor, rotated) {
+            compressor.compress(rotated);
+        }
+
+        Ok(())
+    }
+}
+
+// Compresses rotated files on its own thread, a large file would otherwise delay the replies to
+// the server's keepalives. A file that fails is tried again with the next one
+struct Compressor {
+    files: Option<Sender<PathBuf>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Compressor {
+    fn start(compression: Compression) -> Compressor {
+        let (files, received) = mpsc::channel::<PathBuf>();
+        let worker = thread::spawn(move || {
+            let mut pending = Vec::new();
+            for file in received {
+                pending.push(file);
+                pending = compress_pending(pending, compression);
+            }
+        });
+
+        Compressor { files: Some(files), worker: Some(worker) }
+    }
+
+    fn compress(&self, file: PathBuf) {
+        if let Some(files) = &self.files {
+            // only fails when the worker panicked, the file stays plain
+            let _ = files.send(file);
+        }
+    }
+
+    // waits for the files sent so far
+    fn finish(&mut self) {
+        self.files = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// compresses every file, returns the ones to try again; a file that is gone is not retried
+fn compress_pending(files: Vec<PathBuf>, compression: Compression) -> Vec<PathBuf> {
+    let mut failed = Vec::new();
+    for file in files {
+        match compress(&file, compression) {
+            Ok(_) => {},
+            Err(_) if !file.exists() => {
+                warn!(file = %file.display(), "Rotated JSON lines file no longer exists, not compressing it");
+            },
+            Err(e) => {
+                warn!(file = %file.display(), error = %e, "Could not compress rotated JSON lines file, retrying with the next one");
+                failed.push(file);
+            },
+        }
+    }
+    failed
+}
+
+#[async_trait]
+impl Sink for JsonLinesSink {
+    fn name(&self) -> &str {
+        "json_lines"
+    }
+
+    fn identify(&mut self, system_id: &str) {
+        self.encoder.set_system_id(system_id);
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        if self.rotation_due() {
+            self.rotate()?;
+        }
+        let mut lines = Vec::new();
+        for (index, event) in batch.events.iter().enumerate() {
+            lines.extend(self.encoder.value(event, batch, index)?);
+            lines.push(b'\n');
+        }
+        match &mut self.active {
+            Some(active) => {
+                active.writer.write_all(&lines)?;
+                active.size += lines.len() as u64;
+            },
+            None => std::io::stdout().lock().write_all(&lines)?,
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        match &mut self.active {
+            Some(active) => active.sync()?,
+            None => std::io::stdout().flush()?,
+        }
+
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.flush().await?;
+        if let Some(compressor) = &mut self.compressor {
+            compressor.finish();
+        }
+
+        Ok(())
+    }
+}
+
+// "changes.jsonl" becomes "changes.20261019T070713Z.jsonl", with a counter if that exists already
+fn rotated_path(path: &Path) -> PathBuf {
+    let stamp: String = format_timestamp(postgres_epoch_micros(SystemTime::now()))[..19].chars()
+        .filter(|c| *c != '-' && *c != ':').collect();
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let mut counter = 0;
+    loop {
+        let suffix = if counter == 0 { String::new() } else { format!("-{}", counter) };
+        let candidate = path.with_file_name(format!("{}.{}Z{}{}", stem, stamp, suffix, extension));
+        if !candidate.exists() && !compressed_path(&candidate, Compression::Gzip).exists()
+            && !compressed_path(&candidate, Compression::Zstd).exists() {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+fn compressed_path(path: &Path, compression: Compression) -> PathBuf {
+    let extension = match compression {
+        Compression::None => return path.to_path_buf(),
+        Compression::Gzip => "gz",
+        Compression::Zstd => "zst",
+    };
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+// compresses into a temporary file that is renamed once complete, then removes the plain file
+fn compress(path: &Path, compression: Compression) -> io::Result<PathBuf> {
+    if compression == Compression::None {
+        return Ok(path.to_path_buf());
+    }
+    let target = compressed_path(path, compression);
+    let temporary = target.with_extension("tmp");
+    let mut source = File::open(path)?;
+    let output = BufWriter::new(File::create(&temporary)?);
+    let output = match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?
+        },
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, 0)?;
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?
+        },
+        Compression::None => unreachable!(),
+    };
+    output.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temporary, &target)?;
+    fs::remove_file(path)?;
+
+    Ok(target)
+}
+
+fn sync_directory(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(directory) => File::open(directory)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(directory).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("cyphercdc-json-lines-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn rotation_compresses_in_the_background_and_survives_a_failed_reopen() {
+        let directory = directory("rotation");
+        let path = directory.join("changes.jsonl");
+        let config = JsonLinesConfig { path: Some(path.clone()), compression: Compression::Gzip, ..JsonLinesConfig::default() };
+        let mut sink = JsonLinesSink::new("test", "db", config).unwrap();
+        sink.active.as_mut().unwrap().writer.write_all(b"{}\n").unwrap();
+
+        sink.rotate().unwrap();
+        // the path is gone as after a failed reopen: the rotation only reopens it
+        fs::remove_file(&path).unwrap();
+        sink.rotate().unwrap();
+        assert!(path.exists());
+
+        sink.compressor.as_mut().unwrap().finish();
+        let names = files(&directory);
+        assert_eq!(names.len(), 2);
+        assert!(names[0].ends_with(".jsonl.gz"));
+        assert_eq!(names[1], "changes.jsonl");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn removed_files_are_not_retried() {
+        let directory = directory("pending");
+        let plain = directory.join("changes.1.jsonl");
+        fs::write(&plain, b"{}\n").unwrap();
+        // compressing into a directory in the way fails until it is removed
+        let blocked = directory.join("changes.2.jsonl");
+        fs::write(&blocked, b"{}\n").unwrap();
+        fs::create_dir(compressed_path(&blocked, Compression::Zstd).with_extension("tmp")).unwrap();
+        let missing = directory.join("changes.gone.jsonl");
+
+        let failed = compress_pending(vec![plain, blocked.clone(), missing], Compression::Zstd);
+        assert_eq!(failed, vec![blocked]);
+        assert_eq!(files(&directory), ["changes.1.jsonl.zst", "changes.2.jsonl", "changes.2.jsonl.tmp"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use crate::modules::logging::PROTOCOL_TARGET;
+use crate::modules::sink::kafka::protocol::{metadata_request, parse_metadata, request, Metadata, METADATA, METADATA_VERSION};
+use crate::modules::sink::sink_error::SinkError;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::{debug, trace};
+
+// Connections to the brokers of one cluster and what it last told us about leaders and partitions
+pub struct KafkaClient {
+    bootstrap: Vec<String>,
+    client_id: String,
+    timeout: Duration,
+    correlation_id: i32,
+    // by broker address
+    connections: HashMap<String, TcpStream>,
+    brokers: HashMap<i32, String>,
+    // leader per partition, None while a partition has no leader
+    leaders: HashMap<String, Vec<Option<i32>>>,
+}
+
+impl KafkaClient {
+    pub fn new(bootstrap: Vec<String>, client_id: &str, timeout: Duration) -> KafkaClient {
+        KafkaClient {
+            bootstrap,
+            client_id: client_id.to_owned(),
+            timeout,
+            correlation_id: 0,
+            connections: HashMap::new(),
+            brokers: HashMap::new(),
+            leaders: HashMap::new(),
+        }
+    }
+
+    pub fn partition_count(&self, topic: &str) -> Option<usize> {
+        self.leaders.get(topic).map(Vec::len).filter(|count| *count > 0)
+    }
+
+    pub fn leader(&self, topic: &str, partition: i32) -> Option<i32> {
+        self.leaders.get(topic).and_then(|leaders| leaders.get(partition as usize).copied().flatten())
+    }
+
+    pub fn forget_connections(&mut self) {
+        self.connections.clear();
+    }
+
+    // Asks the known brokers, then the bootstrap list, for the leaders of the topics
+    pub async fn refresh_metadata(&mut self, topics: &[String]) -> Result<Metadata, SinkError> {
+        let mut addresses: Vec<String> = self.brokers.values().cloned().collect();
+        addresses.extend(self.bootstrap.iter().cloned());
+        let body = metadata_request(topics);
+        let mut last_error = SinkError::Unavailable(String::from("no Kafka broker configured"));
+        for address in addresses {
+            match self.send_to(&address, METADATA, METADATA_VERSION, &body).await {
+                Ok(response) => {
+                    let metadata = parse_metadata(&response)?;
+                    self.apply(&metadata);
+                    return Ok(metadata);
+                },
+                Err(e) => {
+                    debug!(broker = %address, "Metadata request failed: {}", e);
+                    last_error = e;
+                },
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn apply(&mut self, metadata: &Metadata) {
+        for broker in &metadata.brokers {
+            self.brokers.insert(broker.node_id, format!("{}:{}", broker.host, broker.port));
+        }
+        for topic in &metadata.topics {
+            let leaders = topic.partitions.iter()
+                .map(|p| if p.error_code == 0 && p.leader >= 0 { Some(p.leader) } else { None })
+                .collect();
+            self.leaders.insert(topic.name.clone(), leaders);
+        }
+    }
+
+    // sends to the broker with the node id and returns the response body
+    pub async fn send(&mut self, node_id: i32, api_key: i16, api_version: i16, body: &[u8]) -> Result<Vec<u8>, SinkError> {
+        let address = self.brokers.get(&node_id).cloned()
+            .ok_or_else(|| SinkError::Unavailable(format!("unknown Kafka broker {}", node_id)))?;
+        self.send_to(&address, api_key, api_version, body).await
+    }
+
+    // any broker will do, e.g. for InitProducerId of an idempotent producer
+    pub async fn send_any(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Result<Vec<u8>, SinkError> {
+        let mut addresses: Vec<String> = self.brokers.values().cloned().collect();
+        addresses.sort();
+        addresses.extend(self.bootstrap.iter().cloned());
+        let mut last_error = SinkError::Unavailable(String::from("no Kafka broker configured"));
+        for address in addresses {
+            match self.send_to(&address, api_key, api_version, body).await {
+                Ok(response) => return Ok(response),
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn send_to(&mut self, address: &str, api_key: i16, api_version: i16, body: &[u8]) -> Result<Vec<u8>, SinkError> {
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        let correlation_id = self.correlation_id;
+        let message = request(api_key, api_version, correlation_id, &self.client_id, body);
+        let result = timeout(self.timeout, self.exchange(address, &message)).await
+            .unwrap_or_else(|_| Err(SinkError::Unavailable(format!("Kafka broker {} did not answer within {:?}", address, self.timeout))));
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                // the connection state is unknown after a failed exchange
+                self.connections.remove(address);
+                return Err(e);
+            },
+        };
+        trace!(target: PROTOCOL_TARGET, broker = %address, api_key, bytes = response.len(), "Kafka response");
+        if response.len() < 4 || i32::from_be_bytes(response[..4].try_into().unwrap()) != correlation_id {
+            self.connections.remove(address);
+            return Err(SinkError::Unavailable(format!("unexpected response from Kafka broker {}", address)));
+        }
+
+        Ok(response[4..].to_vec())
+    }
+
+    async fn exchange(&mut self, address: &str, message: &[u8]) -> Result<Vec<u8>, SinkError> {
+        if !self.connections.contains_key(address) {
+            let stream = TcpStream::connect(address).await
+                .map_err(|e| SinkError::Unavailable(format!("could not connect to Kafka broker {}: {}", address, e)))?;
+            stream.set_nodelay(true)?;
+            debug!(broker = %address, "Connected to Kafka broker");
+            self.connections.insert(address.to_owned(), stream);
+        }
+        let stream = self.connections.get_mut(address).unwrap();
+        stream.write_all(message).await?;
+        let size = stream.read_i32().await?;
+        if size < 4 {
+            return Err(SinkError::Unavailable(format!("invalid response size {} from Kafka broker {}", size, address)));
+        }
+        let mut response = vec![0u8; size as usize];
+        stream.read_exact(&mut response).await?;
+
+        Ok(response)
+    }
+}
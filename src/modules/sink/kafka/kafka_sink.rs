@@ -0,0 +1,294 @@
+use crate::modules::pipeline::filter::TablePattern;
+use crate::modules::replication::dto::{ChangeEvent, Operation, Transaction};
+use crate::modules::sink::avro::serializer::{AvroSerializer, SchemaRegistryConfig};
+use crate::modules::sink::format::{unix_millis, EventEncoder, EventFormat};
+use crate::modules::sink::json_lines::Compression;
+use crate::modules::sink::kafka::client::KafkaClient;
+use crate::modules::sink::kafka::protocol::{init_producer_id_request, is_retriable, kafka_error, parse_init_producer_id, parse_produce,
+                                            produce_request, PartitionData, DUPLICATE_SEQUENCE_NUMBER, INIT_PRODUCER_ID,
+                                            INIT_PRODUCER_ID_VERSION, NONE, PRODUCE, PRODUCE_VERSION};
+use crate::modules::sink::kafka::record_batch::{chunks, encode_batch, murmur2, ProducerId, Record};
+use crate::modules::sink::protobuf::descriptor::DescriptorCache;
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_TOPIC: &str = "{pipeline}.{schema}.{table}";
+// well below the 1MB message.max.bytes brokers default to
+pub const DEFAULT_BATCH_SIZE: &str = "256KB";
+// the broker keeps producer state this long without activity
+const TRANSACTION_TIMEOUT_MS: i32 = 60_000;
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    // bootstrap "host:port" list
+    pub brokers: Vec<String>,
+    // topic name template, {pipeline}, {schema} and {table} are replaced
+    pub topic: String,
+    // the first route whose pattern matches the table overrides topic
+    pub routes: Vec<(TablePattern, String)>,
+    pub client_id: String,
+    // -1 waits for all in-sync replicas, 1 for the leader only
+    pub acks: i16,
+    pub idempotent: bool,
+    pub compression: Compression,
+    // records are sent once this many bytes are pending, at the latest on flush; also the
+    // size a record batch is kept under, which has to stay below the broker's message.max.bytes
+    pub batch_size: usize,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub format: EventFormat,
+    // Avro with schemas from this registry instead of format
+    pub avro: Option<SchemaRegistryConfig>,
+    // a record without value after each delete, so log compaction can drop the key
+    pub tombstones: bool,
+    // Protobuf only, keeps the field number of every column across restarts
+    pub field_numbers: Option<PathBuf>,
+}
+
+// Produces every change to a topic chosen per table, keyed by the primary key. flush returns once
+// the broker acknowledged every record written before it; an idempotent producer lets the broker
+// drop batches that are sent again after a timeout
+pub struct KafkaSink {
+    pipeline: String,
+    config: KafkaConfig,
+    encoder: EventEncoder,
+    avro: Option<AvroSerializer>,
+    client: KafkaClient,
+    producer: Option<ProducerId>,
+    // next sequence number per topic partition, for the idempotent producer
+    sequences: HashMap<(String, i32), i32>,
+    pending: BTreeMap<(String, i32), Vec<Record>>,
+    pending_bytes: usize,
+}
+
+impl KafkaSink {
+    pub fn new(pipeline: &str, database: &str, config: KafkaConfig) -> Result<KafkaSink, SinkError> {
+        let avro = config.avro.as_ref().map(|registry| AvroSerializer::new(pipeline, registry, config.timeout)).transpose()?;
+        let mut encoder = EventEncoder::new(config.format, pipeline, database);
+        if let Some(path) = &config.field_numbers {
+            let descriptors = DescriptorCache::open(path.clone())
+                .map_err(|e| SinkError::Rejected(format!("could not read protobuf field numbers from {}: {}", path.display(), e)))?;
+            encoder = encoder.with_descriptors(descriptors);
+        }
+
+        Ok(KafkaSink {
+            pipeline: pipeline.to_owned(),
+            encoder,
+            avro,
+            client: KafkaClient::new(config.brokers.clone(), &config.client_id, config.timeout),
+            config,
+            producer: None,
+            sequences: HashMap::new(),
+            pending: BTreeMap::new(),
+            pending_bytes: 0,
+        })
+    }
+
+    fn topic(&self, event: &ChangeEvent) -> String {
+        let template = self.config.routes.iter()
+            .find(|(pattern, _)| pattern.matches(&event.schema, &event.table))
+            .map(|(_, topic)| topic)
+            .unwrap_or(&self.config.topic);
+        let name = template.replace("{pipeline}", &self.pipeline)
+            .replace("{schema}", &event.schema)
+            .replace("{table}", &event.table);
+        // Kafka allows ASCII letters, digits, '.', '_' and '-' in topic names
+        name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' { c } else { '_' })
+            .take(249).collect()
+    }
+
+    // like the Java client for keyed records; without a key the table decides, which keeps its changes in order
+    async fn partition(&mut self, topic: &str, key: Option<&[u8]>, event: &ChangeEvent) -> Result<i32, SinkError> {
+        if self.client.partition_count(topic).is_none() {
+            let metadata = self.client.refresh_metadata(&[topic.to_owned()]).await?;
+            // a missing topic that is being auto-created is worth waiting for, an invalid or forbidden one is not
+            if let Some(error_code) = metadata.topics.iter().find(|t| t.name == topic).map(|t| t.error_code)
+                && error_code != NONE && !is_retriable(error_code) {
+                return Err(kafka_error(error_code, &format!("metadata request for topic {}", topic)));
+            }
+        }
+        let count = self.client.partition_count(topic)
+            .ok_or_else(|| SinkError::Unavailable(format!("Kafka topic {} has no partitions yet", topic)))?;
+        let table = format!("{}.{}", event.schema, event.table);
+        let hash = murmur2(key.unwrap_or(table.as_bytes())) & 0x7fff_ffff;
+
+        Ok((hash as usize % count) as i32)
+    }
+
+    async fn add_events(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        for (index, event) in batch.events.iter().enumerate() {
+            let topic = self.topic(event);
+            let (key, value, headers) = match &mut self.avro {
+                Some(avro) => {
+                    let (key, value) = avro.encode(&topic, event, batch).await?;
+                    (key, value, Vec::new())
+                },
+                None => (self.encoder.key(event)?, self.encoder.value(event, batch, index)?,
+                         self.encoder.headers(event, batch, index, "ce_")?),
+            };
+            let partition = self.partition(&topic, key.as_deref(), event).await?;
+            let record = Record {
+                key,
+                value: Some(value),
+                headers: headers.into_iter().map(|(name, value)| (name, value.into_bytes())).collect(),
+                timestamp: unix_millis(batch.commit_time),
+            };
+            // same key and partition as the delete, only keyed records can be compacted
+            let tombstone = (self.config.tombstones && event.operation == Operation::Delete && record.key.is_some())
+                .then(|| Record { value: None, headers: Vec::new(), ..record.clone() });
+            let records = self.pending.entry((topic, partition)).or_default();
+            for record in std::iter::once(record).chain(tombstone) {
+                self.pending_bytes += record.size();
+                records.push(record);
+            }
+        }
+        if self.pending_bytes >= self.config.batch_size {
+            self.send_pending().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_pending(&mut self) -> Result<(), SinkError> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_bytes = 0;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if self.config.idempotent && self.producer.is_none() {
+            let response = self.client.send_any(INIT_PRODUCER_ID, INIT_PRODUCER_ID_VERSION,
+                                                &init_producer_id_request(TRANSACTION_TIMEOUT_MS)).await?;
+            let (id, epoch) = parse_init_producer_id(&response)?;
+            info!(producer_id = id, epoch, "Initialized idempotent Kafka producer");
+            self.producer = Some(ProducerId { id, epoch });
+            self.sequences.clear();
+        }
+
+        // one or more batches per partition, sequence numbers continue where the previous batch ended
+        let mut outstanding = Vec::new();
+        for (partition, records) in pending {
+            let mut data = Vec::new();
+            for chunk in chunks(&records, self.config.batch_size) {
+                let sequence = self.sequences.entry(partition.clone()).or_insert(0);
+                data.extend(encode_batch(chunk, self.config.compression, self.producer, *sequence)?);
+                *sequence = next_sequence(*sequence, chunk.len() as i32);
+            }
+            outstanding.push((partition, data));
+        }
+
+        let result = self.produce(outstanding).await;
+        if result.is_err() {
+            // the changes are delivered again after a reconnect, by a new producer with fresh sequence numbers
+            self.producer = None;
+            self.sequences.clear();
+        }
+        result
+    }
+
+    // Sends the batches to their leaders until all are acknowledged, refreshing the metadata between attempts
+    async fn produce(&mut self, mut outstanding: Vec<((String, i32), Vec<u8>)>) -> Result<(), SinkError> {
+        let timeout_ms = self.config.timeout.as_millis().min(i32::MAX as u128) as i32;
+        let mut attempt = 0;
+        loop {
+            let mut by_leader: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+            let mut retry = Vec::new();
+            let mut last_error = SinkError::Unavailable(String::from("Kafka partition has no leader"));
+            for (i, ((topic, partition), _)) in outstanding.iter().enumerate() {
+                match self.client.leader(topic, *partition) {
+                    Some(leader) => by_leader.entry(leader).or_default().push(i),
+                    None => retry.push(i),
+                }
+            }
+
+            for (leader, indices) in by_leader {
+                let data: Vec<PartitionData> = indices.iter()
+                    .map(|&i| PartitionData { topic: &outstanding[i].0.0, partition: outstanding[i].0.1, records: &outstanding[i].1 })
+                    .collect();
+                let body = produce_request(self.config.acks, timeout_ms, &data);
+                let errors: HashMap<(String, i32), i16> = match self.client.send(leader, PRODUCE, PRODUCE_VERSION, &body).await {
+                    Ok(response) => parse_produce(&response)?.into_iter().map(|(t, p, e)| ((t, p), e)).collect(),
+                    Err(e) => {
+                        debug!(broker = leader, "Produce request failed: {}", e);
+                        last_error = e;
+                        HashMap::new()
+                    },
+                };
+                for i in indices {
+                    match errors.get(&outstanding[i].0) {
+                        // a duplicate was written by an earlier attempt that timed out
+                        Some(&NONE) | Some(&DUPLICATE_SEQUENCE_NUMBER) => {},
+                        Some(&code) if is_retriable(code) => {
+                            last_error = kafka_error(code, "Produce");
+                            retry.push(i);
+                        },
+                        Some(&code) => return Err(kafka_error(code, "Produce")),
+                        None => retry.push(i),
+                    }
+                }
+            }
+
+            if retry.is_empty() {
+                return Ok(());
+            }
+            attempt += 1;
+            if attempt > self.config.retries {
+                return Err(last_error);
+            }
+            retry.sort();
+            outstanding = outstanding.into_iter().enumerate()
+                .filter(|(i, _)| retry.binary_search(i).is_ok())
+                .map(|(_, batch)| batch)
+                .collect();
+            let delay = (Duration::from_millis(100) * 2u32.pow(attempt.min(6))).min(MAX_RETRY_BACKOFF);
+            warn!(partitions = outstanding.len(), attempt, "Retrying Kafka produce in {:?}: {}", delay, last_error);
+            tokio::time::sleep(delay).await;
+            let mut topics: Vec<String> = outstanding.iter().map(|((topic, _), _)| topic.clone()).collect();
+            topics.dedup();
+            if let Err(e) = self.client.refresh_metadata(&topics).await {
+                debug!("Metadata refresh failed: {}", e);
+            }
+        }
+    }
+}
+
+// wraps to 0 after i32::MAX, as the broker expects
+fn next_sequence(sequence: i32, increment: i32) -> i32 {
+    if sequence > i32::MAX - increment { increment - (i32::MAX - sequence) - 1 } else { sequence + increment }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn identify(&mut self, system_id: &str) {
+        self.encoder.set_system_id(system_id);
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        let result = self.add_events(batch).await;
+        if result.is_err() {
+            // nothing pending was acknowledged, it all comes again after the reconnect
+            self.pending.clear();
+            self.pending_bytes = 0;
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.send_pending().await
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        let result = self.send_pending().await;
+        self.client.forget_connections();
+        result
+    }
+}
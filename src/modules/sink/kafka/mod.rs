@@ -0,0 +1,4 @@
+pub mod client;
+pub mod kafka_sink;
+pub mod protocol;
+pub mod record_batch;
\ No newline at end of file
@@ -0,0 +1,289 @@
+use crate::modules::sink::sink_error::SinkError;
+
+pub const PRODUCE: i16 = 0;
+pub const METADATA: i16 = 3;
+pub const INIT_PRODUCER_ID: i16 = 22;
+// v7 is the first that accepts zstd batches, Kafka 2.1+
+pub const PRODUCE_VERSION: i16 = 7;
+pub const METADATA_VERSION: i16 = 1;
+pub const INIT_PRODUCER_ID_VERSION: i16 = 0;
+
+pub const NONE: i16 = 0;
+pub const DUPLICATE_SEQUENCE_NUMBER: i16 = 46;
+
+// Request framing: size, api key and version, correlation id, client id, then the body
+pub fn request(api_key: i16, api_version: i16, correlation_id: i32, client_id: &str, body: &[u8]) -> Vec<u8> {
+    let mut header = RequestWriter::new();
+    header.i16(api_key);
+    header.i16(api_version);
+    header.i32(correlation_id);
+    header.string(client_id);
+    let mut message = Vec::with_capacity(header.buf.len() + body.len() + 4);
+    message.extend_from_slice(&((header.buf.len() + body.len()) as i32).to_be_bytes());
+    message.extend_from_slice(&header.buf);
+    message.extend_from_slice(body);
+    message
+}
+
+#[derive(Default)]
+pub struct RequestWriter {
+    pub buf: Vec<u8>,
+}
+
+impl RequestWriter {
+    pub fn new() -> RequestWriter {
+        RequestWriter::default()
+    }
+
+    pub fn i16(&mut self, value: i16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.i16(value.len() as i16);
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn null_string(&mut self) {
+        self.i16(-1);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.i32(value.len() as i32);
+        self.buf.extend_from_slice(value);
+    }
+}
+
+pub struct ResponseReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ResponseReader<'a> {
+    pub fn new(data: &'a [u8]) -> ResponseReader<'a> {
+        ResponseReader { data, position: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SinkError> {
+        let bytes = self.data.get(self.position..self.position + n)
+            .ok_or_else(|| SinkError::Unavailable(String::from("truncated Kafka response")))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SinkError> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    pub fn i16(&mut self) -> Result<i16, SinkError> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, SinkError> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, SinkError> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> Result<String, SinkError> {
+        Ok(self.nullable_string()?.unwrap_or_default())
+    }
+
+    pub fn nullable_string(&mut self) -> Result<Option<String>, SinkError> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(self.take(len as usize)?).into_owned()))
+    }
+
+    // element count of an array, null arrays are empty
+    pub fn array_len(&mut self) -> Result<usize, SinkError> {
+        Ok(self.i32()?.max(0) as usize)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Broker {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PartitionMetadata {
+    pub error_code: i16,
+    pub index: i32,
+    pub leader: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct TopicMetadata {
+    pub error_code: i16,
+    pub name: String,
+    pub partitions: Vec<PartitionMetadata>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub brokers: Vec<Broker>,
+    pub topics: Vec<TopicMetadata>,
+}
+
+// Metadata v1, for the given topics; brokers with auto.create.topics.enable create missing ones
+pub fn metadata_request(topics: &[String]) -> Vec<u8> {
+    let mut body = RequestWriter::new();
+    body.i32(topics.len() as i32);
+    for topic in topics {
+        body.string(topic);
+    }
+    body.buf
+}
+
+pub fn parse_metadata(response: &[u8]) -> Result<Metadata, SinkError> {
+    let mut reader = ResponseReader::new(response);
+    let mut metadata = Metadata::default();
+    for _ in 0..reader.array_len()? {
+        let node_id = reader.i32()?;
+        let host = reader.string()?;
+        let port = reader.i32()?;
+        // rack
+        reader.nullable_string()?;
+        metadata.brokers.push(Broker { node_id, host, port });
+    }
+    // controller id
+    reader.i32()?;
+    for _ in 0..reader.array_len()? {
+        let error_code = reader.i16()?;
+        let name = reader.string()?;
+        // is_internal
+        reader.bool()?;
+        let mut partitions = Vec::new();
+        for _ in 0..reader.array_len()? {
+            let error_code = reader.i16()?;
+            let index = reader.i32()?;
+            let leader = reader.i32()?;
+            // replicas and in-sync replicas
+            for _ in 0..2 {
+                for _ in 0..reader.array_len()? {
+                    reader.i32()?;
+                }
+            }
+            partitions.push(PartitionMetadata { error_code, index, leader });
+        }
+        partitions.sort_by_key(|p| p.index);
+        metadata.topics.push(TopicMetadata { error_code, name, partitions });
+    }
+
+    Ok(metadata)
+}
+
+// InitProducerId v0 without a transactional id, for an idempotent producer
+pub fn init_producer_id_request(transaction_timeout_ms: i32) -> Vec<u8> {
+    let mut body = RequestWriter::new();
+    body.null_string();
+    body.i32(transaction_timeout_ms);
+    body.buf
+}
+
+// producer id and epoch
+pub fn parse_init_producer_id(response: &[u8]) -> Result<(i64, i16), SinkError> {
+    let mut reader = ResponseReader::new(response);
+    // throttle time
+    reader.i32()?;
+    let error_code = reader.i16()?;
+    if error_code != NONE {
+        return Err(kafka_error(error_code, "InitProducerId"));
+    }
+
+    Ok((reader.i64()?, reader.i16()?))
+}
+
+// record batches of one partition
+pub struct PartitionData<'a> {
+    pub topic: &'a str,
+    pub partition: i32,
+    pub records: &'a [u8],
+}
+
+// Produce v7, partitions of the same topic must be adjacent
+pub fn produce_request(acks: i16, timeout_ms: i32, data: &[PartitionData]) -> Vec<u8> {
+    let mut body = RequestWriter::new();
+    // transactional id
+    body.null_string();
+    body.i16(acks);
+    body.i32(timeout_ms);
+    let topics: Vec<&[PartitionData]> = data.chunk_by(|a, b| a.topic == b.topic).collect();
+    body.i32(topics.len() as i32);
+    for partitions in topics {
+        body.string(partitions[0].topic);
+        body.i32(partitions.len() as i32);
+        for partition in partitions {
+            body.i32(partition.partition);
+            body.bytes(partition.records);
+        }
+    }
+    body.buf
+}
+
+// error code per topic and partition
+pub fn parse_produce(response: &[u8]) -> Result<Vec<(String, i32, i16)>, SinkError> {
+    let mut reader = ResponseReader::new(response);
+    let mut results = Vec::new();
+    for _ in 0..reader.array_len()? {
+        let topic = reader.string()?;
+        for _ in 0..reader.array_len()? {
+            let partition = reader.i32()?;
+            let error_code = reader.i16()?;
+            // base offset, log append time, log start offset
+            reader.i64()?;
+            reader.i64()?;
+            reader.i64()?;
+            results.push((topic.clone(), partition, error_code));
+        }
+    }
+
+    Ok(results)
+}
+
+// worth retrying after a metadata refresh, the leader moved or the topic is being created
+pub fn is_retriable(error_code: i16) -> bool {
+    matches!(error_code, 3 | 5 | 6 | 7 | 13 | 14 | 15 | 19 | 20 | 51)
+}
+
+pub fn kafka_error(error_code: i16, context: &str) -> SinkError {
+    let name = match error_code {
+        2 => "CORRUPT_MESSAGE",
+        3 => "UNKNOWN_TOPIC_OR_PARTITION",
+        5 => "LEADER_NOT_AVAILABLE",
+        6 => "NOT_LEADER_OR_FOLLOWER",
+        7 => "REQUEST_TIMED_OUT",
+        10 => "MESSAGE_TOO_LARGE",
+        13 => "NETWORK_EXCEPTION",
+        17 => "INVALID_TOPIC_EXCEPTION",
+        18 => "RECORD_LIST_TOO_LARGE",
+        19 => "NOT_ENOUGH_REPLICAS",
+        20 => "NOT_ENOUGH_REPLICAS_AFTER_APPEND",
+        29 => "TOPIC_AUTHORIZATION_FAILED",
+        31 => "CLUSTER_AUTHORIZATION_FAILED",
+        35 => "UNSUPPORTED_VERSION",
+        45 => "OUT_OF_ORDER_SEQUENCE_NUMBER",
+        47 => "INVALID_PRODUCER_EPOCH",
+        59 => "UNKNOWN_PRODUCER_ID",
+        76 => "UNSUPPORTED_COMPRESSION_TYPE",
+        87 => "INVALID_RECORD",
+        _ => "",
+    };
+    let message = format!("Kafka {} failed with error {} {}", context, error_code, name);
+    if is_retriable(error_code) || matches!(error_code, 45 | 47 | 59) {
+        SinkError::Unavailable(message.trim_end().to_owned())
+    } else {
+        SinkError::Rejected(message.trim_end().to_owned())
+    }
+}
@@ -0,0 +1,302 @@
+use crate::modules::sink::json_lines::Compression;
+use flate2::write::GzEncoder;
+use std::io;
+use std::io::Write;
+
+// magic byte of the v2 record batch format, Kafka 0.11+
+const MAGIC: i8 = 2;
+// compression codec in the lowest three bits of the batch attributes
+const CODEC_GZIP: i16 = 1;
+const CODEC_ZSTD: i16 = 4;
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    // milliseconds since 1970-01-01
+    pub timestamp: i64,
+}
+
+impl Record {
+    // rough encoded size, for batching
+    pub fn size(&self) -> usize {
+        self.key.as_ref().map_or(0, Vec::len) + self.value.as_ref().map_or(0, Vec::len)
+            + self.headers.iter().map(|(k, v)| k.len() + v.len() + 4).sum::<usize>() + 24
+    }
+}
+
+// idempotent producer identity, the broker drops batches whose sequence it has already written
+#[derive(Debug, Clone, Copy)]
+pub struct ProducerId {
+    pub id: i64,
+    pub epoch: i16,
+}
+
+// Splits the records of a partition into batches of at most max_bytes; a record is only on its own
+// in a larger batch when it alone exceeds the limit
+pub fn chunks(records: &[Record], max_bytes: usize) -> Vec<&[Record]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, record) in records.iter().enumerate() {
+        if i > start && bytes + record.size() > max_bytes {
+            chunks.push(&records[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += record.size();
+    }
+    if start < records.len() {
+        chunks.push(&records[start..]);
+    }
+    chunks
+}
+
+// A v2 record batch: fixed header, CRC-32C over everything after the CRC field, then the
+// records, compressed as a whole when a codec is set
+pub fn encode_batch(records: &[Record], compression: Compression, producer: Option<ProducerId>,
+                    base_sequence: i32) -> io::Result<Vec<u8>> {
+    let base_timestamp = records.iter().map(|r| r.timestamp).min().unwrap_or_default();
+    let max_timestamp = records.iter().map(|r| r.timestamp).max().unwrap_or_default();
+    let mut encoded = Vec::new();
+    for (offset_delta, record) in records.iter().enumerate() {
+        encode_record(&mut encoded, record, offset_delta as i64, record.timestamp - base_timestamp);
+    }
+    let (codec, encoded) = match compression {
+        Compression::None => (0, encoded),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&encoded)?;
+            (CODEC_GZIP, encoder.finish()?)
+        },
+        Compression::Zstd => (CODEC_ZSTD, zstd::encode_all(&encoded[..], 0)?),
+    };
+
+    // attributes to the end, the part the CRC covers
+    let mut body = Vec::with_capacity(encoded.len() + 49);
+    body.extend_from_slice(&codec.to_be_bytes());
+    body.extend_from_slice(&(records.len() as i32 - 1).to_be_bytes());
+    body.extend_from_slice(&base_timestamp.to_be_bytes());
+    body.extend_from_slice(&max_timestamp.to_be_bytes());
+    body.extend_from_slice(&producer.map_or(-1, |p| p.id).to_be_bytes());
+    body.extend_from_slice(&producer.map_or(-1, |p| p.epoch).to_be_bytes());
+    body.extend_from_slice(&(if producer.is_some() { base_sequence } else { -1 }).to_be_bytes());
+    body.extend_from_slice(&(records.len() as i32).to_be_bytes());
+    body.extend_from_slice(&encoded);
+
+    let mut batch = Vec::with_capacity(body.len() + 21);
+    // base offset, assigned by the broker
+    batch.extend_from_slice(&0i64.to_be_bytes());
+    // batch length: partition leader epoch, magic and CRC plus the body
+    batch.extend_from_slice(&(body.len() as i32 + 9).to_be_bytes());
+    batch.extend_from_slice(&(-1i32).to_be_bytes());
+    batch.push(MAGIC as u8);
+    batch.extend_from_slice(&crc32c(&body).to_be_bytes());
+    batch.extend_from_slice(&body);
+
+    Ok(batch)
+}
+
+fn encode_record(buf: &mut Vec<u8>, record: &Record, offset_delta: i64, timestamp_delta: i64) {
+    let mut body = Vec::with_capacity(record.size());
+    // attributes, unused
+    body.push(0);
+    put_varint(&mut body, timestamp_delta);
+    put_varint(&mut body, offset_delta);
+    put_varbytes(&mut body, record.key.as_deref());
+    put_varbytes(&mut body, record.value.as_deref());
+    put_varint(&mut body, record.headers.len() as i64);
+    for (key, value) in &record.headers {
+        put_varbytes(&mut body, Some(key.as_bytes()));
+        put_varbytes(&mut body, Some(value));
+    }
+    put_varint(buf, body.len() as i64);
+    buf.extend_from_slice(&body);
+}
+
+fn put_varbytes(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            put_varint(buf, bytes.len() as i64);
+            buf.extend_from_slice(bytes);
+        },
+        None => put_varint(buf, -1),
+    }
+}
+
+// zigzag encoded, as protobuf
+fn put_varint(buf: &mut Vec<u8>, value: i64) {
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    while v >= 0x80 {
+        buf.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+// CRC-32C (Castagnoli), reflected polynomial 0x82F63B78
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+// Kafka's default partitioner, so keys land on the same partitions as with the Java client
+pub fn murmur2(data: &[u8]) -> u32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    let mut h = SEED ^ data.len() as u32;
+    let chunks = data.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    if tail.len() >= 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn record(key: &str, value: &str, timestamp: i64) -> Record {
+        Record { key: Some(key.as_bytes().to_vec()), value: Some(value.as_bytes().to_vec()), headers: Vec::new(), timestamp }
+    }
+
+    #[test]
+    fn crc32c_check_values() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(b"The quick brown fox jumps over the lazy dog"), 0x2262_0404);
+        assert_eq!(crc32c(&[0u8; 32]), 0x8A91_36AA);
+        assert_eq!(crc32c(&[0xffu8; 32]), 0x62A8_AB43);
+    }
+
+    // the cases of Kafka's UtilsTest.testMurmur2
+    #[test]
+    fn murmur2_matches_kafka() {
+        let cases: [(&[u8], i32); 6] = [
+            (b"21", -973_932_308),
+            (b"foobar", -790_332_482),
+            (b"a-little-bit-long-string", -985_981_536),
+            (b"a-little-bit-longer-string", -1_486_304_829),
+            (b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8", -58_897_971),
+            (b"abc", 479_470_107),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(murmur2(data) as i32, expected, "{}", String::from_utf8_lossy(data));
+        }
+    }
+
+    #[test]
+    fn encodes_an_uncompressed_batch() {
+        let batch = encode_batch(&[record("k", "v", 1000)], Compression::None, None, 0).unwrap();
+        let mut expected = Vec::new();
+        // base offset, batch length, partition leader epoch, magic
+        expected.extend_from_slice(&0i64.to_be_bytes());
+        expected.extend_from_slice(&58i32.to_be_bytes());
+        expected.extend_from_slice(&(-1i32).to_be_bytes());
+        expected.push(2);
+        expected.extend_from_slice(&crc32c(&batch[21..]).to_be_bytes());
+        // attributes, last offset delta, base and max timestamp
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&1000i64.to_be_bytes());
+        expected.extend_from_slice(&1000i64.to_be_bytes());
+        // no producer id, epoch or sequence
+        expected.extend_from_slice(&[0xff; 14]);
+        expected.extend_from_slice(&1i32.to_be_bytes());
+        // length 8, attributes, timestamp and offset delta, key "k", value "v", no headers
+        expected.extend_from_slice(&[0x10, 0, 0, 0, 0x02, b'k', 0x02, b'v', 0]);
+        assert_eq!(batch, expected);
+    }
+
+    #[test]
+    fn encodes_deltas_nulls_headers_and_the_producer() {
+        let records = [
+            record("a", "1", 5000),
+            Record { key: None, value: None, headers: vec![(String::from("h"), b"x".to_vec())], timestamp: 4000 },
+        ];
+        let producer = ProducerId { id: 7, epoch: 1 };
+        let batch = encode_batch(&records, Compression::None, Some(producer), 42).unwrap();
+        assert_eq!(i32::from_be_bytes(batch[8..12].try_into().unwrap()) as usize, batch.len() - 12);
+        assert_eq!(u32::from_be_bytes(batch[17..21].try_into().unwrap()), crc32c(&batch[21..]));
+        // last offset delta, base timestamp is the smallest, max timestamp the largest
+        assert_eq!(i32::from_be_bytes(batch[23..27].try_into().unwrap()), 1);
+        assert_eq!(i64::from_be_bytes(batch[27..35].try_into().unwrap()), 4000);
+        assert_eq!(i64::from_be_bytes(batch[35..43].try_into().unwrap()), 5000);
+        assert_eq!(i64::from_be_bytes(batch[43..51].try_into().unwrap()), 7);
+        assert_eq!(i16::from_be_bytes(batch[51..53].try_into().unwrap()), 1);
+        assert_eq!(i32::from_be_bytes(batch[53..57].try_into().unwrap()), 42);
+        assert_eq!(i32::from_be_bytes(batch[57..61].try_into().unwrap()), 2);
+        // length 9, attributes, timestamp delta 1000, offset delta 0, key length 1; all varints are zigzag encoded
+        assert_eq!(&batch[61..67], &[0x12, 0, 0xd0, 0x0f, 0, 0x02]);
+        // length 10, timestamp delta 0, offset delta 1, null key and value, one header h: x
+        assert_eq!(&batch[71..], &[0x14, 0, 0, 0x02, 0x01, 0x01, 0x02, 0x02, b'h', 0x02, b'x']);
+    }
+
+    #[test]
+    fn compresses_the_records_only() {
+        let records = [record("k", "v", 1000), record("k", "w", 1000)];
+        let plain = encode_batch(&records, Compression::None, None, 0).unwrap();
+        let gzip = encode_batch(&records, Compression::Gzip, None, 0).unwrap();
+        assert_eq!(i16::from_be_bytes(gzip[21..23].try_into().unwrap()), CODEC_GZIP);
+        assert_eq!(&gzip[23..61], &plain[23..61]);
+        assert_eq!(u32::from_be_bytes(gzip[17..21].try_into().unwrap()), crc32c(&gzip[21..]));
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&gzip[61..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, &plain[61..]);
+        let zstd = encode_batch(&records, Compression::Zstd, None, 0).unwrap();
+        assert_eq!(i16::from_be_bytes(zstd[21..23].try_into().unwrap()), CODEC_ZSTD);
+        assert_eq!(zstd::decode_all(&zstd[61..]).unwrap(), &plain[61..]);
+    }
+
+    #[test]
+    fn chunks_stay_under_the_limit() {
+        let records: Vec<Record> = (0..10).map(|i| record("k", &"x".repeat(75), i)).collect();
+        // 100 bytes per record
+        assert_eq!(records[0].size(), 100);
+        let sizes = |max| chunks(&records, max).iter().map(|c| c.len()).collect::<Vec<_>>();
+        assert_eq!(sizes(1000), vec![10]);
+        assert_eq!(sizes(999), vec![9, 1]);
+        assert_eq!(sizes(250), vec![2, 2, 2, 2, 2]);
+        // a record larger than the limit goes alone
+        assert_eq!(sizes(50), vec![1; 10]);
+        assert!(chunks(&[], 100).is_empty());
+    }
+}
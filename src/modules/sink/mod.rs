@@ -0,0 +1,16 @@
+pub mod apply;
+pub mod avro;
+pub mod cloudevents;
+pub mod debezium;
+pub mod format;
+pub mod http_client;
+pub mod json_lines;
+pub mod kafka;
+pub mod postgres;
+pub mod protobuf;
+pub mod sink_error;
+pub mod sqlite;
+pub mod stdout;
+pub mod traits;
+pub mod utils;
+pub mod webhook;
\ No newline at end of file
@@ -0,0 +1,401 @@
+use crate::dto::DBConfig;
+use crate::modules::conninfo::target::candidate_hosts;
+use crate::modules::conninfo::utils::to_conninfo;
+use crate::modules::db::{quote_identifier, quote_table_name};
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::sink::apply::{identity, primary_key, row, text_value, where_clause, AppliedLsn};
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, NoTls, Statement, Transaction as DbTransaction};
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_OFFSETS_TABLE: &str = "public.cyphercdc_offsets";
+// oids below are assigned by initdb, the same on every server of a major version
+const FIRST_NORMAL_OBJECT_ID: u32 = 16384;
+
+// what an insert does when the row exists, and an update when it does not
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    // upsert: the insert updates the existing row, the update inserts the missing one
+    #[default]
+    Update,
+    // keep the target row, skip the update of a missing one
+    Nothing,
+    // fail the pipeline
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    pub target: DBConfig,
+    pub on_conflict: OnConflict,
+    // creates missing tables and columns from the relation's columns
+    pub create_tables: bool,
+    // "schema.table" keeping the applied LSN per pipeline
+    pub offsets_table: String,
+}
+
+// what the target knows about a table, for the columns of its current relation
+#[derive(Debug, Clone)]
+struct TargetTable {
+    source_columns: Vec<Column>,
+    // type of each source column on the target, for the casts of the parameters
+    types: Vec<String>,
+    primary_key: Vec<String>,
+}
+
+// Applies every source transaction to another PostgreSQL database in one transaction, which also
+// stores the end LSN of the source transaction. Transactions at or before the stored LSN were
+// applied before a restart and are skipped, so each one is applied exactly once
+pub struct PostgresSink {
+    pipeline: String,
+    config: PostgresConfig,
+    client: Option<Client>,
+    statements: HashMap<String, Statement>,
+    tables: HashMap<(String, String), TargetTable>,
+    applied: AppliedLsn,
+}
+
+impl PostgresSink {
+    pub fn new(pipeline: &str, config: PostgresConfig) -> PostgresSink {
+        PostgresSink {
+            pipeline: pipeline.to_owned(),
+            config,
+            client: None,
+            statements: HashMap::new(),
+            tables: HashMap::new(),
+            applied: AppliedLsn::default(),
+        }
+    }
+
+    async fn connect(&mut self) -> Result<(), SinkError> {
+        if self.client.is_some() {
+            return Ok(());
+        }
+        let target = &self.config.target;
+        let mut last_error = SinkError::Unavailable(String::from("no target host to connect to"));
+        for (host, port) in candidate_hosts(target).map_err(SinkError::Rejected)? {
+            match tokio_postgres::connect(to_conninfo(target, &host, port).expose(), NoTls).await {
+                Ok((client, connection)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = connection.await {
+                            warn!(error = %e, "Target connection error");
+                        }
+                    });
+                    debug!(host = %host, port, database = %target.db_name, "Connected to target database");
+                    self.client = Some(client);
+                    break;
+                },
+                Err(e) => last_error = target_error(e, &format!("connecting to {}:{}", host, port)),
+            }
+        }
+        let Some(client) = &self.client else {
+            return Err(last_error);
+        };
+
+        let offsets = quote_table_name(&self.config.offsets_table);
+        // notices like "relation already exists, skipping" would end up in the log at info
+        let result = client.batch_execute(&format!("SET client_min_messages = warning; CREATE TABLE IF NOT EXISTS {} (pipeline text PRIMARY KEY, lsn pg_lsn NOT NULL, \
+            updated_at timestamptz NOT NULL DEFAULT now())", offsets)).await;
+        let result = match result {
+            Ok(()) => client.query_opt(&*format!("SELECT lsn::text FROM {} WHERE pipeline = $1", offsets), &[&self.pipeline]).await,
+            Err(e) => Err(e),
+        };
+        let row = match result {
+            Ok(row) => row,
+            Err(e) => {
+                self.client = None;
+                return Err(target_error(e, &format!("reading {}", self.config.offsets_table)));
+            },
+        };
+        if let Some(applied) = self.applied.load(row.map(|row| row.get(0)))? {
+            info!(lsn = %applied, "Target database applied up to");
+        }
+
+        Ok(())
+    }
+
+    async fn apply(&mut self, client: &mut Client, batch: &Transaction) -> Result<(), SinkError> {
+        let transaction = client.transaction().await.map_err(|e| target_error(e, "starting a transaction"))?;
+        let mut events = batch.events.iter().peekable();
+        while let Some(event) = events.next() {
+            if event.operation != Operation::Truncate {
+                self.apply_event(&transaction, event).await?;
+                continue;
+            }
+            // the tables of one TRUNCATE share its LSN; truncated together, foreign keys between them do not fail it
+            let mut names = vec![qualified_name(event)];
+            while let Some(next) = events.next_if(|e| e.operation == Operation::Truncate && e.lsn == event.lsn) {
+                names.push(qualified_name(next));
+            }
+            let sql = format!("TRUNCATE {}", names.join(", "));
+            transaction.batch_execute(&sql).await.map_err(|e| target_error(e, &sql))?;
+        }
+        let offsets = quote_table_name(&self.config.offsets_table);
+        let sql = format!("INSERT INTO {} (pipeline, lsn) VALUES ($1, $2::text::pg_lsn) \
+            ON CONFLICT (pipeline) DO UPDATE SET lsn = EXCLUDED.lsn, updated_at = now()", offsets);
+        transaction.execute(&*sql, &[&self.pipeline, &batch.end_lsn.to_string()]).await
+            .map_err(|e| target_error(e, &format!("storing the applied LSN in {}", self.config.offsets_table)))?;
+        transaction.commit().await.map_err(|e| target_error(e, "committing"))?;
+
+        Ok(())
+    }
+
+    async fn apply_event(&mut self, transaction: &DbTransaction<'_>, event: &ChangeEvent) -> Result<(), SinkError> {
+        let name = qualified_name(event);
+        let table = self.table(transaction, event).await?;
+
+        match event.operation {
+            Operation::Insert | Operation::Read => {
+                self.insert(transaction, &name, &table, event, row(event, event.new.as_ref())?).await?;
+            },
+            Operation::Update => {
+                let new = row(event, event.new.as_ref())?;
+                let mut sql = format!("UPDATE {} SET ", name);
+                let mut params = Vec::new();
+                let mut assignments = Vec::new();
+                for ((column, value), kind) in event.columns.iter().zip(new).zip(&table.types) {
+                    // the server did not send unchanged TOAST values, the target keeps its own
+                    if *value != ColumnValue::UnchangedToast {
+                        params.push(text_value(value)?.map(str::to_owned));
+                        assignments.push(format!("{} = ${}::text::{}", quote_identifier(&column.name), params.len(), kind));
+                    }
+                }
+                sql.push_str(&assignments.join(", "));
+                sql.push_str(&key_condition(event, &table, identity(event)?, &mut params)?);
+                let updated = self.execute(transaction, &sql, &params).await?;
+                if updated == 0 {
+                    match self.config.on_conflict {
+                        OnConflict::Update => self.insert(transaction, &name, &table, event, new).await?,
+                        OnConflict::Nothing => debug!(table = %name, "Skipped the update of a missing row"),
+                        OnConflict::Error => return Err(SinkError::Rejected(format!("updated row of {} does not exist on the target", name))),
+                    }
+                }
+            },
+            Operation::Delete => {
+                let mut params = Vec::new();
+                let sql = format!("DELETE FROM {}{}", name, key_condition(event, &table, identity(event)?, &mut params)?);
+                let deleted = self.execute(transaction, &sql, &params).await?;
+                if deleted == 0 && self.config.on_conflict == OnConflict::Error {
+                    return Err(SinkError::Rejected(format!("deleted row of {} does not exist on the target", name)));
+                }
+            },
+            // applied by apply, together with the other tables of the statement
+            Operation::Truncate => {},
+        }
+
+        Ok(())
+    }
+
+    async fn insert(&mut self, transaction: &DbTransaction<'_>, name: &str, table: &TargetTable, event: &ChangeEvent,
+                    values: &[ColumnValue]) -> Result<(), SinkError> {
+        let mut columns = Vec::new();
+        let mut casts = Vec::new();
+        let mut params = Vec::new();
+        for ((column, value), kind) in event.columns.iter().zip(values).zip(&table.types) {
+            if *value != ColumnValue::UnchangedToast {
+                params.push(text_value(value)?.map(str::to_owned));
+                columns.push(quote_identifier(&column.name));
+                casts.push(format!("${}::text::{}", params.len(), kind));
+            }
+        }
+        let mut sql = format!("INSERT INTO {} ({}) VALUES ({})", name, columns.join(", "), casts.join(", "));
+        let key: Vec<String> = table.primary_key.iter().map(|c| quote_identifier(c)).collect();
+        let updates: Vec<String> = columns.iter().filter(|c| !key.contains(c)).map(|c| format!("{} = EXCLUDED.{}", c, c)).collect();
+        match self.config.on_conflict {
+            OnConflict::Update if !key.is_empty() && !updates.is_empty() => {
+                sql.push_str(&format!(" ON CONFLICT ({}) DO UPDATE SET {}", key.join(", "), updates.join(", ")));
+            },
+            OnConflict::Update | OnConflict::Nothing => sql.push_str(" ON CONFLICT DO NOTHING"),
+            OnConflict::Error => {},
+        }
+        self.execute(transaction, &sql, &params).await?;
+
+        Ok(())
+    }
+
+    async fn execute(&mut self, transaction: &DbTransaction<'_>, sql: &str, params: &[Option<String>]) -> Result<u64, SinkError> {
+        let statement = match self.statements.get(sql) {
+            Some(statement) => statement.clone(),
+            None => {
+                let statement = transaction.prepare(sql).await.map_err(|e| target_error(e, &format!("preparing {}", sql)))?;
+                self.statements.insert(sql.to_owned(), statement.clone());
+                statement
+            },
+        };
+        let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        transaction.execute(&statement, &params).await.map_err(|e| target_error(e, &format!("executing {}", sql)))
+    }
+
+    // the target's view of the event's table, created or extended first when create_tables is set
+    async fn table(&mut self, transaction: &DbTransaction<'_>, event: &ChangeEvent) -> Result<TargetTable, SinkError> {
+        let key = (event.schema.clone(), event.table.clone());
+        if let Some(table) = self.tables.get(&key)
+            && table.source_columns == event.columns {
+            return Ok(table.clone());
+        }
+        let name = qualified_name(event);
+        let query = |e| target_error(e, &format!("reading the columns of {}", name));
+
+        let exists = transaction.query_one("SELECT to_regclass($1::text) IS NOT NULL", &[&name]).await.map_err(query)?.get::<_, bool>(0);
+        if !exists {
+            if !self.config.create_tables {
+                return Err(SinkError::Rejected(format!("table {} does not exist on the target, create it or set create_tables = true", name)));
+            }
+            self.create_table(transaction, &name, event).await?;
+        }
+        let mut target = target_columns(transaction, &name).await.map_err(query)?;
+
+        let missing: Vec<&Column> = event.columns.iter().filter(|c| !target.iter().any(|(name, _, _)| *name == c.name)).collect();
+        if !missing.is_empty() {
+            if !self.config.create_tables {
+                let names: Vec<&str> = missing.iter().map(|c| c.name.as_str()).collect();
+                return Err(SinkError::Rejected(format!("columns {} of {} do not exist on the target", names.join(", "), name)));
+            }
+            for column in missing {
+                let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", name, quote_identifier(&column.name),
+                                  type_name(transaction, column).await?);
+                transaction.batch_execute(&sql).await.map_err(|e| target_error(e, &sql))?;
+                info!(table = %name, column = %column.name, "Added column to target table");
+            }
+            target = target_columns(transaction, &name).await.map_err(query)?;
+        }
+
+        let table = TargetTable {
+            source_columns: event.columns.clone(),
+            types: event.columns.iter()
+                .map(|c| target.iter().find(|(name, _, _)| *name == c.name).map(|(_, kind, _)| kind.clone()).unwrap_or_default())
+                .collect(),
+            primary_key: target.iter().filter(|(_, _, key)| *key).map(|(name, _, _)| name.clone()).collect(),
+        };
+        self.tables.insert(key, table.clone());
+
+        Ok(table)
+    }
+
+    async fn create_table(&mut self, transaction: &DbTransaction<'_>, name: &str, event: &ChangeEvent) -> Result<(), SinkError> {
+        let mut definitions = Vec::new();
+        for column in &event.columns {
+            definitions.push(format!("{} {}", quote_identifier(&column.name), type_name(transaction, column).await?));
+        }
+        let key: Vec<String> = primary_key(&event.columns).iter().map(|c| quote_identifier(&c.name)).collect();
+        if !key.is_empty() {
+            definitions.push(format!("PRIMARY KEY ({})", key.join(", ")));
+        }
+        let schema_exists = transaction.query_one("SELECT EXISTS (SELECT FROM pg_namespace WHERE nspname = $1)", &[&event.schema]).await
+            .map_err(|e| target_error(e, &format!("looking up schema {}", event.schema)))?.get::<_, bool>(0);
+        let mut sql = format!("CREATE TABLE {} ({})", name, definitions.join(", "));
+        if !schema_exists {
+            sql = format!("CREATE SCHEMA {}; {}", quote_identifier(&event.schema), sql);
+        }
+        transaction.batch_execute(&sql).await.map_err(|e| target_error(e, &format!("creating {}", name)))?;
+        info!(table = %name, "Created target table");
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for PostgresSink {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        if batch.events.is_empty() {
+            return Ok(());
+        }
+        self.connect().await?;
+        if self.applied.contains(batch) {
+            return Ok(());
+        }
+
+        let mut client = self.client.take().unwrap();
+        let result = self.apply(&mut client, batch).await;
+        match &result {
+            Ok(()) => {
+                self.applied.advance(batch);
+                self.client = Some(client);
+            },
+            // the next attempt starts on a new connection, with the target's tables read again
+            Err(_) => {
+                self.statements.clear();
+                self.tables.clear();
+            },
+        }
+        result
+    }
+
+    // nothing is buffered, write_batch commits a target transaction per batch
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.client = None;
+        Ok(())
+    }
+}
+
+fn qualified_name(event: &ChangeEvent) -> String {
+    format!("{}.{}", quote_identifier(&event.schema), quote_identifier(&event.table))
+}
+
+// the key columns cast from their text form, IS NULL for null ones
+fn key_condition(event: &ChangeEvent, table: &TargetTable, values: &[ColumnValue], params: &mut Vec<Option<String>>)
+                 -> Result<String, SinkError> {
+    where_clause(event, values, |i, column, value| match text_value(value)? {
+        Some(text) => {
+            params.push(Some(text.to_owned()));
+            Ok(format!("{} = ${}::text::{}", quote_identifier(&column.name), params.len(), table.types[i]))
+        },
+        None => Ok(format!("{} IS NULL", quote_identifier(&column.name))),
+    })
+}
+
+// name, type and primary key membership of the columns of a target table, in column order
+async fn target_columns(transaction: &DbTransaction<'_>, name: &str) -> Result<Vec<(String, String, bool)>, tokio_postgres::Error> {
+    let rows = transaction.query("SELECT a.attname::text, format_type(a.atttypid, a.atttypmod), \
+        coalesce(a.attnum = ANY(i.indkey), false) FROM pg_attribute a \
+        LEFT JOIN pg_index i ON i.indrelid = a.attrelid AND i.indisprimary \
+        WHERE a.attrelid = $1::text::regclass AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum", &[&name]).await?;
+
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect())
+}
+
+// the SQL name of a column's type: built-in types have the same oid on every server, any other
+// type is looked up by its name and has to exist on the target
+async fn type_name(transaction: &DbTransaction<'_>, column: &Column) -> Result<String, SinkError> {
+    let row = if column.type_oid < FIRST_NORMAL_OBJECT_ID {
+        transaction.query_opt("SELECT format_type(oid, $2) FROM pg_type WHERE oid = $1", &[&column.type_oid, &column.type_modifier]).await
+    } else if let Some(name) = &column.type_name {
+        transaction.query_opt("SELECT format_type(to_regtype($1)::oid, $2) WHERE to_regtype($1) IS NOT NULL",
+                              &[name, &column.type_modifier]).await
+    } else {
+        return Err(SinkError::Rejected(format!("the server did not name the type (oid {}) of column {}, create the table yourself",
+                                               column.type_oid, column.name)));
+    };
+    let row = row.map_err(|e| target_error(e, "looking up a column type"))?;
+    let name = column.type_name.as_deref().map_or_else(|| format!("oid {}", column.type_oid), str::to_owned);
+    row.map(|row| row.get::<_, String>(0)).ok_or_else(|| SinkError::Rejected(format!("type {} of column {} does not exist on the target, create it first",
+                                                                                     name, column.name)))
+}
+
+// connection problems, serialization failures and shutdowns are worth a retry, constraint
+// violations and invalid data are not
+fn target_error(e: tokio_postgres::Error, context: &str) -> SinkError {
+    let message = match e.as_db_error() {
+        Some(db) => format!("{} failed on the target: {}{}", context, db.message(),
+                            db.detail().map(|d| format!(" ({})", d)).unwrap_or_default()),
+        None => format!("{} failed on the target: {}", context, e),
+    };
+    match e.code().map(|code| &code.code()[..2]) {
+        Some("08" | "40" | "53" | "57" | "58") | None => SinkError::Unavailable(message),
+        Some(_) => SinkError::Rejected(message),
+    }
+}
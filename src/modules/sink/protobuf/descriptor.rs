@@ -0,0 +1,568 @@
+use crate::modules::replication::dto::Column;
+use crate::modules::sink::avro::schema::{avro_name, avro_names};
+use crate::modules::sink::format::{BOOL_OID, BYTEA_OID, DATE_OID, FLOAT4_OID, FLOAT8_OID, INT2_OID, INT4_OID, INT8_OID, OID_OID,
+                                   TIMESTAMPTZ_OID, TIMESTAMP_OID, TIME_OID};
+use crate::modules::sink::protobuf::wire::{put_bytes_field, put_string_field, put_varint_field};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const TIMESTAMP_FILE: &str = "google/protobuf/timestamp.proto";
+const TIMESTAMP_TYPE: &str = "google.protobuf.Timestamp";
+
+// the Operation enum, value numbers as in the .proto
+pub const OPERATIONS: [(&str, u64); 6] = [("OPERATION_UNSPECIFIED", 0), ("OPERATION_INSERT", 1), ("OPERATION_UPDATE", 2),
+    ("OPERATION_DELETE", 3), ("OPERATION_TRUNCATE", 4), ("OPERATION_READ", 5)];
+
+// Protobuf type of a column, timestamps are google.protobuf.Timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtoType {
+    Bool,
+    Int32,
+    Int64,
+    Uint32,
+    Float,
+    Double,
+    Bytes,
+    String,
+    // int32 days since 1970-01-01
+    Date,
+    // int64 microseconds since midnight
+    TimeMicros,
+    // timestamptz
+    Timestamp,
+    // timestamp, read as UTC
+    LocalTimestamp,
+}
+
+impl ProtoType {
+    pub fn of(column: &Column) -> ProtoType {
+        match column.type_oid {
+            BOOL_OID => ProtoType::Bool,
+            INT2_OID | INT4_OID => ProtoType::Int32,
+            INT8_OID => ProtoType::Int64,
+            OID_OID => ProtoType::Uint32,
+            FLOAT4_OID => ProtoType::Float,
+            FLOAT8_OID => ProtoType::Double,
+            BYTEA_OID => ProtoType::Bytes,
+            DATE_OID => ProtoType::Date,
+            TIME_OID => ProtoType::TimeMicros,
+            TIMESTAMP_OID => ProtoType::LocalTimestamp,
+            TIMESTAMPTZ_OID => ProtoType::Timestamp,
+            // numeric keeps its exact text
+            _ => ProtoType::String,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        match self {
+            ProtoType::Bool => "bool",
+            ProtoType::Int32 | ProtoType::Date => "int32",
+            ProtoType::Int64 | ProtoType::TimeMicros => "int64",
+            ProtoType::Uint32 => "uint32",
+            ProtoType::Float => "float",
+            ProtoType::Double => "double",
+            ProtoType::Bytes => "bytes",
+            ProtoType::String => "string",
+            ProtoType::Timestamp | ProtoType::LocalTimestamp => TIMESTAMP_TYPE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    // proto3 default, left out when it has its default value
+    Implicit,
+    // explicit presence, a null column is a missing field
+    Optional,
+    Repeated,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    number: u32,
+    // a scalar, a message of the file or google.protobuf.Timestamp
+    type_name: &'static str,
+    label: Label,
+    comment: String,
+}
+
+#[derive(Debug, Clone)]
+struct Message {
+    name: &'static str,
+    comment: String,
+    fields: Vec<Field>,
+    // numbers of dropped or retyped columns, never used again
+    reserved: Vec<u32>,
+}
+
+// The messages of one table version, generated from the columns of its relation: Row with one
+// field per column, Key with the primary key columns under the same numbers, and the Change
+// envelope with the operation, the old and new Row and a Source. Field numbers come from
+// DescriptorCache, which never gives one another meaning
+#[derive(Debug, Clone)]
+pub struct TableDescriptor {
+    pub package: String,
+    pub types: Vec<ProtoType>,
+    // the Row field number of each column
+    pub numbers: Vec<u32>,
+    table: String,
+    messages: Vec<Message>,
+}
+
+impl TableDescriptor {
+    // the package is e.g. "inventory.public.orders", every part a valid identifier; numbers are
+    // the field numbers of the columns, reserved the retired ones
+    fn new(pipeline: &str, schema: &str, table: &str, columns: &[Column], numbers: Vec<u32>, reserved: Vec<u32>)
+           -> Result<TableDescriptor, String> {
+        let types: Vec<ProtoType> = columns.iter().map(ProtoType::of).collect();
+        // field names follow the Avro rules, which protobuf identifiers share
+        let names = avro_names(columns).map_err(|e| format!("no protobuf descriptor for {}.{}: {}", schema, table, e))?;
+        let column_fields = |key_only: bool| -> Vec<Field> {
+            columns.iter().zip(&types).zip(&names).zip(&numbers)
+                .filter(|(((column, _), _), _)| !key_only || column.primary_key)
+                .map(|(((column, kind), name), number)| Field {
+                    name: name.clone(),
+                    number: *number,
+                    type_name: kind.type_name(),
+                    label: if kind.type_name() == TIMESTAMP_TYPE { Label::Implicit } else { Label::Optional },
+                    comment: column_comment(column, *kind),
+                })
+                .collect()
+        };
+        let field = |name: &str, number: u32, type_name: &'static str, label: Label, comment: &str| Field {
+            name: name.to_owned(), number, type_name, label, comment: comment.to_owned(),
+        };
+
+        let mut messages = vec![Message {
+            name: "Change",
+            comment: format!("A change of {}.{}", schema, table),
+            fields: vec![
+                field("op", 1, "Operation", Label::Implicit, ""),
+                field("before", 2, "Row", Label::Implicit, "the old row of updates with REPLICA IDENTITY FULL or a changed key, and of deletes"),
+                field("after", 3, "Row", Label::Implicit, "the new row of inserts, updates and reads"),
+                field("source", 4, "Source", Label::Implicit, ""),
+                field("unchanged", 5, "string", Label::Repeated, "columns left out of after, their TOASTed value did not change and was not sent"),
+            ],
+            reserved: Vec::new(),
+        }, Message {
+            name: "Source",
+            comment: String::from("Where the change was read"),
+            fields: vec![
+                field("name", 1, "string", Label::Implicit, "the pipeline"),
+                field("database", 2, "string", Label::Implicit, ""),
+                field("schema", 3, "string", Label::Implicit, ""),
+                field("table", 4, "string", Label::Implicit, ""),
+                field("lsn", 5, "uint64", Label::Implicit, "WAL position of the change"),
+                field("commit_lsn", 6, "uint64", Label::Implicit, "WAL position of the commit of its transaction"),
+                field("xid", 7, "uint32", Label::Implicit, ""),
+                field("commit_time", 8, TIMESTAMP_TYPE, Label::Implicit, ""),
+            ],
+            reserved: Vec::new(),
+        }, Message {
+            name: "Row",
+            comment: String::from("One field per column, null columns are missing"),
+            fields: column_fields(false),
+            reserved,
+        }];
+        let key = column_fields(true);
+        if !key.is_empty() {
+            messages.push(Message {
+                name: "Key",
+                comment: String::from("The primary key columns, the Kafka record key"),
+                fields: key,
+                reserved: Vec::new(),
+            });
+        }
+
+        Ok(TableDescriptor {
+            package: [pipeline, schema, table].map(avro_name).join("."),
+            types,
+            numbers,
+            table: format!("{}.{}", schema, table),
+            messages,
+        })
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.messages.iter().any(|message| message.name == "Key")
+    }
+
+    // fully qualified name of a message, e.g. inventory.public.orders.Change
+    pub fn message_name(&self, message: &str) -> String {
+        format!("{}.{}", self.package, message)
+    }
+
+    // relative path of the .proto, following the package like protoc expects
+    pub fn file_name(&self) -> String {
+        format!("{}.proto", self.package.replace('.', "/"))
+    }
+
+    // the .proto source, for protoc and buf
+    pub fn proto(&self) -> String {
+        let mut proto = format!("// Generated by cyphercdc from the columns of {}\nsyntax = \"proto3\";\n\npackage {};\n\nimport \"{}\";\n",
+                                self.table, self.package, TIMESTAMP_FILE);
+        proto.push_str("\nenum Operation {\n");
+        for (name, number) in OPERATIONS {
+            proto.push_str(&format!("  {} = {};\n", name, number));
+        }
+        proto.push_str("}\n");
+        for message in &self.messages {
+            proto.push_str(&format!("\n// {}\nmessage {} {{\n", message.comment, message.name));
+            for field in &message.fields {
+                if !field.comment.is_empty() {
+                    proto.push_str(&format!("  // {}\n", field.comment));
+                }
+                let label = match field.label {
+                    Label::Implicit => "",
+                    Label::Optional => "optional ",
+                    Label::Repeated => "repeated ",
+                };
+                proto.push_str(&format!("  {}{} {} = {};\n", label, field.type_name, field.name, field.number));
+            }
+            if !message.reserved.is_empty() {
+                let numbers: Vec<String> = message.reserved.iter().map(u32::to_string).collect();
+                proto.push_str(&format!("  reserved {};\n", numbers.join(", ")));
+            }
+            proto.push_str("}\n");
+        }
+
+        proto
+    }
+
+    // the FileDescriptorProto of the .proto, as protoc would produce it
+    pub fn file_descriptor(&self) -> Vec<u8> {
+        let mut file = Vec::new();
+        put_string_field(&mut file, 1, &self.file_name());
+        put_string_field(&mut file, 2, &self.package);
+        put_string_field(&mut file, 3, TIMESTAMP_FILE);
+        for message in &self.messages {
+            put_bytes_field(&mut file, 4, &self.message_descriptor(message));
+        }
+        let mut operation = Vec::new();
+        put_string_field(&mut operation, 1, "Operation");
+        for (name, number) in OPERATIONS {
+            let mut value = Vec::new();
+            put_string_field(&mut value, 1, name);
+            put_varint_field(&mut value, 2, number);
+            put_bytes_field(&mut operation, 2, &value);
+        }
+        put_bytes_field(&mut file, 5, &operation);
+        put_string_field(&mut file, 12, "proto3");
+
+        file
+    }
+
+    fn message_descriptor(&self, message: &Message) -> Vec<u8> {
+        let mut descriptor = Vec::new();
+        put_string_field(&mut descriptor, 1, message.name);
+        // a proto3 optional field is the only member of a synthetic oneof
+        let mut oneofs = Vec::new();
+        for field in &message.fields {
+            let mut encoded = Vec::new();
+            put_string_field(&mut encoded, 1, &field.name);
+            put_varint_field(&mut encoded, 3, field.number as u64);
+            put_varint_field(&mut encoded, 4, if field.label == Label::Repeated { 3 } else { 1 });
+            match scalar_type(field.type_name) {
+                Some(kind) => put_varint_field(&mut encoded, 5, kind),
+                None => {
+                    put_varint_field(&mut encoded, 5, if field.type_name == "Operation" { 14 } else { 11 });
+                    let type_name = if field.type_name == TIMESTAMP_TYPE { field.type_name.to_owned() } else { self.message_name(field.type_name) };
+                    put_string_field(&mut encoded, 6, &format!(".{}", type_name));
+                },
+            }
+            if field.label == Label::Optional {
+                put_varint_field(&mut encoded, 9, oneofs.len() as u64);
+                oneofs.push(format!("_{}", field.name));
+            }
+            put_string_field(&mut encoded, 10, &json_name(&field.name));
+            if field.label == Label::Optional {
+                put_varint_field(&mut encoded, 17, 1);
+            }
+            put_bytes_field(&mut descriptor, 2, &encoded);
+        }
+        for oneof in oneofs {
+            let mut encoded = Vec::new();
+            put_string_field(&mut encoded, 1, &oneof);
+            put_bytes_field(&mut descriptor, 8, &encoded);
+        }
+        // ReservedRange, the end is exclusive
+        for number in &message.reserved {
+            let mut range = Vec::new();
+            put_varint_field(&mut range, 1, *number as u64);
+            put_varint_field(&mut range, 2, *number as u64 + 1);
+            put_bytes_field(&mut descriptor, 9, &range);
+        }
+
+        descriptor
+    }
+}
+
+// The descriptor of every table a pipeline has encoded, rebuilt only when a relation changes
+// its columns. Consumers decode by field number, so a column keeps the number it was first
+// given as long as it has the same name and type; new, renamed and retyped columns get the next
+// unused one and the numbers of dropped columns are never used again. With a file the numbers
+// survive restarts, it is written before a descriptor with a new number is used
+#[derive(Debug, Default)]
+pub struct DescriptorCache {
+    tables: HashMap<(String, String), CachedTable>,
+    // every Row field number given out, by schema and table
+    numbers: BTreeMap<String, BTreeMap<String, Vec<FieldNumber>>>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct CachedTable {
+    columns: Vec<Column>,
+    descriptor: Arc<TableDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FieldNumber {
+    number: u32,
+    column: String,
+    #[serde(rename = "type")]
+    kind: ProtoType,
+}
+
+// protobuf keeps 19000 to 19999 for itself
+const RESERVED_NUMBERS: std::ops::RangeInclusive<u32> = 19_000..=19_999;
+
+impl DescriptorCache {
+    // the numbers kept in the file at path, which does not exist before the first relation
+    pub fn open(path: PathBuf) -> io::Result<DescriptorCache> {
+        let numbers = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(DescriptorCache { tables: HashMap::new(), numbers, path: Some(path) })
+    }
+
+    // like open, but numbers given out to columns the file does not know are not written back
+    pub fn read(path: PathBuf) -> io::Result<DescriptorCache> {
+        Ok(DescriptorCache { path: None, ..DescriptorCache::open(path)? })
+    }
+
+    pub fn descriptor(&mut self, pipeline: &str, schema: &str, table: &str, columns: &[Column]) -> Result<Arc<TableDescriptor>, String> {
+        let key = (schema.to_owned(), table.to_owned());
+        if let Some(cached) = self.tables.get(&key).filter(|cached| cached.columns == columns) {
+            return Ok(cached.descriptor.clone());
+        }
+
+        let fields = self.numbers.entry(schema.to_owned()).or_default().entry(table.to_owned()).or_default();
+        let mut numbers = Vec::with_capacity(columns.len());
+        let mut assigned = false;
+        for column in columns {
+            let kind = ProtoType::of(column);
+            let number = match fields.iter().find(|field| field.column == column.name && field.kind == kind) {
+                Some(field) => field.number,
+                None => {
+                    let mut number = fields.iter().map(|field| field.number).max().unwrap_or(0) + 1;
+                    if RESERVED_NUMBERS.contains(&number) {
+                        number = RESERVED_NUMBERS.end() + 1;
+                    }
+                    fields.push(FieldNumber { number, column: column.name.clone(), kind });
+                    assigned = true;
+                    number
+                },
+            };
+            numbers.push(number);
+        }
+        let reserved = fields.iter().map(|field| field.number).filter(|number| !numbers.contains(number)).collect();
+        let descriptor = Arc::new(TableDescriptor::new(pipeline, schema, table, columns, numbers, reserved)?);
+        if assigned {
+            self.save().map_err(|e| format!("could not save the protobuf field numbers of {}.{}: {}", schema, table, e))?;
+        }
+
+        self.tables.insert(key, CachedTable { columns: columns.to_vec(), descriptor: descriptor.clone() });
+        Ok(descriptor)
+    }
+
+    // write to a temporary file, fsync and rename, like the offset file
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let temporary = path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary)?;
+        serde_json::to_writer_pretty(&mut file, &self.numbers)?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+}
+
+// Writes the .proto of every table and descriptors.pb, a FileDescriptorSet with all of them and
+// google/protobuf/timestamp.proto as `protoc --include_imports` would write it; returns the paths
+pub fn write_descriptors(directory: &Path, tables: &[TableDescriptor]) -> io::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    let mut set = Vec::new();
+    put_bytes_field(&mut set, 1, &timestamp_file_descriptor());
+    for table in tables {
+        let path = directory.join(table.file_name());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, table.proto())?;
+        written.push(path);
+        put_bytes_field(&mut set, 1, &table.file_descriptor());
+    }
+    fs::create_dir_all(directory)?;
+    let path = directory.join("descriptors.pb");
+    fs::write(&path, set)?;
+    written.push(path);
+
+    Ok(written)
+}
+
+fn timestamp_file_descriptor() -> Vec<u8> {
+    let mut message = Vec::new();
+    put_string_field(&mut message, 1, "Timestamp");
+    for (name, number, kind) in [("seconds", 1, 3), ("nanos", 2, 5)] {
+        let mut field = Vec::new();
+        put_string_field(&mut field, 1, name);
+        put_varint_field(&mut field, 3, number);
+        put_varint_field(&mut field, 4, 1);
+        put_varint_field(&mut field, 5, kind);
+        put_string_field(&mut field, 10, name);
+        put_bytes_field(&mut message, 2, &field);
+    }
+    let mut file = Vec::new();
+    put_string_field(&mut file, 1, TIMESTAMP_FILE);
+    put_string_field(&mut file, 2, "google.protobuf");
+    put_bytes_field(&mut file, 4, &message);
+    put_string_field(&mut file, 12, "proto3");
+
+    file
+}
+
+// FieldDescriptorProto.Type of the scalars
+fn scalar_type(type_name: &str) -> Option<u64> {
+    Some(match type_name {
+        "double" => 1,
+        "float" => 2,
+        "int64" => 3,
+        "uint64" => 4,
+        "int32" => 5,
+        "bool" => 8,
+        "string" => 9,
+        "bytes" => 12,
+        "uint32" => 13,
+        _ => return None,
+    })
+}
+
+// lowerCamelCase, like protoc derives it
+fn json_name(name: &str) -> String {
+    let mut json = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            json.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            json.push(c);
+        }
+    }
+    json
+}
+
+fn column_comment(column: &Column, kind: ProtoType) -> String {
+    let unit = match kind {
+        ProtoType::Date => ", days since 1970-01-01",
+        ProtoType::TimeMicros => ", microseconds since midnight",
+        ProtoType::LocalTimestamp => ", without time zone, read as UTC",
+        _ => "",
+    };
+    format!("{} (type oid {}){}", column.name, column.type_oid, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn column(name: &str, type_oid: u32) -> Column {
+        Column { name: name.to_owned(), type_oid, type_modifier: -1, key: false, primary_key: false, type_name: None }
+    }
+
+    #[test]
+    fn cache_reuses_the_descriptor_of_unchanged_columns() {
+        let mut cache = DescriptorCache::default();
+        let columns = [column("id", 23), column("name", 25)];
+
+        let first = cache.descriptor("p", "public", "t", &columns).unwrap();
+        let second = cache.descriptor("p", "public", "t", &columns).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn cache_accepts_added_columns() {
+        let mut cache = DescriptorCache::default();
+        cache.descriptor("p", "public", "t", &[column("id", 23)]).unwrap();
+
+        let added = cache.descriptor("p", "public", "t", &[column("id", 23), column("name", 25)]).unwrap();
+        assert!(added.proto().contains("optional string name = 2;"));
+    }
+
+    #[test]
+    fn columns_keep_their_number_and_dropped_ones_are_not_reused() {
+        let mut cache = DescriptorCache::default();
+        cache.descriptor("p", "public", "t", &[column("id", 23), column("name", 25), column("price", 701)]).unwrap();
+
+        // dropping name leaves price at 3 and reserves 2
+        let dropped = cache.descriptor("p", "public", "t", &[column("id", 23), column("price", 701)]).unwrap();
+        assert_eq!(dropped.numbers, [1, 3]);
+        assert!(dropped.proto().contains("  optional double price = 3;\n  reserved 2;\n}"), "{}", dropped.proto());
+        // a new column and a retyped one get fresh numbers
+        let changed = cache.descriptor("p", "public", "t", &[column("id", 20), column("price", 701), column("email", 25)]).unwrap();
+        assert_eq!(changed.numbers, [4, 3, 5]);
+        assert!(changed.proto().contains("reserved 1, 2;"));
+        // other tables are independent
+        assert_eq!(cache.descriptor("p", "public", "u", &[column("price", 701)]).unwrap().numbers, [1]);
+    }
+
+    #[test]
+    fn numbers_survive_a_restart_in_the_file() {
+        let path = std::env::temp_dir().join(format!("cyphercdc-field-numbers-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut cache = DescriptorCache::open(path.clone()).unwrap();
+        cache.descriptor("p", "public", "t", &[column("id", 23), column("name", 25)]).unwrap();
+        cache.descriptor("p", "public", "t", &[column("id", 23)]).unwrap();
+
+        let mut restarted = DescriptorCache::open(path.clone()).unwrap();
+        let descriptor = restarted.descriptor("p", "public", "t", &[column("id", 23), column("email", 25)]).unwrap();
+        assert_eq!(descriptor.numbers, [1, 3]);
+
+        // read leaves the file as it is
+        let mut exported = DescriptorCache::read(path.clone()).unwrap();
+        exported.descriptor("p", "public", "t", &[column("id", 23), column("phone", 25)]).unwrap();
+        let stored: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(stored["public"]["t"].as_array().unwrap().len(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn key_holds_the_primary_key_columns() {
+        // REPLICA IDENTITY FULL flags every column as key
+        let mut columns = [column("id", 23), column("name", 25)];
+        columns[0].primary_key = true;
+        columns.iter_mut().for_each(|column| column.key = true);
+        let descriptor = DescriptorCache::default().descriptor("p", "public", "t", &columns).unwrap();
+        let proto = descriptor.proto();
+        let key = &proto[proto.find("message Key").unwrap()..];
+        assert!(key.contains("optional int32 id = 1;") && !key.contains("name"), "{}", key);
+
+        columns[0].primary_key = false;
+        assert!(!DescriptorCache::default().descriptor("p", "public", "t", &columns).unwrap().has_key());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::modules::replication::dto::{ChangeEvent, ColumnValue, Operation, Transaction};
+use crate::modules::sink::avro::schema::avro_name;
+use crate::modules::sink::format::{parse_date, parse_time, parse_timestamp, unix_micros};
+use crate::modules::sink::protobuf::descriptor::{ProtoType, TableDescriptor};
+use crate::modules::sink::protobuf::wire::{put_bytes_field, put_string_field, put_tag, put_timestamp_field, put_varint_field,
+                                           FIXED32, FIXED64};
+use std::borrow::Cow;
+
+// the range of google.protobuf.Timestamp, 0001-01-01 to 9999-12-31
+const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+// the Key message, the primary key columns of the new row or of the old one for deletes
+pub fn encode_key(descriptor: &TableDescriptor, event: &ChangeEvent) -> Option<Vec<u8>> {
+    if !descriptor.has_key() {
+        return None;
+    }
+    let values = event.new.as_ref().or(event.old.as_ref())?;
+    let mut buf = Vec::new();
+    for (((column, value), kind), number) in event.columns.iter().zip(values).zip(&descriptor.types).zip(&descriptor.numbers) {
+        if column.primary_key {
+            put_column(&mut buf, *number, *kind, value);
+        }
+    }
+
+    Some(buf)
+}
+
+// the Change message; name and database fill its Source
+pub fn encode_change(descriptor: &TableDescriptor, event: &ChangeEvent, transaction: &Transaction, name: &str, database: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_varint_field(&mut buf, 1, operation_number(event.operation));
+    for (number, row) in [(2, &event.old), (3, &event.new)] {
+        if let Some(values) = row {
+            let mut encoded = Vec::new();
+            for ((value, kind), number) in values.iter().zip(&descriptor.types).zip(&descriptor.numbers) {
+                put_column(&mut encoded, *number, *kind, value);
+            }
+            put_bytes_field(&mut buf, number, &encoded);
+        }
+    }
+
+    let mut source = Vec::new();
+    for (number, value) in [(1, name), (2, database), (3, &event.schema), (4, &event.table)] {
+        if !value.is_empty() {
+            put_string_field(&mut source, number, value);
+        }
+    }
+    put_varint_field(&mut source, 5, event.lsn.0);
+    put_varint_field(&mut source, 6, transaction.commit_lsn.0);
+    if transaction.xid != 0 {
+        put_varint_field(&mut source, 7, transaction.xid as u64);
+    }
+    let commit_time = unix_micros(transaction.commit_time);
+    put_timestamp_field(&mut source, 8, commit_time.div_euclid(1_000_000), (commit_time.rem_euclid(1_000_000) * 1000) as i32);
+    put_bytes_field(&mut buf, 4, &source);
+
+    if let Some(values) = &event.new {
+        for (column, value) in event.columns.iter().zip(values) {
+            if *value == ColumnValue::UnchangedToast {
+                put_string_field(&mut buf, 5, &avro_name(&column.name));
+            }
+        }
+    }
+
+    buf
+}
+
+fn operation_number(operation: Operation) -> u64 {
+    match operation {
+        Operation::Insert => 1,
+        Operation::Update => 2,
+        Operation::Delete => 3,
+        Operation::Truncate => 4,
+        Operation::Read => 5,
+    }
+}
+
+// a field with explicit presence; nulls, unchanged TOAST values and values that do not fit the type are left out
+fn put_column(buf: &mut Vec<u8>, number: u32, kind: ProtoType, value: &ColumnValue) {
+    let text = match value {
+        ColumnValue::Null | ColumnValue::UnchangedToast => return,
+        ColumnValue::Binary(bytes) if kind == ProtoType::Bytes => return put_bytes_field(buf, number, bytes),
+        ColumnValue::Binary(bytes) => String::from_utf8_lossy(bytes),
+        ColumnValue::Text(text) => Cow::Borrowed(text.as_str()),
+    };
+    let text = text.as_ref();
+    match kind {
+        ProtoType::Bool => put_varint_field(buf, number, (text == "t") as u64),
+        ProtoType::Int32 => if let Ok(v) = text.parse::<i32>() {
+            put_varint_field(buf, number, v as i64 as u64);
+        },
+        ProtoType::Int64 => if let Ok(v) = text.parse::<i64>() {
+            put_varint_field(buf, number, v as u64);
+        },
+        ProtoType::Uint32 => if let Ok(v) = text.parse::<u32>() {
+            put_varint_field(buf, number, v as u64);
+        },
+        ProtoType::Float => if let Ok(v) = text.parse::<f32>() {
+            put_tag(buf, number, FIXED32);
+            buf.extend_from_slice(&v.to_le_bytes());
+        },
+        ProtoType::Double => if let Ok(v) = text.parse::<f64>() {
+            put_tag(buf, number, FIXED64);
+            buf.extend_from_slice(&v.to_le_bytes());
+        },
+        ProtoType::Bytes => if let Some(bytes) = text.strip_prefix("\\x").and_then(|hex| hex::decode(hex).ok()) {
+            put_bytes_field(buf, number, &bytes);
+        },
+        ProtoType::String => put_string_field(buf, number, text),
+        ProtoType::Date => {
+            let days = match text {
+                "infinity" => Some(i32::MAX as i64),
+                "-infinity" => Some(i32::MIN as i64),
+                _ => parse_date(text).filter(|days| i32::try_from(*days).is_ok()),
+            };
+            if let Some(days) = days {
+                put_varint_field(buf, number, days as u64);
+            }
+        },
+        ProtoType::TimeMicros => if let Some(micros) = parse_time(text) {
+            put_varint_field(buf, number, micros as u64);
+        },
+        ProtoType::Timestamp | ProtoType::LocalTimestamp => {
+            // PostgreSQL's infinite timestamps as the extremes of the type
+            let timestamp = match text {
+                "infinity" => Some((MAX_TIMESTAMP_SECONDS, 999_999_999)),
+                "-infinity" => Some((MIN_TIMESTAMP_SECONDS, 0)),
+                _ => parse_timestamp(text, kind == ProtoType::Timestamp)
+                    .map(|micros| (micros.div_euclid(1_000_000), (micros.rem_euclid(1_000_000) * 1000) as i32))
+                    .filter(|(seconds, _)| (MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(seconds)),
+            };
+            if let Some((seconds, nanos)) = timestamp {
+                put_timestamp_field(buf, number, seconds, nanos);
+            }
+        },
+    }
+}
@@ -0,0 +1,3 @@
+pub mod descriptor;
+pub mod encoding;
+pub mod wire;
\ No newline at end of file
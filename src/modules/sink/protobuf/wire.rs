@@ -0,0 +1,45 @@
+// the protobuf wire format, https://protobuf.dev/programming-guides/encoding/
+pub const VARINT: u32 = 0;
+pub const FIXED64: u32 = 1;
+pub const LENGTH_DELIMITED: u32 = 2;
+pub const FIXED32: u32 = 5;
+
+pub fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn put_tag(buf: &mut Vec<u8>, number: u32, wire_type: u32) {
+    put_varint(buf, ((number << 3) | wire_type) as u64);
+}
+
+// int32, int64, uint32, uint64, bool and enum values; negative numbers take ten bytes
+pub fn put_varint_field(buf: &mut Vec<u8>, number: u32, value: u64) {
+    put_tag(buf, number, VARINT);
+    put_varint(buf, value);
+}
+
+pub fn put_bytes_field(buf: &mut Vec<u8>, number: u32, bytes: &[u8]) {
+    put_tag(buf, number, LENGTH_DELIMITED);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+pub fn put_string_field(buf: &mut Vec<u8>, number: u32, value: &str) {
+    put_bytes_field(buf, number, value.as_bytes());
+}
+
+// google.protobuf.Timestamp, default fields are left out
+pub fn put_timestamp_field(buf: &mut Vec<u8>, number: u32, seconds: i64, nanos: i32) {
+    let mut timestamp = Vec::new();
+    if seconds != 0 {
+        put_varint_field(&mut timestamp, 1, seconds as u64);
+    }
+    if nanos != 0 {
+        put_varint_field(&mut timestamp, 2, nanos as i64 as u64);
+    }
+    put_bytes_field(buf, number, &timestamp);
+}
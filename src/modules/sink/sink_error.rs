@@ -0,0 +1,45 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+#[derive(Debug)]
+pub enum SinkError {
+    // the destination cannot be reached right now, worth a reconnect and redelivery
+    Unavailable(String),
+    // the destination refused the data, retrying would not change that
+    Rejected(String),
+}
+
+impl SinkError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SinkError::Unavailable(_) => true,
+            SinkError::Rejected(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Unavailable(msg) => write!(f, "Sink unavailable: {}", msg),
+            SinkError::Rejected(msg) => write!(f, "Sink rejected the data: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+impl From<std::io::Error> for SinkError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::InvalidData => SinkError::Rejected(e.to_string()),
+            _ => SinkError::Unavailable(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SinkError {
+    fn from(e: serde_json::Error) -> Self {
+        SinkError::Rejected(e.to_string())
+    }
+}
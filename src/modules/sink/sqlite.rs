@@ -0,0 +1,505 @@
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::sink::apply::{identity, primary_key, row, text_value, where_clause, AppliedLsn};
+use crate::modules::sink::format::{BOOL_OID, BYTEA_OID, FLOAT4_OID, FLOAT8_OID, INT2_OID, INT4_OID, INT8_OID, NUMERIC_OID, OID_OID};
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, ErrorCode, OptionalExtension, Transaction as DbTransaction};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info};
+
+pub const DEFAULT_TABLE_NAME: &str = "{schema}_{table}";
+pub const OFFSETS_TABLE: &str = "cyphercdc_offsets";
+// the source table of every mirror table, so two tables mapped to one name are noticed across restarts
+const SOURCES_TABLE: &str = "cyphercdc_sources";
+// how long a write waits for readers holding a lock on the file
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+    // table name template, {schema} and {table} are replaced
+    pub table_name: String,
+}
+
+// the type affinity a column is declared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Affinity {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+    Blob,
+}
+
+impl Affinity {
+    fn of(column: &Column) -> Affinity {
+        match column.type_oid {
+            BOOL_OID | INT8_OID | INT2_OID | INT4_OID | OID_OID => Affinity::Integer,
+            FLOAT4_OID | FLOAT8_OID => Affinity::Real,
+            NUMERIC_OID => Affinity::Numeric,
+            BYTEA_OID => Affinity::Blob,
+            // dates and timestamps keep their ISO text, which SQLite's date functions read
+            _ => Affinity::Text,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Affinity::Integer => "INTEGER",
+            Affinity::Real => "REAL",
+            Affinity::Numeric => "NUMERIC",
+            Affinity::Text => "TEXT",
+            Affinity::Blob => "BLOB",
+        }
+    }
+}
+
+// the mirror of a source table, for the columns of its current relation
+#[derive(Debug, Clone)]
+struct MirrorTable {
+    name: String,
+    source_columns: Vec<Column>,
+    primary_key: Vec<String>,
+}
+
+// Mirrors the published tables into a SQLite file, creating them from the relations. Every source
+// transaction is applied in one SQLite transaction, which also stores its end LSN; transactions at
+// or before the stored LSN were applied before a restart and are skipped
+pub struct SqliteSink {
+    pipeline: String,
+    config: SqliteConfig,
+    connection: Option<Connection>,
+    tables: HashMap<(String, String), MirrorTable>,
+    applied: AppliedLsn,
+}
+
+impl SqliteSink {
+    pub fn new(pipeline: &str, config: SqliteConfig) -> SqliteSink {
+        SqliteSink {
+            pipeline: pipeline.to_owned(),
+            config,
+            connection: None,
+            tables: HashMap::new(),
+            applied: AppliedLsn::default(),
+        }
+    }
+
+    fn open(&mut self) -> Result<(), SinkError> {
+        if self.connection.is_some() {
+            return Ok(());
+        }
+        let path = self.config.path.display().to_string();
+        let connection = Connection::open(&self.config.path).map_err(|e| sqlite_error(e, &format!("opening {}", path)))?;
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(|e| sqlite_error(e, "setting the busy timeout"))?;
+        // readers can query the file while changes are applied, every commit is synced before it is acknowledged
+        connection.pragma_update(None, "journal_mode", "WAL").map_err(|e| sqlite_error(e, "enabling WAL"))?;
+        connection.pragma_update(None, "synchronous", "FULL").map_err(|e| sqlite_error(e, "setting synchronous"))?;
+        connection.execute_batch(&format!("CREATE TABLE IF NOT EXISTS {} (pipeline TEXT PRIMARY KEY, lsn TEXT NOT NULL, \
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP)", OFFSETS_TABLE))
+            .map_err(|e| sqlite_error(e, &format!("creating {}", OFFSETS_TABLE)))?;
+        // SQLite compares table names ignoring ASCII case
+        connection.execute_batch(&format!("CREATE TABLE IF NOT EXISTS {} (name TEXT PRIMARY KEY COLLATE NOCASE, \
+            source_schema TEXT NOT NULL, source_table TEXT NOT NULL)", SOURCES_TABLE))
+            .map_err(|e| sqlite_error(e, &format!("creating {}", SOURCES_TABLE)))?;
+        let lsn: Option<String> = connection.query_row(&format!("SELECT lsn FROM {} WHERE pipeline = ?1", OFFSETS_TABLE),
+                                                       [&self.pipeline], |row| row.get(0)).optional()
+            .map_err(|e| sqlite_error(e, &format!("reading {}", OFFSETS_TABLE)))?;
+        match self.applied.load(lsn)? {
+            Some(applied) => info!(path, lsn = %applied, "SQLite mirror applied up to"),
+            None => debug!(path, "Opened SQLite mirror"),
+        }
+        self.connection = Some(connection);
+
+        Ok(())
+    }
+
+    fn apply(&mut self, connection: &mut Connection, batch: &Transaction) -> Result<(), SinkError> {
+        let transaction = connection.transaction().map_err(|e| sqlite_error(e, "starting a transaction"))?;
+        for event in &batch.events {
+            self.apply_event(&transaction, event)?;
+        }
+        let sql = format!("INSERT INTO {} (pipeline, lsn) VALUES (?1, ?2) \
+            ON CONFLICT (pipeline) DO UPDATE SET lsn = excluded.lsn, updated_at = CURRENT_TIMESTAMP", OFFSETS_TABLE);
+        transaction.execute(&sql, [&self.pipeline, &batch.end_lsn.to_string()])
+            .map_err(|e| sqlite_error(e, &format!("storing the applied LSN in {}", OFFSETS_TABLE)))?;
+        transaction.commit().map_err(|e| sqlite_error(e, "committing"))?;
+
+        Ok(())
+    }
+
+    fn apply_event(&mut self, transaction: &DbTransaction<'_>, event: &ChangeEvent) -> Result<(), SinkError> {
+        let table = self.table(transaction, event)?;
+        let name = quote(&table.name);
+
+        match event.operation {
+            Operation::Truncate => {
+                execute(transaction, &format!("DELETE FROM {}", name), Vec::new())?;
+            },
+            Operation::Insert | Operation::Read => {
+                upsert(transaction, &table, event, row(event, event.new.as_ref())?)?;
+            },
+            Operation::Update => {
+                let new = row(event, event.new.as_ref())?;
+                let mut params = Vec::new();
+                let mut assignments = Vec::new();
+                for (column, value) in event.columns.iter().zip(new) {
+                    // the server did not send unchanged TOAST values, the mirror keeps its own
+                    if *value != ColumnValue::UnchangedToast {
+                        params.push(sqlite_value(column, value)?);
+                        assignments.push(format!("{} = ?{}", quote(&column.name), params.len()));
+                    }
+                }
+                let condition = key_condition(event, identity(event)?, &mut params)?;
+                let sql = format!("UPDATE {} SET {}{}", name, assignments.join(", "), condition);
+                if execute(transaction, &sql, params)? == 0 {
+                    upsert(transaction, &table, event, new)?;
+                }
+            },
+            Operation::Delete => {
+                let mut params = Vec::new();
+                let sql = format!("DELETE FROM {}{}", name, key_condition(event, identity(event)?, &mut params)?);
+                execute(transaction, &sql, params)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    // the mirror of the event's table, created or extended first
+    fn table(&mut self, transaction: &DbTransaction<'_>, event: &ChangeEvent) -> Result<MirrorTable, SinkError> {
+        let key = (event.schema.clone(), event.table.clone());
+        if let Some(table) = self.tables.get(&key)
+            && table.source_columns == event.columns {
+            return Ok(table.clone());
+        }
+        let name = self.config.table_name.replace("{schema}", &event.schema).replace("{table}", &event.table);
+        claim_name(transaction, &name, event)?;
+
+        let mut mirror = mirror_columns(transaction, &name)?;
+        if mirror.is_empty() {
+            let mut definitions: Vec<String> = event.columns.iter()
+                .map(|c| format!("{} {}", quote(&c.name), Affinity::of(c).name()))
+                .collect();
+            let key: Vec<String> = primary_key(&event.columns).iter().map(|c| quote(&c.name)).collect();
+            if !key.is_empty() {
+                definitions.push(format!("PRIMARY KEY ({})", key.join(", ")));
+            }
+            let sql = format!("CREATE TABLE {} ({})", quote(&name), definitions.join(", "));
+            transaction.execute_batch(&sql).map_err(|e| sqlite_error(e, &format!("creating {}", name)))?;
+            info!(table = %name, "Created mirror table");
+            mirror = mirror_columns(transaction, &name)?;
+        }
+
+        let mut added = false;
+        for column in event.columns.iter().filter(|c| !mirror.iter().any(|(name, _)| *name == c.name)) {
+            let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", quote(&name), quote(&column.name), Affinity::of(column).name());
+            transaction.execute_batch(&sql).map_err(|e| sqlite_error(e, &sql))?;
+            info!(table = %name, column = %column.name, "Added column to mirror table");
+            added = true;
+        }
+        if added {
+            mirror = mirror_columns(transaction, &name)?;
+        }
+
+        let mut primary_key: Vec<(i64, String)> = mirror.into_iter().filter(|(_, position)| *position > 0)
+            .map(|(name, position)| (position, name)).collect();
+        primary_key.sort();
+        let table = MirrorTable {
+            name,
+            source_columns: event.columns.clone(),
+            primary_key: primary_key.into_iter().map(|(_, name)| name).collect(),
+        };
+        self.tables.insert(key, table.clone());
+
+        Ok(table)
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        if batch.events.is_empty() {
+            return Ok(());
+        }
+        self.open()?;
+        if self.applied.contains(batch) {
+            return Ok(());
+        }
+
+        let mut connection = self.connection.take().unwrap();
+        let result = self.apply(&mut connection, batch);
+        match &result {
+            Ok(()) => self.applied.advance(batch),
+            // rolled back, the tables are read again for the next attempt
+            Err(_) => self.tables.clear(),
+        }
+        self.connection = Some(connection);
+        result
+    }
+
+    // write_batch commits and syncs every batch, synchronous is FULL
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.connection = None;
+        Ok(())
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+// INSERT, replacing the non-key columns of an existing row with the same primary key
+fn upsert(transaction: &DbTransaction<'_>, table: &MirrorTable, event: &ChangeEvent, values: &[ColumnValue]) -> Result<(), SinkError> {
+    let mut columns = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut params = Vec::new();
+    for (column, value) in event.columns.iter().zip(values) {
+        if *value != ColumnValue::UnchangedToast {
+            params.push(sqlite_value(column, value)?);
+            columns.push(quote(&column.name));
+            placeholders.push(format!("?{}", params.len()));
+        }
+    }
+    let mut sql = format!("INSERT INTO {} ({}) VALUES ({})", quote(&table.name), columns.join(", "), placeholders.join(", "));
+    let key: Vec<String> = table.primary_key.iter().map(|c| quote(c)).collect();
+    let updates: Vec<String> = columns.iter().filter(|c| !key.contains(c)).map(|c| format!("{} = excluded.{}", c, c)).collect();
+    if !key.is_empty() {
+        match updates.is_empty() {
+            true => sql.push_str(" ON CONFLICT DO NOTHING"),
+            false => sql.push_str(&format!(" ON CONFLICT ({}) DO UPDATE SET {}", key.join(", "), updates.join(", "))),
+        }
+    }
+    execute(transaction, &sql, params)?;
+
+    Ok(())
+}
+
+// the key columns, IS matches nulls too
+fn key_condition(event: &ChangeEvent, values: &[ColumnValue], params: &mut Vec<Value>) -> Result<String, SinkError> {
+    where_clause(event, values, |_, column, value| {
+        params.push(sqlite_value(column, value)?);
+        Ok(format!("{} IS ?{}", quote(&column.name), params.len()))
+    })
+}
+
+// records the event's table as the source of the mirror table name, unless another table or
+// cyphercdc itself uses it already; e.g. a.b_c and a_b.c are both a_b_c with {schema}_{table}
+fn claim_name(transaction: &DbTransaction<'_>, name: &str, event: &ChangeEvent) -> Result<(), SinkError> {
+    let query = |e| sqlite_error(e, &format!("reading {}", SOURCES_TABLE));
+    if [OFFSETS_TABLE, SOURCES_TABLE].iter().any(|table| table.eq_ignore_ascii_case(name)) {
+        return Err(SinkError::Rejected(format!("{}.{} would be mirrored into {}, which cyphercdc uses, set another table_name",
+                                               event.schema, event.table, name)));
+    }
+    let source: Option<(String, String)> = transaction
+        .query_row(&format!("SELECT source_schema, source_table FROM {} WHERE name = ?1", SOURCES_TABLE), [name],
+                   |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional().map_err(query)?;
+    match source {
+        Some((schema, table)) if schema != event.schema || table != event.table => {
+            Err(SinkError::Rejected(format!("{}.{} and {}.{} would both be mirrored into {}, set a table_name that keeps them apart",
+                                            schema, table, event.schema, event.table, name)))
+        },
+        Some(_) => Ok(()),
+        None => {
+            let sql = format!("INSERT INTO {} (name, source_schema, source_table) VALUES (?1, ?2, ?3)", SOURCES_TABLE);
+            execute(transaction, &sql, vec![Value::Text(name.to_owned()), Value::Text(event.schema.clone()), Value::Text(event.table.clone())])?;
+            Ok(())
+        },
+    }
+}
+
+fn execute(transaction: &DbTransaction<'_>, sql: &str, params: Vec<Value>) -> Result<usize, SinkError> {
+    let mut statement = transaction.prepare_cached(sql).map_err(|e| sqlite_error(e, &format!("preparing {}", sql)))?;
+    statement.execute(params_from_iter(params)).map_err(|e| sqlite_error(e, &format!("executing {}", sql)))
+}
+
+// name and primary key position (0 outside of it) of the columns of a mirror table, none when it does not exist
+fn mirror_columns(transaction: &DbTransaction<'_>, name: &str) -> Result<Vec<(String, i64)>, SinkError> {
+    let query = |e| sqlite_error(e, &format!("reading the columns of {}", name));
+    let mut statement = transaction.prepare_cached("SELECT name, pk FROM pragma_table_info(?1)").map_err(query)?;
+    let rows = statement.query_map([name], |row| Ok((row.get(0)?, row.get(1)?))).map_err(query)?;
+    rows.collect::<Result<_, _>>().map_err(query)
+}
+
+// the text form converted for the column's affinity; values that do not parse are stored as text
+fn sqlite_value(column: &Column, value: &ColumnValue) -> Result<Value, SinkError> {
+    let Some(text) = text_value(value)? else {
+        return Ok(Value::Null);
+    };
+    let value = match Affinity::of(column) {
+        Affinity::Integer if column.type_oid == BOOL_OID => Some(Value::Integer((text == "t") as i64)),
+        Affinity::Integer => text.parse::<i64>().ok().map(Value::Integer),
+        // SQLite would store NaN as null, it stays text
+        Affinity::Real => text.parse::<f64>().ok().filter(|v| !v.is_nan()).map(Value::Real),
+        Affinity::Blob => text.strip_prefix("\\x").and_then(|hex| hex::decode(hex).ok()).map(Value::Blob),
+        Affinity::Numeric | Affinity::Text => None,
+    };
+
+    Ok(value.unwrap_or_else(|| Value::Text(text.to_owned())))
+}
+
+// a locked or full database is worth a retry, constraint violations and invalid SQL are not
+fn sqlite_error(e: rusqlite::Error, context: &str) -> SinkError {
+    let message = format!("{} failed on the SQLite mirror: {}", context, e);
+    match e.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked | ErrorCode::DiskFull | ErrorCode::SystemIoFailure
+             | ErrorCode::CannotOpen | ErrorCode::OutOfMemory) => SinkError::Unavailable(message),
+        _ => SinkError::Rejected(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::replication::lsn::Lsn;
+
+    const TEXT_OID: u32 = 25;
+
+    fn sink(path: &str) -> SqliteSink {
+        SqliteSink::new("p", SqliteConfig { path: PathBuf::from(path), table_name: DEFAULT_TABLE_NAME.to_owned() })
+    }
+
+    fn column(name: &str, type_oid: u32, key: bool, primary_key: bool) -> Column {
+        Column { name: name.to_owned(), type_oid, type_modifier: -1, key, primary_key, type_name: None }
+    }
+
+    // id int4 primary key, name text, price numeric, active bool
+    fn orders() -> Vec<Column> {
+        vec![column("id", INT4_OID, true, true), column("name", TEXT_OID, false, false),
+             column("price", NUMERIC_OID, false, false), column("active", BOOL_OID, false, false)]
+    }
+
+    fn values(values: &[Option<&str>]) -> Vec<ColumnValue> {
+        values.iter().map(|v| v.map_or(ColumnValue::Null, |v| ColumnValue::Text(v.to_owned()))).collect()
+    }
+
+    fn change(operation: Operation, columns: &[Column], old: Option<&[Option<&str>]>, new: Option<&[Option<&str>]>) -> ChangeEvent {
+        ChangeEvent { operation, columns: columns.to_vec(), old: old.map(values), new: new.map(values), ..event("public", "orders") }
+    }
+
+    fn batch(end_lsn: u64, events: Vec<ChangeEvent>) -> Transaction {
+        Transaction { xid: 1, commit_lsn: Lsn(end_lsn - 1), end_lsn: Lsn(end_lsn), commit_time: 0, events }
+    }
+
+    fn rows(connection: &Connection, sql: &str) -> Vec<Vec<Value>> {
+        let mut statement = connection.prepare(sql).unwrap();
+        let count = statement.column_count();
+        statement.query_map([], |row| (0..count).map(|i| row.get(i)).collect()).unwrap()
+            .collect::<Result<_, _>>().unwrap()
+    }
+
+    fn event(schema: &str, table: &str) -> ChangeEvent {
+        ChangeEvent {
+            lsn: Lsn(1),
+            xid: 1,
+            schema: schema.to_owned(),
+            table: table.to_owned(),
+            operation: Operation::Truncate,
+            columns: Vec::new(),
+            old: None,
+            new: None,
+        }
+    }
+
+    #[test]
+    fn claim_name_rejects_a_second_source_table() {
+        let mut sink = SqliteSink::new("p", SqliteConfig { path: PathBuf::from(":memory:"), table_name: DEFAULT_TABLE_NAME.to_owned() });
+        sink.open().unwrap();
+        let connection = sink.connection.as_mut().unwrap();
+        let transaction = connection.transaction().unwrap();
+
+        claim_name(&transaction, "a_b_c", &event("a", "b_c")).unwrap();
+        claim_name(&transaction, "a_b_c", &event("a", "b_c")).unwrap();
+        let error = claim_name(&transaction, "A_B_C", &event("a_b", "c")).unwrap_err();
+        assert!(error.to_string().contains("a.b_c and a_b.c would both be mirrored into A_B_C"), "{}", error);
+        assert!(claim_name(&transaction, "cyphercdc_offsets", &event("cyphercdc", "offsets")).is_err());
+    }
+
+    #[tokio::test]
+    async fn applies_inserts_updates_deletes_and_truncates() {
+        let mut sink = sink(":memory:");
+        let columns = orders();
+        sink.write_batch(&batch(0x10, vec![
+            change(Operation::Insert, &columns, None, Some(&[Some("1"), Some("a"), Some("1.50"), Some("t")])),
+            change(Operation::Insert, &columns, None, Some(&[Some("2"), Some("b"), None, Some("f")])),
+        ])).await.unwrap();
+        let table = "SELECT id, name, price, active FROM public_orders ORDER BY id";
+        assert_eq!(rows(sink.connection.as_ref().unwrap(), table), vec![
+            vec![Value::Integer(1), Value::Text(String::from("a")), Value::Real(1.5), Value::Integer(1)],
+            vec![Value::Integer(2), Value::Text(String::from("b")), Value::Null, Value::Integer(0)],
+        ]);
+
+        sink.write_batch(&batch(0x20, vec![
+            change(Operation::Update, &columns, None, Some(&[Some("1"), Some("c"), Some("2"), Some("t")])),
+            change(Operation::Delete, &columns, Some(&[Some("2"), None, None, None]), None),
+        ])).await.unwrap();
+        assert_eq!(rows(sink.connection.as_ref().unwrap(), table), vec![
+            vec![Value::Integer(1), Value::Text(String::from("c")), Value::Integer(2), Value::Integer(1)],
+        ]);
+
+        sink.write_batch(&batch(0x30, vec![change(Operation::Truncate, &columns, None, None)])).await.unwrap();
+        assert!(rows(sink.connection.as_ref().unwrap(), table).is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_update_of_a_missing_row_inserts_it() {
+        let mut sink = sink(":memory:");
+        let columns = orders();
+        sink.write_batch(&batch(0x10, vec![
+            change(Operation::Update, &columns, None, Some(&[Some("7"), Some("late"), None, Some("f")])),
+        ])).await.unwrap();
+        assert_eq!(rows(sink.connection.as_ref().unwrap(), "SELECT id, name FROM public_orders"),
+                   vec![vec![Value::Integer(7), Value::Text(String::from("late"))]]);
+    }
+
+    #[tokio::test]
+    async fn full_identity_deletes_match_every_column_nulls_included() {
+        let mut sink = sink(":memory:");
+        // REPLICA IDENTITY FULL on a table without a primary key
+        let columns = vec![column("id", INT4_OID, true, false), column("note", TEXT_OID, true, false)];
+        sink.write_batch(&batch(0x10, vec![
+            change(Operation::Insert, &columns, None, Some(&[Some("1"), None])),
+            change(Operation::Insert, &columns, None, Some(&[Some("1"), Some("x")])),
+            change(Operation::Insert, &columns, None, Some(&[Some("2"), None])),
+        ])).await.unwrap();
+        sink.write_batch(&batch(0x20, vec![change(Operation::Delete, &columns, Some(&[Some("1"), None]), None)])).await.unwrap();
+
+        assert_eq!(rows(sink.connection.as_ref().unwrap(), "SELECT id, note FROM public_orders ORDER BY id, note"), vec![
+            vec![Value::Integer(1), Value::Text(String::from("x"))],
+            vec![Value::Integer(2), Value::Null],
+        ]);
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_lsn_stored_in_the_offsets_table() {
+        // a shared in-memory database outlives the sink's connection while this one is open
+        let path = "file:sqlite_resume?mode=memory&cache=shared";
+        let observer = Connection::open(path).unwrap();
+        let columns = orders();
+        let insert = |id| change(Operation::Insert, &columns, None, Some(&[Some(id), None, None, None]));
+
+        let mut first = sink(path);
+        first.write_batch(&batch(0x20, vec![insert("1")])).await.unwrap();
+        first.close().await.unwrap();
+        assert_eq!(rows(&observer, "SELECT pipeline, lsn FROM cyphercdc_offsets"),
+                   vec![vec![Value::Text(String::from("p")), Value::Text(String::from("0/20"))]]);
+
+        let mut second = sink(path);
+        second.write_batch(&batch(0x20, vec![insert("2")])).await.unwrap();
+        second.write_batch(&batch(0x30, vec![insert("3")])).await.unwrap();
+        assert_eq!(rows(&observer, "SELECT id FROM public_orders ORDER BY id"), vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]);
+        assert_eq!(rows(&observer, "SELECT lsn FROM cyphercdc_offsets"), vec![vec![Value::Text(String::from("0/30"))]]);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::modules::replication::dto::Transaction;
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use std::io::Write;
+
+// Prints every event and a commit line per transaction, prefixed with the pipeline name
+pub struct StdoutSink {
+    pipeline: String,
+}
+
+impl StdoutSink {
+    pub fn new(pipeline: &str) -> StdoutSink {
+        StdoutSink { pipeline: pipeline.to_owned() }
+    }
+}
+
+#[async_trait]
+impl Sink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        let mut out = std::io::stdout().lock();
+        for event in &batch.events {
+            writeln!(out, "[{}] {}", self.pipeline, event)?;
+        }
+        writeln!(out, "[{}] COMMIT xid={} lsn={} time={} events={}", self.pipeline, batch.xid,
+                 batch.commit_lsn, batch.commit_time, batch.events.len())?;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        std::io::stdout().flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::modules::replication::dto::Transaction;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::sink::sink_error::SinkError;
+use async_trait::async_trait;
+
+// Destination of change events. The replication loop writes one batch per source transaction,
+// calls flush before it acknowledges anything to the server and only advances the slot to the
+// end of the last batch written before a successful flush. Batches after the acknowledged LSN
+// are delivered again after a reconnect or restart, so a sink sees every change at least once.
+#[async_trait]
+pub trait Sink: Send {
+    // label for logs and metrics
+    fn name(&self) -> &str;
+
+    // system identifier of the source server, after every connect and before the first batch
+    fn identify(&mut self, _system_id: &str) {}
+
+    // may buffer, nothing written is considered delivered until flush returns
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError>;
+
+    // makes every batch written so far durable
+    async fn flush(&mut self) -> Result<(), SinkError>;
+
+    // the server was told that everything up to lsn is delivered, state kept for redelivery can go
+    async fn acknowledge(&mut self, _lsn: Lsn) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), SinkError> {
+        self.flush().await
+    }
+}
@@ -0,0 +1,98 @@
+use crate::modules::replication::dto::Transaction;
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Builder, Runtime};
+
+// flush at least this often while batches are pending, and after this many batches
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_UNFLUSHED_BATCHES: usize = 1000;
+
+// Drives a sink from the synchronous replication loop and tracks which LSN is durable
+pub struct SinkDriver {
+    sink: Box<dyn Sink>,
+    // created on first use, sinks may spawn tasks or use tokio I/O
+    runtime: Option<Runtime>,
+    // end LSN of the last batch written
+    written: Option<Lsn>,
+    acknowledged: Option<Lsn>,
+    unflushed: usize,
+    last_flush: Instant,
+}
+
+impl SinkDriver {
+    pub fn new(sink: Box<dyn Sink>) -> SinkDriver {
+        SinkDriver { sink, runtime: None, written: None, acknowledged: None, unflushed: 0, last_flush: Instant::now() }
+    }
+
+    pub fn name(&self) -> &str {
+        self.sink.name()
+    }
+
+    pub fn identify(&mut self, system_id: &str) {
+        self.sink.identify(system_id);
+    }
+
+    fn start_runtime(&mut self) -> Result<(), SinkError> {
+        if self.runtime.is_none() {
+            self.runtime = Some(Builder::new_current_thread().enable_all().build()?);
+        }
+
+        Ok(())
+    }
+
+    pub fn write(&mut self, transaction: &Transaction) -> Result<(), SinkError> {
+        self.start_runtime()?;
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime.block_on(self.sink.write_batch(transaction))?;
+        self.written = Some(transaction.end_lsn);
+        self.unflushed += 1;
+
+        Ok(())
+    }
+
+    // batches were written that the server must not be told about yet
+    pub fn has_unflushed(&self) -> bool {
+        self.unflushed > 0
+    }
+
+    pub fn flush_due(&self) -> bool {
+        self.unflushed >= MAX_UNFLUSHED_BATCHES || (self.unflushed > 0 && self.last_flush.elapsed() >= FLUSH_INTERVAL)
+    }
+
+    // the end LSN of the last batch, once everything written is durable
+    pub fn flush(&mut self) -> Result<Option<Lsn>, SinkError> {
+        if self.unflushed == 0 {
+            return Ok(None);
+        }
+        self.start_runtime()?;
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime.block_on(self.sink.flush())?;
+        self.unflushed = 0;
+        self.last_flush = Instant::now();
+
+        Ok(self.written)
+    }
+
+    pub fn acknowledge(&mut self, lsn: Lsn) -> Result<(), SinkError> {
+        if self.acknowledged.is_some_and(|acknowledged| acknowledged >= lsn) {
+            return Ok(());
+        }
+        self.start_runtime()?;
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime.block_on(self.sink.acknowledge(lsn))?;
+        self.acknowledged = Some(lsn);
+
+        Ok(())
+    }
+
+    pub fn close(&mut self) -> Result<(), SinkError> {
+        self.start_runtime()?;
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime.block_on(self.sink.close())?;
+        self.unflushed = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+use crate::modules::replication::dto::Transaction;
+use crate::modules::secret::SecretString;
+use crate::modules::sink::format::{CloudEventsMode, EventEncoder, EventFormat};
+use crate::modules::sink::http_client::{HttpClient, HttpResponse};
+use crate::modules::sink::sink_error::SinkError;
+use crate::modules::sink::traits::Sink;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-Cyphercdc-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Cyphercdc-Timestamp";
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+// a Retry-After beyond this is not waited for in one attempt
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    // http://host[:port]/path the batches are POSTed to
+    pub url: String,
+    pub format: EventFormat,
+    // events per request
+    pub batch_size: usize,
+    // pending events are sent once the oldest waited this long, at the latest on flush
+    pub linger: Duration,
+    pub headers: Vec<(String, SecretString)>,
+    // signs every request with HMAC-SHA256 when set
+    pub hmac_secret: Option<SecretString>,
+    pub timeout: Duration,
+    pub retries: u32,
+    // consecutive failed requests that open the circuit, and how long it stays open
+    pub failure_threshold: u32,
+    pub open_duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CircuitState {
+    Closed { failures: u32 },
+    // requests fail without reaching the endpoint until then
+    Open { until: Instant },
+    // the cooldown is over, the next request decides
+    HalfOpen,
+}
+
+// Stops hammering an endpoint that keeps failing: after failure_threshold failed requests in a
+// row the circuit opens and requests fail at once, after open_duration one request is let through
+struct CircuitBreaker {
+    state: CircuitState,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl CircuitBreaker {
+    fn check(&mut self, url: &str) -> Result<(), SinkError> {
+        if let CircuitState::Open { until } = self.state {
+            let now = Instant::now();
+            if now < until {
+                return Err(SinkError::Unavailable(format!("circuit to {} is open for another {:?}", url, until - now)));
+            }
+            self.state = CircuitState::HalfOpen;
+        }
+
+        Ok(())
+    }
+
+    fn record_success(&mut self, url: &str) {
+        if !matches!(self.state, CircuitState::Closed { .. }) {
+            info!(url, "Circuit closed");
+        }
+        self.state = CircuitState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&mut self, url: &str) {
+        let failures = match self.state {
+            CircuitState::Closed { failures } => failures + 1,
+            _ => self.failure_threshold,
+        };
+        self.state = if failures >= self.failure_threshold {
+            warn!(url, failures, "Circuit opened for {:?}", self.open_duration);
+            CircuitState::Open { until: Instant::now() + self.open_duration }
+        } else {
+            CircuitState::Closed { failures }
+        };
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self.state, CircuitState::Open { .. })
+    }
+}
+
+// POSTs the events as JSON arrays, up to batch_size per request. flush returns once the endpoint
+// answered 2xx to every request; 5xx, 408, 429 and timeouts are retried with backoff, any other
+// status fails the pipeline
+pub struct WebhookSink {
+    config: WebhookConfig,
+    encoder: EventEncoder,
+    client: HttpClient,
+    breaker: CircuitBreaker,
+    // encoded events not sent yet
+    pending: Vec<Vec<u8>>,
+    pending_since: Option<Instant>,
+}
+
+impl WebhookSink {
+    pub fn new(pipeline: &str, database: &str, config: WebhookConfig) -> Result<WebhookSink, SinkError> {
+        let client = HttpClient::new(&config.url, config.timeout).map_err(SinkError::Rejected)?;
+
+        Ok(WebhookSink {
+            encoder: EventEncoder::new(config.format, pipeline, database),
+            client,
+            breaker: CircuitBreaker {
+                state: CircuitState::Closed { failures: 0 },
+                failure_threshold: config.failure_threshold,
+                open_duration: config.open_duration,
+            },
+            config,
+            pending: Vec::new(),
+            pending_since: None,
+        })
+    }
+
+    async fn add_events(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        for (index, event) in batch.events.iter().enumerate() {
+            self.pending.push(self.encoder.value(event, batch, index)?);
+            self.pending_since.get_or_insert_with(Instant::now);
+        }
+        while self.pending.len() >= self.config.batch_size {
+            self.send_batch().await?;
+        }
+        if self.pending_since.is_some_and(|since| since.elapsed() >= self.config.linger) {
+            self.send_pending().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_pending(&mut self) -> Result<(), SinkError> {
+        while !self.pending.is_empty() {
+            self.send_batch().await?;
+        }
+
+        Ok(())
+    }
+
+    // the first batch_size pending events in one request
+    async fn send_batch(&mut self) -> Result<(), SinkError> {
+        let count = self.pending.len().min(self.config.batch_size);
+        let mut body = Vec::from(*b"[");
+        for (i, event) in self.pending[..count].iter().enumerate() {
+            if i > 0 {
+                body.push(b',');
+            }
+            body.extend_from_slice(event);
+        }
+        body.push(b']');
+        self.send(&body).await?;
+        self.pending.drain(..count);
+        self.pending_since = (!self.pending.is_empty()).then(Instant::now);
+
+        Ok(())
+    }
+
+    async fn send(&mut self, body: &[u8]) -> Result<(), SinkError> {
+        let url = self.client.url();
+        self.breaker.check(&url)?;
+        let mut attempt = 0;
+        loop {
+            let (error, retry_after) = match self.client.request("POST", "", &self.headers(body), body).await {
+                Ok(response) if response.is_success() => {
+                    self.breaker.record_success(&url);
+                    return Ok(());
+                },
+                Ok(response) => match response.error(format!("{} answered with status {}: {}", url, response.status, excerpt(&response))) {
+                    // the endpoint is up and refused the data, that is not for the circuit breaker to count
+                    error @ SinkError::Rejected(_) => return Err(error),
+                    error => (error, retry_after(&response)),
+                },
+                Err(e) => (e, None),
+            };
+            self.breaker.record_failure(&url);
+            attempt += 1;
+            if attempt > self.config.retries || self.breaker.is_open() {
+                return Err(error);
+            }
+            let backoff = (Duration::from_millis(100) * 2u32.pow(attempt.min(7))).min(MAX_RETRY_BACKOFF);
+            let delay = retry_after.map_or(backoff, |after| after.clamp(backoff, MAX_RETRY_AFTER));
+            warn!(url, attempt, "Retrying webhook request in {:?}: {}", delay, error);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn headers(&self, body: &[u8]) -> Vec<(String, String)> {
+        let content_type = match self.config.format {
+            EventFormat::CloudEvents { mode: CloudEventsMode::Structured } => "application/cloudevents-batch+json",
+            _ => "application/json",
+        };
+        let mut headers = vec![(String::from("Content-Type"), content_type.to_owned())];
+        if let Some(secret) = &self.config.hmac_secret {
+            // the timestamp is signed with the body, so receivers can reject replayed requests
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string();
+            headers.push((String::from(TIMESTAMP_HEADER), timestamp.clone()));
+            headers.push((String::from(SIGNATURE_HEADER), format!("sha256={}", sign(secret, &timestamp, body))));
+        }
+        headers.extend(self.config.headers.iter().map(|(name, value)| (name.clone(), value.expose().clone())));
+
+        headers
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn identify(&mut self, system_id: &str) {
+        self.encoder.set_system_id(system_id);
+    }
+
+    async fn write_batch(&mut self, batch: &Transaction) -> Result<(), SinkError> {
+        let result = self.add_events(batch).await;
+        if result.is_err() {
+            // nothing pending was acknowledged, it all comes again after the reconnect
+            self.pending.clear();
+            self.pending_since = None;
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        let result = self.send_pending().await;
+        if result.is_err() {
+            self.pending.clear();
+            self.pending_since = None;
+        }
+        result
+    }
+}
+
+// hex HMAC-SHA256 of "<timestamp>.<body>"
+fn sign(secret: &SecretString, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.expose().as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// the start of the response body, for the error message
+fn excerpt(response: &HttpResponse) -> String {
+    String::from_utf8_lossy(&response.body).trim().chars().take(200).collect()
+}
+
+// Retry-After in seconds, the HTTP date form is ignored
+fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    response.header("retry-after").and_then(|value| value.trim().parse::<u64>().ok()).map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation};
+    use crate::modules::replication::lsn::Lsn;
+    use crate::modules::sink::http_client::mock::{response, MockServer};
+
+    fn config(server: &MockServer) -> WebhookConfig {
+        WebhookConfig {
+            url: format!("{}/hook", server.url),
+            format: EventFormat::Json,
+            batch_size: 10,
+            linger: Duration::from_secs(3600),
+            headers: Vec::new(),
+            hmac_secret: None,
+            timeout: Duration::from_secs(5),
+            retries: 3,
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(60),
+        }
+    }
+
+    fn transaction(ids: &[&str]) -> Transaction {
+        let column = Column { name: String::from("id"), type_oid: 23, type_modifier: -1, key: true, primary_key: true, type_name: None };
+        let events = ids.iter().map(|id| ChangeEvent {
+            lsn: Lsn(0x16B_3748),
+            xid: 740,
+            schema: String::from("public"),
+            table: String::from("orders"),
+            operation: Operation::Insert,
+            columns: vec![column.clone()],
+            old: None,
+            new: Some(vec![ColumnValue::Text(id.to_string())]),
+        }).collect();
+
+        Transaction { xid: 740, commit_lsn: Lsn(0x16B_3748), end_lsn: Lsn(0x16B_3780), commit_time: 0, events }
+    }
+
+    async fn deliver(sink: &mut WebhookSink, ids: &[&str]) -> Result<(), SinkError> {
+        sink.write_batch(&transaction(ids)).await?;
+        sink.flush().await
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn signs_the_timestamp_and_body_and_sends_the_configured_headers() {
+        let server = MockServer::start(vec![response(200, "")]).await;
+        let mut config = config(&server);
+        config.hmac_secret = Some(SecretString::new(String::from("s3cret")));
+        config.headers = vec![(String::from("Authorization"), SecretString::new(String::from("Bearer t0ken")))];
+        let mut sink = WebhookSink::new("inv", "mydb", config).unwrap();
+        deliver(&mut sink, &["1"]).await.unwrap();
+
+        let request = &server.requests()[0];
+        let body = request.strip_prefix("POST /hook ").unwrap();
+        assert!(body.starts_with('[') && body.ends_with(']'));
+        let headers = &server.headers()[0];
+        assert_eq!(header(headers, "Content-Type"), Some("application/json"));
+        assert_eq!(header(headers, "Authorization"), Some("Bearer t0ken"));
+        let timestamp = header(headers, TIMESTAMP_HEADER).unwrap();
+        let mut mac = HmacSha256::new_from_slice(b"s3cret").unwrap();
+        mac.update(format!("{}.{}", timestamp, body).as_bytes());
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(header(headers, SIGNATURE_HEADER), Some(expected.as_str()));
+    }
+
+    #[tokio::test]
+    async fn sends_no_signature_without_a_secret() {
+        let server = MockServer::start(vec![response(204, "")]).await;
+        let mut sink = WebhookSink::new("inv", "mydb", config(&server)).unwrap();
+        deliver(&mut sink, &["1", "2"]).await.unwrap();
+
+        let headers = &server.headers()[0];
+        assert_eq!((header(headers, SIGNATURE_HEADER), header(headers, TIMESTAMP_HEADER)), (None, None));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_and_timeouts() {
+        // the first request is never answered
+        let server = MockServer::start(vec![String::new(), response(503, "busy"), response(200, "")]).await;
+        let mut config = config(&server);
+        config.timeout = Duration::from_millis(200);
+        let mut sink = WebhookSink::new("inv", "mydb", config).unwrap();
+        deliver(&mut sink, &["1"]).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|request| request == &requests[0]));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_configured_retries() {
+        let server = MockServer::start(vec![response(500, ""), response(500, ""), response(200, "")]).await;
+        let mut config = config(&server);
+        config.retries = 1;
+        let mut sink = WebhookSink::new("inv", "mydb", config).unwrap();
+        match deliver(&mut sink, &["1"]).await {
+            Err(SinkError::Unavailable(message)) => assert!(message.contains("answered with status 500"), "{}", message),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn acknowledges_only_success_statuses() {
+        for status in [302, 400, 404] {
+            let server = MockServer::start(vec![response(status, "no"), response(200, "")]).await;
+            let mut sink = WebhookSink::new("inv", "mydb", config(&server)).unwrap();
+            match deliver(&mut sink, &["1"]).await {
+                Err(SinkError::Rejected(message)) => assert!(message.contains(&format!("status {}: no", status)), "{}", message),
+                other => panic!("unexpected {:?} for {}", other, status),
+            }
+            // not retried, and nothing is left pending for the next flush
+            assert_eq!(server.requests().len(), 1);
+            sink.flush().await.unwrap();
+            assert_eq!(server.requests().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn an_open_circuit_fails_without_reaching_the_endpoint() {
+        let server = MockServer::start(vec![response(500, ""), response(500, ""), response(200, "")]).await;
+        let mut config = config(&server);
+        config.failure_threshold = 2;
+        config.retries = 5;
+        let mut sink = WebhookSink::new("inv", "mydb", config).unwrap();
+        assert!(deliver(&mut sink, &["1"]).await.is_err());
+        assert_eq!(server.requests().len(), 2);
+        match deliver(&mut sink, &["1"]).await {
+            Err(SinkError::Unavailable(message)) => assert!(message.contains("is open"), "{}", message),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn circuit_opens_half_opens_and_closes() {
+        let mut breaker = CircuitBreaker {
+            state: CircuitState::Closed { failures: 0 },
+            failure_threshold: 2,
+            open_duration: Duration::from_millis(50),
+        };
+        breaker.record_failure("url");
+        assert_eq!(breaker.state, CircuitState::Closed { failures: 1 });
+        breaker.record_success("url");
+        assert_eq!(breaker.state, CircuitState::Closed { failures: 0 });
+        breaker.record_failure("url");
+        breaker.record_failure("url");
+        assert!(breaker.is_open());
+        assert!(matches!(breaker.check("url"), Err(SinkError::Unavailable(_))));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.check("url").is_ok());
+        assert_eq!(breaker.state, CircuitState::HalfOpen);
+        // one failure in half-open opens it again
+        breaker.record_failure("url");
+        assert!(breaker.is_open());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.check("url").is_ok());
+        breaker.record_success("url");
+        assert_eq!(breaker.state, CircuitState::Closed { failures: 0 });
+    }
+}
@@ -0,0 +1,2 @@
+pub mod monitor;
+pub mod utils;
\ No newline at end of file
@@ -0,0 +1,384 @@
+use crate::dto::DBConfig;
+use crate::modules::db::connect_to;
+use crate::modules::shutdown::ShutdownSignal;
+use crate::modules::slot::utils::drop_slot;
+use postgres::Client;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// current WAL position, the replay position on a standby
+const HEALTH_QUERY: &str = "WITH wal AS (SELECT CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn() \
+        ELSE pg_current_wal_lsn() END AS lsn) \
+    SELECT s.slot_name::text, s.active, to_jsonb(s)->>'wal_status' AS wal_status, \
+        (wal.lsn - s.restart_lsn)::bigint AS retained_bytes, \
+        (wal.lsn - s.confirmed_flush_lsn)::bigint AS lag_bytes, \
+        (to_jsonb(s)->>'safe_wal_size')::bigint AS safe_wal_size, \
+        extract(epoch FROM now() - (to_jsonb(s)->>'inactive_since')::timestamptz)::float8 AS inactive_seconds, \
+        extract(epoch FROM r.flush_lag)::float8 AS flush_lag_seconds \
+    FROM pg_replication_slots s CROSS JOIN wal LEFT JOIN pg_stat_replication r ON r.pid = s.active_pid \
+    WHERE s.slot_name = ANY($1)";
+
+#[derive(Debug, Clone)]
+pub struct SlotHealth {
+    pub slot_name: String,
+    pub active: bool,
+    // reserved, extended, unreserved or lost (PostgreSQL 13+)
+    pub wal_status: Option<String>,
+    pub retained_bytes: Option<i64>,
+    // WAL written since the consumer's confirmed_flush_lsn
+    pub lag_bytes: Option<i64>,
+    // bytes left before the slot is invalidated by max_slot_wal_keep_size
+    pub safe_wal_size: Option<i64>,
+    pub flush_lag_seconds: Option<f64>,
+    // from the server on PostgreSQL 17+, otherwise measured by the monitor
+    pub inactive_seconds: Option<f64>,
+}
+
+impl fmt::Display for SlotHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+        write!(f, "{} active={} wal_status={} retained={} lag={} lag_time={} inactive={} safe_wal_size={}",
+               self.slot_name, self.active, optional(self.wal_status.clone()),
+               optional(self.retained_bytes.map(format_bytes)), optional(self.lag_bytes.map(format_bytes)),
+               optional(self.flush_lag_seconds.map(|s| format!("{:.1}s", s))),
+               optional(self.inactive_seconds.map(|s| format!("{:.0}s", s))),
+               optional(self.safe_wal_size.map(format_bytes)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alert {
+    RetainedWal,
+    LagBytes,
+    LagTime,
+    Inactive,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alert::RetainedWal => write!(f, "retained WAL"),
+            Alert::LagBytes => write!(f, "lag"),
+            Alert::LagTime => write!(f, "lag time"),
+            Alert::Inactive => write!(f, "inactive time"),
+        }
+    }
+}
+
+// unset thresholds are not checked
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    pub retained_bytes: Option<i64>,
+    pub lag_bytes: Option<i64>,
+    pub lag_time: Option<Duration>,
+    pub inactive: Option<Duration>,
+}
+
+// drop an inactive slot that has been abandoned for this long and holds at least this much WAL
+#[derive(Debug, Clone)]
+pub struct DropPolicy {
+    pub inactive_for: Duration,
+    pub min_retained_bytes: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum SlotEvent {
+    Status(SlotHealth),
+    ThresholdCrossed { slot: String, alert: Alert, value: String, threshold: String },
+    Recovered { slot: String, alert: Alert },
+    WalStatusChanged { slot: String, from: String, to: String },
+    Missing { slot: String },
+    Dropped { slot: String, reason: String },
+    PollFailed { error: String },
+}
+
+impl SlotEvent {
+    // events an operator should look at
+    pub fn is_warning(&self) -> bool {
+        match self {
+            SlotEvent::ThresholdCrossed { .. } | SlotEvent::Missing { .. } | SlotEvent::Dropped { .. } | SlotEvent::PollFailed { .. } => true,
+            SlotEvent::WalStatusChanged { to, .. } => to == "unreserved" || to == "lost",
+            SlotEvent::Status(_) | SlotEvent::Recovered { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for SlotEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotEvent::Status(health) => write!(f, "{}", health),
+            SlotEvent::ThresholdCrossed { slot, alert, value, threshold } =>
+                write!(f, "slot {} {} is {}, above {}", slot, alert, value, threshold),
+            SlotEvent::Recovered { slot, alert } => write!(f, "slot {} {} is back below the threshold", slot, alert),
+            SlotEvent::WalStatusChanged { slot, from, to } => write!(f, "slot {} wal_status changed from {} to {}", slot, from, to),
+            SlotEvent::Missing { slot } => write!(f, "slot {} does not exist", slot),
+            SlotEvent::Dropped { slot, reason } => write!(f, "dropped slot {}: {}", slot, reason),
+            SlotEvent::PollFailed { error } => write!(f, "could not poll slots: {}", error),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SlotState {
+    alerts: HashSet<Alert>,
+    wal_status: Option<String>,
+    // when the monitor first saw the slot inactive, for servers that do not report inactive_since
+    inactive_since: Option<Instant>,
+    missing: bool,
+}
+
+type EventListener = Box<dyn Fn(&SlotEvent) + Send>;
+
+// Polls pg_replication_slots and pg_stat_replication for a set of slots, reports threshold
+// crossings once per crossing and optionally drops abandoned slots before they fill the disk
+pub struct SlotMonitor {
+    config: DBConfig,
+    slots: Vec<String>,
+    interval: Duration,
+    thresholds: Thresholds,
+    drop_policy: Option<DropPolicy>,
+    listeners: Vec<EventListener>,
+    shutdown: ShutdownSignal,
+    states: HashMap<String, SlotState>,
+}
+
+impl SlotMonitor {
+    pub fn new(config: DBConfig, slots: Vec<String>) -> SlotMonitor {
+        SlotMonitor {
+            config,
+            slots,
+            interval: Duration::from_secs(30),
+            thresholds: Thresholds::default(),
+            drop_policy: None,
+            listeners: Vec::new(),
+            shutdown: ShutdownSignal::new(),
+            states: HashMap::new(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> SlotMonitor {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_thresholds(mut self, thresholds: Thresholds) -> SlotMonitor {
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn with_drop_policy(mut self, policy: DropPolicy) -> SlotMonitor {
+        self.drop_policy = Some(policy);
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: ShutdownSignal) -> SlotMonitor {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn on_event<F>(mut self, listener: F) -> SlotMonitor
+    where F: Fn(&SlotEvent) + Send + 'static {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    // polls until shutdown, reconnecting after failures
+    pub fn run(&mut self) {
+        let mut client: Option<Client> = None;
+        while !self.shutdown.is_triggered() {
+            if let Err(e) = self.poll_with(&mut client) {
+                client = None;
+                self.emit(SlotEvent::PollFailed { error: e.to_string() });
+            }
+            let deadline = Instant::now() + self.interval;
+            while !self.shutdown.is_triggered() && Instant::now() < deadline {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(200)));
+            }
+        }
+    }
+
+    pub fn poll_once(&mut self) -> Result<(), Box<dyn Error>> {
+        self.poll_with(&mut None)
+    }
+
+    fn poll_with(&mut self, client: &mut Option<Client>) -> Result<(), Box<dyn Error>> {
+        if client.is_none() {
+            *client = Some(connect_to(&self.config)?);
+        }
+        let client = client.as_mut().unwrap();
+        let rows = client.query(HEALTH_QUERY, &[&self.slots])?;
+        let mut seen = HashSet::new();
+        for row in &rows {
+            let mut health = SlotHealth {
+                slot_name: row.get("slot_name"),
+                active: row.get("active"),
+                wal_status: row.get("wal_status"),
+                retained_bytes: row.get("retained_bytes"),
+                lag_bytes: row.get("lag_bytes"),
+                safe_wal_size: row.get("safe_wal_size"),
+                flush_lag_seconds: row.get("flush_lag_seconds"),
+                inactive_seconds: row.get("inactive_seconds"),
+            };
+            seen.insert(health.slot_name.clone());
+            let state = self.states.entry(health.slot_name.clone()).or_default();
+            state.missing = false;
+            if health.active {
+                state.inactive_since = None;
+            } else if health.inactive_seconds.is_none() {
+                let since = *state.inactive_since.get_or_insert_with(Instant::now);
+                health.inactive_seconds = Some(since.elapsed().as_secs_f64());
+            }
+            self.evaluate(client, health)?;
+        }
+
+        for slot in self.slots.clone() {
+            let state = self.states.entry(slot.clone()).or_default();
+            if !seen.contains(&slot) && !state.missing {
+                state.missing = true;
+                self.emit(SlotEvent::Missing { slot });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&mut self, client: &mut Client, health: SlotHealth) -> Result<(), Box<dyn Error>> {
+        let slot = health.slot_name.clone();
+        let mut events = vec![SlotEvent::Status(health.clone())];
+        let state = self.states.entry(slot.clone()).or_default();
+
+        if let Some(status) = &health.wal_status {
+            if let Some(previous) = &state.wal_status
+                && previous != status {
+                events.push(SlotEvent::WalStatusChanged { slot: slot.clone(), from: previous.clone(), to: status.clone() });
+            }
+            state.wal_status = Some(status.clone());
+        }
+
+        let checks = [
+            (Alert::RetainedWal, health.retained_bytes.zip(self.thresholds.retained_bytes)
+                .map(|(v, t)| (v > t, format_bytes(v), format_bytes(t)))),
+            (Alert::LagBytes, health.lag_bytes.zip(self.thresholds.lag_bytes)
+                .map(|(v, t)| (v > t, format_bytes(v), format_bytes(t)))),
+            (Alert::LagTime, health.flush_lag_seconds.zip(self.thresholds.lag_time)
+                .map(|(v, t)| (v > t.as_secs_f64(), format!("{:.1}s", v), format!("{}s", t.as_secs())))),
+            (Alert::Inactive, health.inactive_seconds.filter(|_| !health.active).zip(self.thresholds.inactive)
+                .map(|(v, t)| (v > t.as_secs_f64(), format!("{:.0}s", v), format!("{}s", t.as_secs())))),
+        ];
+        for (alert, check) in checks {
+            let crossed = check.as_ref().is_some_and(|(crossed, _, _)| *crossed);
+            if crossed && state.alerts.insert(alert) {
+                let (_, value, threshold) = check.unwrap();
+                events.push(SlotEvent::ThresholdCrossed { slot: slot.clone(), alert, value, threshold });
+            } else if !crossed && state.alerts.remove(&alert) {
+                events.push(SlotEvent::Recovered { slot: slot.clone(), alert });
+            }
+        }
+
+        if let Some(policy) = &self.drop_policy
+            && !health.active
+            && health.inactive_seconds.is_some_and(|s| s >= policy.inactive_for.as_secs_f64())
+            && health.retained_bytes.unwrap_or(0) >= policy.min_retained_bytes {
+            drop_slot(client, &slot)?;
+            self.states.remove(&slot);
+            events.push(SlotEvent::Dropped {
+                slot: slot.clone(),
+                reason: format!("inactive for {:.0}s holding {}", health.inactive_seconds.unwrap_or_default(),
+                                format_bytes(health.retained_bytes.unwrap_or(0))),
+            });
+        }
+
+        for event in events {
+            self.emit(event);
+        }
+
+        Ok(())
+    }
+
+    fn emit(&self, event: SlotEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+}
+
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+// "1024", "512kB", "64MB", "10GB", "1TB" (powers of 1024, like PostgreSQL)
+pub fn parse_bytes(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: i64 = number.parse().map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: i64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1 << 10,
+        "mb" | "m" => 1 << 20,
+        "gb" | "g" => 1 << 30,
+        "tb" | "t" => 1 << 40,
+        other => return Err(format!("invalid size unit '{}' in '{}'", other, value)),
+    };
+
+    number.checked_mul(multiplier).ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+// "250ms", "90", "90s", "15m", "2h", "7d"
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+    let multiplier = match unit.trim() {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        other => return Err(format!("invalid duration unit '{}' in '{}'", other, value)),
+    };
+
+    number.checked_mul(multiplier).map(Duration::from_secs).ok_or_else(|| format!("duration '{}' is too long", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_bytes("1024"), Ok(1024));
+        assert_eq!(parse_bytes(" 512kB "), Ok(512 << 10));
+        assert_eq!(parse_bytes("64MB"), Ok(64 << 20));
+        assert_eq!(parse_bytes("10g"), Ok(10 << 30));
+        assert_eq!(parse_bytes("1TB"), Ok(1 << 40));
+        assert!(parse_bytes("1PB").is_err());
+        assert!(parse_bytes("MB").is_err());
+        assert_eq!(parse_bytes("8388607TB"), Ok(8_388_607 << 40));
+        assert_eq!(parse_bytes("8388608TB"), Err(String::from("size '8388608TB' is too large")));
+        assert!(parse_bytes("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(604_800)));
+        assert!(parse_duration("1w").is_err());
+        assert_eq!(parse_duration("213503982334601d"), Ok(Duration::from_secs(213_503_982_334_601 * 86400)));
+        assert_eq!(parse_duration("213503982334602d"), Err(String::from("duration '213503982334602d' is too long")));
+    }
+}
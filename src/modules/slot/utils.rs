@@ -0,0 +1,74 @@
+use crate::modules::db::server_version_num;
+use postgres::Client;
+use std::error::Error;
+
+pub struct SlotInfo {
+    pub slot_name: String,
+    pub plugin: Option<String>,
+    pub slot_type: String,
+    pub database: Option<String>,
+    pub active: bool,
+    pub active_pid: Option<i32>,
+    pub restart_lsn: Option<String>,
+    pub confirmed_flush_lsn: Option<String>,
+    pub retained_bytes: Option<i64>,
+    // PostgreSQL 17+, None on older servers
+    pub failover: Option<bool>,
+    pub synced: Option<bool>,
+}
+
+const SLOT_QUERY: &str = "SELECT slot_name::text, plugin::text, slot_type, database::text, active, active_pid, \
+    restart_lsn::text, confirmed_flush_lsn::text, \
+    (pg_current_wal_lsn() - restart_lsn)::bigint AS retained_bytes, \
+    (to_jsonb(s)->>'failover')::bool AS failover, (to_jsonb(s)->>'synced')::bool AS synced \
+    FROM pg_replication_slots s";
+
+pub fn list_slots(client: &mut Client) -> Result<Vec<SlotInfo>, Box<dyn Error>> {
+    let rows = client.query(&*format!("{} ORDER BY slot_name", SLOT_QUERY), &[])?;
+
+    Ok(rows.iter().map(slot_from_row).collect())
+}
+
+pub fn find_slot(client: &mut Client, slot_name: &str) -> Result<Option<SlotInfo>, Box<dyn Error>> {
+    let row = client.query_opt(&*format!("{} WHERE slot_name = $1", SLOT_QUERY), &[&slot_name])?;
+
+    Ok(row.as_ref().map(slot_from_row))
+}
+
+// returns the LSN from which the new slot streams changes,
+// a failover slot is synchronized to standbys and survives a promotion
+pub fn create_slot(client: &mut Client, slot_name: &str, failover: bool) -> Result<String, Box<dyn Error>> {
+    if failover && server_version_num(client)? < 170000 {
+        return Err("failover slots require PostgreSQL 17 or later".into());
+    }
+    let row = if failover {
+        client.query_one("SELECT lsn::text FROM pg_create_logical_replication_slot($1, 'pgoutput', false, false, true)",
+                         &[&slot_name])?
+    } else {
+        client.query_one("SELECT lsn::text FROM pg_create_logical_replication_slot($1, 'pgoutput')", &[&slot_name])?
+    };
+
+    Ok(row.get(0))
+}
+
+pub fn drop_slot(client: &mut Client, slot_name: &str) -> Result<(), Box<dyn Error>> {
+    client.execute("SELECT pg_drop_replication_slot($1)", &[&slot_name])?;
+
+    Ok(())
+}
+
+fn slot_from_row(row: &postgres::Row) -> SlotInfo {
+    SlotInfo {
+        slot_name: row.get("slot_name"),
+        plugin: row.get("plugin"),
+        slot_type: row.get("slot_type"),
+        database: row.get("database"),
+        active: row.get("active"),
+        active_pid: row.get("active_pid"),
+        restart_lsn: row.get("restart_lsn"),
+        confirmed_flush_lsn: row.get("confirmed_flush_lsn"),
+        retained_bytes: row.get("retained_bytes"),
+        failover: row.get("failover"),
+        synced: row.get("synced"),
+    }
+}
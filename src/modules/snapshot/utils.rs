@@ -0,0 +1,153 @@
+use crate::modules::db::{quote_identifier, quote_table_name};
+use crate::modules::metrics::registry::METRICS;
+use crate::modules::metrics::status::{PipelineState, STATUS};
+use crate::modules::replication::dto::{ChangeEvent, Column, ColumnValue, Operation, Transaction};
+use crate::modules::replication::lsn::Lsn;
+use crate::modules::replication::message_utils::postgres_epoch_micros;
+use crate::modules::sink::format::EventEncoder;
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::ToSql;
+use postgres::{Client, GenericClient, IsolationLevel, RowIter, Transaction as DbTransaction};
+use std::error::Error;
+use std::io::Write;
+use std::time::SystemTime;
+use tracing::info;
+
+// Reads every table of the publication in one repeatable read transaction and prints each row
+// as a JSON object, or as a read event of the encoder's format; returns the number of rows read
+pub fn snapshot_publication(client: &mut Client, publication: &str, encoder: Option<&EventEncoder>) -> Result<u64, Box<dyn Error>> {
+    STATUS.update(publication, |status| status.state = PipelineState::Snapshotting);
+    let result = snapshot_tables(client, publication, encoder);
+    STATUS.update(publication, |status| match &result {
+        Ok(_) => status.state = PipelineState::Stopped,
+        Err(e) => {
+            status.state = PipelineState::Failed;
+            status.last_error = Some(e.to_string());
+        },
+    });
+
+    result
+}
+
+fn snapshot_tables(client: &mut Client, publication: &str, encoder: Option<&EventEncoder>) -> Result<u64, Box<dyn Error>> {
+    let mut transaction = client.build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()?;
+    // the events of every table share the position the snapshot was taken at, by its first query;
+    // a standby has no WAL of its own to insert into, its position is what it replayed
+    let position: String = transaction.query_one("SELECT CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn() \
+        ELSE pg_current_wal_lsn() END::text", &[])?.get(0);
+    let position: Lsn = position.parse()?;
+    let tables = publication_tables(&mut transaction, publication)?;
+    let labels = [("publication", publication)];
+    METRICS.set_gauge("cyphercdc_snapshot_tables", &labels, tables.len() as f64);
+    METRICS.set_gauge("cyphercdc_snapshot_tables_done", &labels, 0.0);
+
+    let mut total = 0;
+    for (done, table) in tables.into_iter().enumerate() {
+        let rows = match encoder {
+            Some(encoder) => print_events(&mut transaction, &table, position, encoder)?,
+            None => print_rows(&mut transaction, &table)?,
+        };
+        info!(table = %table, rows, "Snapshot of table read");
+        total += rows;
+        METRICS.inc_counter("cyphercdc_snapshot_rows_total", &[("publication", publication), ("table", &table)], rows);
+        METRICS.set_gauge("cyphercdc_snapshot_tables_done", &labels, (done + 1) as f64);
+        STATUS.update(publication, |status| {
+            status.tables.entry(table.clone()).or_default().insert(String::from("rows"), rows);
+        });
+    }
+    transaction.commit()?;
+
+    Ok(total)
+}
+
+// "schema.table" of every table of the publication
+pub fn publication_tables(client: &mut impl GenericClient, publication: &str) -> Result<Vec<String>, postgres::Error> {
+    let rows = client.query("SELECT schemaname || '.' || tablename FROM pg_publication_tables \
+        WHERE pubname = $1 ORDER BY schemaname, tablename", &[&publication])?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+// the columns of a table like a Relation message describes them: the replica identity columns are
+// the key, every column with REPLICA IDENTITY FULL and none with NOTHING; the primary key whatever the identity
+pub fn table_columns(client: &mut impl GenericClient, table: &str) -> Result<Vec<Column>, postgres::Error> {
+    let rows = client.query("SELECT a.attname::text, a.atttypid, a.atttypmod, \
+        CASE c.relreplident WHEN 'f' THEN true WHEN 'n' THEN false ELSE coalesce(a.attnum = ANY(i.indkey), false) END, \
+        CASE WHEN a.atttypid >= 16384 THEN array[n.nspname::text, t.typname::text] END, \
+        coalesce(a.attnum = ANY(p.indkey), false) \
+        FROM pg_attribute a JOIN pg_class c ON c.oid = a.attrelid \
+        JOIN pg_type t ON t.oid = a.atttypid JOIN pg_namespace n ON n.oid = t.typnamespace \
+        LEFT JOIN pg_index i ON i.indrelid = a.attrelid AND CASE c.relreplident WHEN 'i' THEN i.indisreplident ELSE i.indisprimary END \
+        LEFT JOIN pg_index p ON p.indrelid = a.attrelid AND p.indisprimary \
+        WHERE a.attrelid = $1::text::regclass AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum", &[&quote_table_name(table)])?;
+
+    Ok(rows.iter().map(|row| Column {
+        name: row.get(0),
+        type_oid: row.get(1),
+        type_modifier: row.get(2),
+        key: row.get(3),
+        primary_key: row.get(5),
+        type_name: row.get::<_, Option<Vec<String>>>(4)
+            .map(|name| format!("{}.{}", quote_identifier(&name[0]), quote_identifier(&name[1]))),
+    }).collect())
+}
+
+fn print_rows(transaction: &mut DbTransaction, table: &str) -> Result<u64, Box<dyn Error>> {
+    let mut rows = stream_rows(transaction, &format!("SELECT row_to_json(t)::text FROM {} t", quote_table_name(table)))?;
+    let mut out = std::io::stdout().lock();
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        out.write_all(row.get::<_, &str>(0).as_bytes())?;
+        out.write_all(b"\n")?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+// the rows of a query as the server sends them, tables do not have to fit into memory
+fn stream_rows<'a>(transaction: &'a mut DbTransaction, query: &str) -> Result<RowIter<'a>, postgres::Error> {
+    transaction.query_raw(query, std::iter::empty::<&(dyn ToSql + Sync)>())
+}
+
+// every row as the read event a consumer of the change stream would expect,
+// with the columns in their text form like pgoutput sends them
+fn print_events(transaction: &mut DbTransaction, table: &str, position: Lsn, encoder: &EventEncoder) -> Result<u64, Box<dyn Error>> {
+    let (schema, name) = table.split_once('.').unwrap_or(("public", table));
+    let columns = table_columns(transaction, table)?;
+    let select: Vec<String> = columns.iter().map(|c| format!("{}::text", quote_identifier(&c.name))).collect();
+    let mut rows = stream_rows(transaction, &format!("SELECT {} FROM {}", select.join(", "), quote_table_name(table)))?;
+
+    let batch = Transaction {
+        xid: 0,
+        commit_lsn: position,
+        end_lsn: position,
+        commit_time: postgres_epoch_micros(SystemTime::now()),
+        events: Vec::new(),
+    };
+    let mut out = std::io::stdout().lock();
+    let mut index = 0;
+    while let Some(row) = rows.next()? {
+        let values = (0..columns.len())
+            .map(|i| row.get::<_, Option<String>>(i).map_or(ColumnValue::Null, ColumnValue::Text))
+            .collect();
+        let event = ChangeEvent {
+            lsn: position,
+            xid: 0,
+            schema: schema.to_owned(),
+            table: name.to_owned(),
+            operation: Operation::Read,
+            columns: columns.clone(),
+            old: None,
+            new: Some(values),
+        };
+        out.write_all(&encoder.value(&event, &batch, index)?)?;
+        out.write_all(b"\n")?;
+        index += 1;
+    }
+
+    Ok(index as u64)
+}